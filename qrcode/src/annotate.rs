@@ -0,0 +1,150 @@
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+use image::{DynamicImage, ImageFormat, Rgba, RgbaImage};
+use imageproc::drawing::{draw_filled_rect_mut, draw_line_segment_mut};
+use imageproc::geometric_transformations::Projection;
+use imageproc::rect::Rect;
+
+use crate::decode::Trace;
+
+const DECODED: Rgba<u8> = Rgba([0, 200, 0, 255]);
+const FAILED: Rgba<u8> = Rgba([230, 0, 0, 255]);
+const FINDER: Rgba<u8> = Rgba([255, 140, 0, 255]);
+const LABEL: Rgba<u8> = Rgba([255, 255, 255, 255]);
+
+// 3x5 点阵数字，每行低 3 位从左到右
+const DIGITS: [[u8; 5]; 10] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b111, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b010, 0b010, 0b010],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+];
+
+/// 在原图副本上标注检测到的二维码：绿色边框为解码成功并标出结果序号（从 1 开始），
+/// 红色边框加叉为解码失败，橙色方框为定位图案
+pub fn draw(img: &DynamicImage, trace: &Trace) -> RgbaImage {
+    let mut out = img.to_rgba8();
+    let (w, h) = out.dimensions();
+    let thickness = (w.max(h) / 400).max(1) as i32;
+
+    for detection in &trace.detections {
+        let color = if detection.outcome.is_ok() { DECODED } else { FAILED };
+        let bounds = detection.bounds;
+        polygon(&mut out, &bounds, color, thickness);
+
+        let extent = detection.size as f32 + 1.0;
+        let grid = [(0.0, 0.0), (extent, 0.0), (extent, extent), (0.0, extent)];
+        if let Some(projection) = Projection::from_control_points(grid, bounds) {
+            let far = detection.size as f32 - 7.0;
+            for (x, y) in [(0.0, 0.0), (far, 0.0), (0.0, far)] {
+                let square = [(x, y), (x + 7.0, y), (x + 7.0, y + 7.0), (x, y + 7.0)].map(|p| projection * p);
+                polygon(&mut out, &square, FINDER, thickness);
+            }
+        }
+
+        match detection.outcome {
+            Ok(index) => label(&mut out, bounds[0], index + 1, thickness * 3),
+            Err(_) => {
+                line(&mut out, bounds[0], bounds[2], FAILED, thickness);
+                line(&mut out, bounds[1], bounds[3], FAILED, thickness);
+            }
+        }
+    }
+    out
+}
+
+/// 写出 rqrr 二值化后的图像：白色、黑色为阈值化结果，彩色区域为识别出的定位图案等连通区域
+pub fn write_threshold(trace: &Trace, path: &Path) -> io::Result<()> {
+    let prepared = trace
+        .prepared
+        .as_ref()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no thresholded image, qr scanning is disabled"))?;
+    ImageFormat::from_path(path).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    // write_state_to 写入失败时会 panic，先确认文件可写
+    File::create(path)?;
+    let name = path
+        .to_str()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path is not valid UTF-8"))?;
+    prepared.write_state_to(name);
+    Ok(())
+}
+
+fn polygon(img: &mut RgbaImage, points: &[(f32, f32)], color: Rgba<u8>, thickness: i32) {
+    for (i, &start) in points.iter().enumerate() {
+        line(img, start, points[(i + 1) % points.len()], color, thickness);
+    }
+}
+
+// 平移多次绘制来加粗线条
+fn line(img: &mut RgbaImage, start: (f32, f32), end: (f32, f32), color: Rgba<u8>, thickness: i32) {
+    let half = thickness / 2;
+    for dx in -half..=thickness - 1 - half {
+        for dy in -half..=thickness - 1 - half {
+            let (dx, dy) = (dx as f32, dy as f32);
+            draw_line_segment_mut(img, (start.0 + dx, start.1 + dy), (end.0 + dx, end.1 + dy), color);
+        }
+    }
+}
+
+// 在角点左上方绘制带底色的序号
+fn label(img: &mut RgbaImage, corner: (f32, f32), number: usize, scale: i32) {
+    let text = number.to_string();
+    let width = (text.len() as i32 * 4 + 1) * scale;
+    let height = 7 * scale;
+    let x = (corner.0 as i32 - width).max(0);
+    let y = (corner.1 as i32 - height).max(0);
+    draw_filled_rect_mut(img, Rect::at(x, y).of_size(width as u32, height as u32), DECODED);
+
+    for (i, digit) in text.bytes().enumerate() {
+        let glyph = DIGITS[(digit - b'0') as usize];
+        for (row, bits) in glyph.iter().enumerate() {
+            for col in 0..3 {
+                if bits & (0b100 >> col) != 0 {
+                    let px = x + (1 + i as i32 * 4 + col) * scale;
+                    let py = y + (1 + row as i32) * scale;
+                    draw_filled_rect_mut(img, Rect::at(px, py).of_size(scale as u32, scale as u32), LABEL);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode::{self, ScanOptions};
+    use crate::encode::build_code;
+    use crate::render::{self, Matrix, Style};
+
+    #[test]
+    fn test_draw_decoded_code() {
+        let code = build_code(b"annotate", qrcode::EcLevel::M, None).unwrap();
+        let style = Style {
+            quiet_zone: 4,
+            module_size: 8,
+            foreground: Rgba([0, 0, 0, 255]),
+            background: Rgba([255, 255, 255, 255]),
+        };
+        let img = DynamicImage::ImageRgba8(render::render_image(&Matrix::from_code(&code), &style, None));
+
+        let mut trace = Trace::default();
+        decode::scan_traced(&img.to_luma8(), &ScanOptions::default(), &mut trace).unwrap();
+        assert_eq!(trace.strategy, Some("original"));
+        assert_eq!(trace.detections.len(), 1);
+        assert_eq!(trace.detections[0].outcome.as_ref().ok(), Some(&0));
+
+        // 左上角定位图案外框位于静区之后
+        let out = draw(&img, &trace);
+        let (x, y) = trace.detections[0].bounds[0];
+        assert!((x - 32.0).abs() <= 2.0 && (y - 32.0).abs() <= 2.0);
+        assert_eq!(out.get_pixel(x as u32 + 20, y as u32), &FINDER);
+    }
+}
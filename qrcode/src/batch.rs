@@ -0,0 +1,264 @@
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use image::{DynamicImage, ImageError};
+use rayon::prelude::*;
+use serde_json::{Value, json};
+use walkdir::WalkDir;
+
+use crate::decode::{self, Decoded, ScanError, ScanOptions, Trace};
+use crate::frames;
+use crate::source::Source;
+
+// 未指定 --include 时目录中参与扫描的文件
+const DEFAULT_INCLUDE: &str =
+    "*.{png,apng,jpg,jpeg,gif,bmp,tif,tiff,webp,ico,pbm,pgm,ppm,pnm,tga,qoi,avif,svg,svgz,pdf}";
+
+/// 目录遍历时的文件过滤条件，按相对于目录的路径匹配，不区分大小写
+pub struct Filter {
+    include: GlobSet,
+    exclude: GlobSet,
+}
+
+impl Filter {
+    pub fn new(include: &[String], exclude: &[String]) -> Result<Self, globset::Error> {
+        let include = if include.is_empty() {
+            build(&[DEFAULT_INCLUDE.to_string()])?
+        } else {
+            build(include)?
+        };
+        Ok(Self {
+            include,
+            exclude: build(exclude)?,
+        })
+    }
+
+    fn matches(&self, path: &Path) -> bool {
+        self.include.is_match(path) && !self.exclude.is_match(path)
+    }
+}
+
+fn build(patterns: &[String]) -> Result<GlobSet, globset::Error> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(GlobBuilder::new(pattern).case_insensitive(true).build()?);
+    }
+    builder.build()
+}
+
+/// 展开命令行输入：目录递归展开为其中匹配的文件，其余参数原样保留
+pub fn expand(paths: &[PathBuf], filter: &Filter) -> Vec<PathBuf> {
+    let mut inputs = Vec::new();
+    for path in paths {
+        if !path.is_dir() {
+            inputs.push(path.clone());
+            continue;
+        }
+        for entry in WalkDir::new(path).follow_links(true).sort_by_file_name() {
+            match entry {
+                Ok(entry) if entry.file_type().is_file() => {
+                    let relative = entry.path().strip_prefix(path).unwrap_or(entry.path());
+                    if filter.matches(relative) {
+                        inputs.push(entry.into_path());
+                    }
+                }
+                Ok(_) => {}
+                // 无法访问的路径保留下来，读取时报告错误
+                Err(e) => inputs.extend(e.path().map(Path::to_path_buf)),
+            }
+        }
+    }
+    inputs
+}
+
+/// 读取或扫描失败的原因
+pub enum Failure {
+    Read(io::Error),
+    Open(ImageError),
+    /// 扫描区域在图像之外，附带图像尺寸
+    Crop(u32, u32),
+    Scan(ScanError),
+}
+
+impl Failure {
+    /// 与单文件模式一致的错误信息，包含出错的位置
+    pub fn report(&self, location: &str) -> String {
+        match self {
+            Failure::Read(e) => format!("can not read image: {} {}", location, e),
+            Failure::Open(e) => format!("can not open image: {} {}", location, e),
+            Failure::Crop(w, h) => format!("crop region is outside the image: {} ({}x{})", location, w, h),
+            Failure::Scan(ScanError::NotFound) => format!("can not find qrcode in image: {}", location),
+            Failure::Scan(ScanError::Decode(e)) => format!("can not decode qrcode in image: {} {}", location, e),
+            Failure::Scan(ScanError::TimeLimit) => {
+                format!("can not find qrcode in image within the time limit: {}", location)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::Read(e) => write!(f, "can not read image: {}", e),
+            Failure::Open(e) => write!(f, "can not open image: {}", e),
+            Failure::Crop(w, h) => write!(f, "crop region is outside the image ({}x{})", w, h),
+            Failure::Scan(ScanError::NotFound) => f.write_str("can not find qrcode in image"),
+            Failure::Scan(ScanError::Decode(e)) => write!(f, "can not decode qrcode in image: {}", e),
+            Failure::Scan(ScanError::TimeLimit) => f.write_str("can not find qrcode in image within the time limit"),
+        }
+    }
+}
+
+/// 单帧的扫描结果
+pub struct Scanned {
+    pub file: String,
+    /// 帧序号，从 0 开始；整个文件读取失败时为空
+    pub frame: Option<usize>,
+    /// 文件包含的帧数
+    pub frames: usize,
+    pub outcome: Result<Vec<Decoded>, Failure>,
+    /// 调试用的原图及扫描过程，只记录第一帧
+    pub trace: Option<(DynamicImage, Trace)>,
+    /// 各阶段耗时，读取文件的耗时只计入第一帧
+    pub timings: Vec<(String, Duration)>,
+}
+
+impl Scanned {
+    /// 错误信息中的位置，多帧图片附带帧序号
+    pub fn location(&self) -> String {
+        match self.frame {
+            Some(frame) if self.frames > 1 => format!("{} (frame {})", self.file, frame),
+            _ => self.file.clone(),
+        }
+    }
+
+    /// NDJSON 输出的一行，`timings` 为真时附带各阶段耗时
+    pub fn to_json(&self, timings: bool) -> Value {
+        let mut line = match &self.outcome {
+            Ok(results) => json!({ "file": self.file, "frame": self.frame, "results": results }),
+            Err(e) => json!({ "file": self.file, "frame": self.frame, "results": [], "error": e.to_string() }),
+        };
+        if timings {
+            line["timings"] = self
+                .timings
+                .iter()
+                .map(|(stage, elapsed)| json!({ "stage": stage, "ms": elapsed.as_secs_f64() * 1000.0 }))
+                .collect();
+        }
+        line
+    }
+
+    /// 各阶段耗时的文本报告
+    pub fn timings_report(&self) -> String {
+        let total: Duration = self.timings.iter().map(|(_, elapsed)| *elapsed).sum();
+        let stages = self
+            .timings
+            .iter()
+            .chain([&("total".to_string(), total)])
+            .map(|(stage, elapsed)| format!("{} {:.1}ms", stage, elapsed.as_secs_f64() * 1000.0))
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!("{}: {}", self.location(), stages)
+    }
+}
+
+/// 读取一个输入并并行扫描其中的每一帧，`trace` 为真时保留第一帧的扫描过程
+pub fn scan_input(path: &Path, base64: bool, options: &ScanOptions, trace: bool) -> Vec<Scanned> {
+    let started = Instant::now();
+    let source = Source::parse(path);
+    let file = source.to_string();
+    let failed = |failure| {
+        vec![Scanned {
+            file: file.clone(),
+            frame: None,
+            frames: 0,
+            outcome: Err(failure),
+            trace: None,
+            timings: Vec::new(),
+        }]
+    };
+
+    let bytes = match source.read(base64) {
+        Ok(bytes) => bytes,
+        Err(e) => return failed(Failure::Read(e)),
+    };
+    let images = match frames::load(&bytes, &options.render) {
+        Ok(images) => images,
+        Err(e) => return failed(Failure::Open(e)),
+    };
+    let load = started.elapsed();
+
+    let total = images.len();
+    images
+        .into_par_iter()
+        .enumerate()
+        .map(|(frame, img)| {
+            let mut scanned = Scanned {
+                file: file.clone(),
+                frame: Some(frame),
+                frames: total,
+                outcome: Err(Failure::Scan(ScanError::NotFound)),
+                trace: None,
+                timings: Vec::new(),
+            };
+            if frame == 0 {
+                scanned.timings.push(("load".to_string(), load));
+            }
+
+            // 先裁剪再转换灰度，避免整幅大图参与处理
+            let img = match options.crop {
+                Some(crop) => match crop.apply(&img) {
+                    Some(cropped) => cropped,
+                    None => {
+                        scanned.outcome = Err(Failure::Crop(img.width(), img.height()));
+                        return scanned;
+                    }
+                },
+                None => img,
+            };
+            let started = Instant::now();
+            let gray = img.to_luma8();
+            scanned.timings.push(("grayscale".to_string(), started.elapsed()));
+
+            let mut steps = Trace::default();
+            scanned.outcome = decode::scan_traced(&gray, options, &mut steps).map_err(Failure::Scan);
+            scanned.timings.append(&mut steps.timings);
+            if trace && frame == 0 {
+                scanned.trace = Some((img, steps));
+            }
+            scanned
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn test_expand() {
+        let dir = std::env::temp_dir().join(format!("qrcode-batch-{}", std::process::id()));
+        fs::create_dir_all(dir.join("sub/skip")).unwrap();
+        for name in ["a.png", "notes.txt", "sub/B.JPG", "sub/skip/c.png"] {
+            fs::write(dir.join(name), b"").unwrap();
+        }
+
+        let names = |filter: &Filter| -> Vec<String> {
+            expand(&[dir.clone(), PathBuf::from("-")], filter)
+                .iter()
+                .map(|p| p.strip_prefix(&dir).unwrap_or(p).to_string_lossy().replace('\\', "/"))
+                .collect()
+        };
+        let filter = Filter::new(&[], &[]).unwrap();
+        assert_eq!(names(&filter), ["a.png", "sub/B.JPG", "sub/skip/c.png", "-"]);
+        let filter = Filter::new(&["*.png".to_string()], &["sub/skip/**".to_string()]).unwrap();
+        assert_eq!(names(&filter), ["a.png", "-"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
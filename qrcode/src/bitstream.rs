@@ -0,0 +1,564 @@
+use encoding_rs::{Encoding, GBK, SHIFT_JIS, UTF_8, WINDOWS_1252};
+use rqrr::{BitGrid, DeQRError, Grid, MetaData};
+use serde::Serialize;
+
+/// 纠错后的数据码流
+#[derive(Debug, Default, Clone)]
+pub struct Stream {
+    /// 按 ECI 声明分段的数据字节，`None` 表示段前没有 ECI 声明
+    pub runs: Vec<(Option<u32>, Vec<u8>)>,
+    /// 包含日文汉字模式段，字节为 Shift JIS 编码
+    pub kanji: bool,
+    /// 包含中文汉字模式段，字节为 GB 2312 编码
+    pub hanzi: bool,
+    /// 结构化追加头
+    pub structured_append: Option<StructuredAppend>,
+}
+
+/// 结构化追加头，一条消息最多拆分为 16 个符号
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct StructuredAppend {
+    /// 符号序号，从 1 开始
+    pub index: u8,
+    pub total: u8,
+    /// 完整消息所有字节的异或值
+    pub parity: u8,
+}
+
+// ECI 指派号对应的字符集标签
+const ECI_CHARSETS: &[(u32, &str)] = &[
+    (1, "iso-8859-1"),
+    (3, "iso-8859-1"),
+    (4, "iso-8859-2"),
+    (5, "iso-8859-3"),
+    (6, "iso-8859-4"),
+    (7, "iso-8859-5"),
+    (8, "iso-8859-6"),
+    (9, "iso-8859-7"),
+    (10, "iso-8859-8"),
+    (11, "iso-8859-9"),
+    (12, "iso-8859-10"),
+    (13, "iso-8859-11"),
+    (15, "iso-8859-13"),
+    (16, "iso-8859-14"),
+    (17, "iso-8859-15"),
+    (18, "iso-8859-16"),
+    (20, "shift_jis"),
+    (21, "windows-1250"),
+    (22, "windows-1251"),
+    (23, "windows-1252"),
+    (24, "windows-1256"),
+    (25, "utf-16be"),
+    (26, "utf-8"),
+    (27, "us-ascii"),
+    (28, "big5"),
+    (29, "gb2312"),
+    (30, "euc-kr"),
+    (32, "gb18030"),
+];
+
+impl Stream {
+    pub fn bytes(&self) -> Vec<u8> {
+        self.runs.iter().flat_map(|(_, bytes)| bytes.iter().copied()).collect()
+    }
+
+    /// 第一个 ECI 声明
+    pub fn eci(&self) -> Option<u32> {
+        self.runs.iter().find_map(|(eci, _)| *eci)
+    }
+
+    /// 拼接结构化追加的后续符号，未声明 ECI 的分段沿用前一分段的字符集
+    pub fn append(&mut self, other: &Stream) {
+        for (eci, bytes) in &other.runs {
+            match eci {
+                Some(_) => self.runs.push((*eci, bytes.clone())),
+                None => self.push(bytes),
+            }
+        }
+        self.kanji |= other.kanji;
+        self.hanzi |= other.hanzi;
+    }
+
+    fn push(&mut self, bytes: &[u8]) {
+        match self.runs.last_mut() {
+            Some((_, run)) => run.extend_from_slice(bytes),
+            None => self.runs.push((None, bytes.to_vec())),
+        }
+    }
+
+    /// 按指定字符集、ECI 声明或内容猜测转换为文本，返回文本及编码名称；无法识别为文本时编码为 `binary`
+    pub fn decode_text(&self, charset: Option<&'static Encoding>) -> (String, &'static str) {
+        let mut text = String::new();
+        // 编码名称取自声明了 ECI 或包含非 ASCII 字节的分段
+        let (mut primary, mut fallback, mut binary) = (None, None, false);
+        for (eci, bytes) in &self.runs {
+            let encoding = charset
+                .or_else(|| eci.and_then(eci_encoding))
+                .or_else(|| self.detect(bytes));
+            let Some(encoding) = encoding else {
+                text.push_str(&String::from_utf8_lossy(bytes));
+                binary = true;
+                continue;
+            };
+            text.push_str(&encoding.decode_without_bom_handling(bytes).0);
+            if eci.is_some() || !bytes.is_ascii() {
+                primary.get_or_insert(encoding.name());
+            } else {
+                fallback.get_or_insert(encoding.name());
+            }
+        }
+        let name = if binary {
+            "binary"
+        } else {
+            primary.or(fallback).unwrap_or(UTF_8.name())
+        };
+        (text, name)
+    }
+
+    // 未声明 ECI 时优先使用汉字模式暗示的字符集，其次 UTF-8、GBK、Shift JIS，最后是不含控制字符的 ISO-8859-1
+    fn detect(&self, bytes: &[u8]) -> Option<&'static Encoding> {
+        let hinted = [(self.kanji, SHIFT_JIS), (self.hanzi, GBK)];
+        let candidates = hinted
+            .iter()
+            .filter(|(hint, _)| *hint)
+            .map(|(_, encoding)| *encoding)
+            .chain([UTF_8, GBK, SHIFT_JIS]);
+        for encoding in candidates {
+            if encoding
+                .decode_without_bom_handling_and_without_replacement(bytes)
+                .is_some()
+            {
+                return Some(encoding);
+            }
+        }
+        let latin1 = bytes
+            .iter()
+            .all(|&b| matches!(b, b'\t' | b'\n' | b'\r' | 0x20..=0x7e | 0xa0..=0xff));
+        latin1.then_some(WINDOWS_1252)
+    }
+}
+
+fn eci_encoding(eci: u32) -> Option<&'static Encoding> {
+    ECI_CHARSETS
+        .iter()
+        .find(|(id, _)| *id == eci)
+        .and_then(|(_, label)| Encoding::for_label(label.as_bytes()))
+}
+
+/// 读取并纠错数据码流，失败时按镜像方向重试
+pub fn read<G: BitGrid>(grid: &Grid<G>) -> Result<(MetaData, Stream), DeQRError> {
+    read_grid(&grid.grid).or_else(|e| read_grid(&Mirrored(&grid.grid)).map_err(|_| e))
+}
+
+// 行列互换的网格，部分二维码以镜像方式印刷
+struct Mirrored<'a>(&'a dyn BitGrid);
+
+impl BitGrid for Mirrored<'_> {
+    fn size(&self) -> usize {
+        self.0.size()
+    }
+
+    fn bit(&self, y: usize, x: usize) -> bool {
+        self.0.bit(x, y)
+    }
+}
+
+// 去除掩码的网格，格式信息区域保持原样以便 rqrr 读取格式
+struct Unmasked<'a> {
+    grid: &'a dyn BitGrid,
+    mask: Option<u16>,
+}
+
+impl BitGrid for Unmasked<'_> {
+    fn size(&self) -> usize {
+        self.grid.size()
+    }
+
+    fn bit(&self, y: usize, x: usize) -> bool {
+        let size = self.grid.size();
+        let format = (x == 8 && (y <= 8 || y >= size - 8)) || (y == 8 && (x <= 8 || x >= size - 8));
+        let flip = match self.mask {
+            Some(mask) if !format => mask_bit(mask, y, x),
+            _ => false,
+        };
+        self.grid.bit(y, x) ^ flip
+    }
+}
+
+fn mask_bit(mask: u16, y: usize, x: usize) -> bool {
+    match mask {
+        0 => (y + x).is_multiple_of(2),
+        1 => y.is_multiple_of(2),
+        2 => x.is_multiple_of(3),
+        3 => (y + x).is_multiple_of(3),
+        4 => (y / 2 + x / 3).is_multiple_of(2),
+        5 => (y * x) % 2 + (y * x) % 3 == 0,
+        6 => ((y * x) % 2 + (y * x) % 3).is_multiple_of(2),
+        _ => ((y * x) % 3 + (y + x) % 2).is_multiple_of(2),
+    }
+}
+
+fn read_grid(grid: &dyn BitGrid) -> Result<(MetaData, Stream), DeQRError> {
+    let (meta, _) = Grid::new(Unmasked { grid, mask: None }).get_raw_data()?;
+    let (_, raw) = Grid::new(Unmasked {
+        grid,
+        mask: Some(meta.mask),
+    })
+    .get_raw_data()?;
+
+    // 格式信息中的纠错等级编码为 M=0 L=1 H=2 Q=3，表格按 L M Q H 排列
+    let level = [1, 0, 3, 2][meta.ecc_level as usize & 3];
+    let version = meta.version.0;
+    let (len1, count1, len2, count2) = DATA_BYTES_PER_BLOCK[version - 1][level];
+    let ec_len = EC_BYTES_PER_BLOCK[version - 1][level];
+    let lens: Vec<usize> = [(len1, count1), (len2, count2)]
+        .iter()
+        .flat_map(|&(len, count)| std::iter::repeat_n(len, count))
+        .collect();
+
+    let codewords = &raw.data[..raw.len / 8];
+    if codewords.len() < lens.iter().sum::<usize>() + ec_len * lens.len() {
+        return Err(DeQRError::DataUnderflow);
+    }
+
+    // 码字按块交错排列：先是各块的数据码字，然后是各块的纠错码字
+    let mut blocks: Vec<Vec<u8>> = lens.iter().map(|len| Vec::with_capacity(len + ec_len)).collect();
+    let mut words = codewords.iter().copied();
+    for i in 0..len1.max(len2) {
+        for (block, len) in blocks.iter_mut().zip(&lens) {
+            if i < *len {
+                block.extend(words.next());
+            }
+        }
+    }
+    for _ in 0..ec_len {
+        for block in blocks.iter_mut() {
+            block.extend(words.next());
+        }
+    }
+
+    let decoder = reed_solomon::Decoder::new(ec_len);
+    let mut data = Vec::new();
+    for block in &blocks {
+        let corrected = decoder.correct(block, None).map_err(|_| DeQRError::DataEcc)?;
+        data.extend_from_slice(corrected.data());
+    }
+    Ok((meta, parse(&data, version)?))
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl BitReader<'_> {
+    fn remaining(&self) -> usize {
+        self.data.len() * 8 - self.pos
+    }
+
+    fn take(&mut self, n: usize) -> Result<u32, DeQRError> {
+        if self.remaining() < n {
+            return Err(DeQRError::DataUnderflow);
+        }
+        let mut value = 0;
+        for _ in 0..n {
+            let bit = self.data[self.pos / 8] >> (7 - self.pos % 8) & 1;
+            value = value << 1 | bit as u32;
+            self.pos += 1;
+        }
+        Ok(value)
+    }
+}
+
+const ALPHANUMERIC: &[u8; 45] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ $%*+-./:";
+
+/// 按模式指示符解析数据段
+fn parse(data: &[u8], version: usize) -> Result<Stream, DeQRError> {
+    let mut bits = BitReader { data, pos: 0 };
+    let mut stream = Stream::default();
+    // 字符计数指示符的位数随版本区间变化
+    let count_bits = |widths: [usize; 3]| match version {
+        1..=9 => widths[0],
+        10..=26 => widths[1],
+        _ => widths[2],
+    };
+
+    while bits.remaining() >= 4 {
+        match bits.take(4)? {
+            0 => break,
+            // 数字模式：每 3 位数字 10 位
+            1 => {
+                let mut count = bits.take(count_bits([10, 12, 14]))? as usize;
+                while count > 0 {
+                    let digits = count.min(3);
+                    let value = bits.take([4, 7, 10][digits - 1])?;
+                    let text = format!("{:0width$}", value, width = digits);
+                    if text.len() != digits {
+                        return Err(DeQRError::DataEcc);
+                    }
+                    stream.push(text.as_bytes());
+                    count -= digits;
+                }
+            }
+            // 字母数字模式：每 2 个字符 11 位
+            2 => {
+                let mut count = bits.take(count_bits([9, 11, 13]))? as usize;
+                while count > 0 {
+                    let chars = count.min(2);
+                    let mut value = bits.take(if chars == 2 { 11 } else { 6 })? as usize;
+                    let mut pair = [0; 2];
+                    for c in pair[..chars].iter_mut().rev() {
+                        *c = ALPHANUMERIC[value % 45];
+                        value /= 45;
+                    }
+                    if value != 0 {
+                        return Err(DeQRError::DataEcc);
+                    }
+                    stream.push(&pair[..chars]);
+                    count -= chars;
+                }
+            }
+            // 字节模式
+            4 => {
+                let count = bits.take(count_bits([8, 16, 16]))?;
+                for _ in 0..count {
+                    stream.push(&[bits.take(8)? as u8]);
+                }
+            }
+            // 日文汉字模式：13 位压缩的 Shift JIS 双字节
+            8 => {
+                stream.kanji = true;
+                let count = bits.take(count_bits([8, 10, 12]))?;
+                for _ in 0..count {
+                    let value = bits.take(13)?;
+                    let code = ((value / 0xc0) << 8) | (value % 0xc0);
+                    let code = if code + 0x8140 <= 0x9ffc {
+                        code + 0x8140
+                    } else {
+                        code + 0xc140
+                    };
+                    stream.push(&[(code >> 8) as u8, code as u8]);
+                }
+            }
+            // 中文汉字模式（GB/T 18284）：4 位子集指示符后为 13 位压缩的 GB 2312 双字节
+            13 => {
+                if bits.take(4)? != 1 {
+                    return Err(DeQRError::UnknownDataType);
+                }
+                stream.hanzi = true;
+                let count = bits.take(count_bits([8, 10, 12]))?;
+                for _ in 0..count {
+                    let value = bits.take(13)?;
+                    let code = ((value / 0x60) << 8) | (value % 0x60);
+                    let code = if code < 0x0a00 { code + 0xa1a1 } else { code + 0xa6a1 };
+                    stream.push(&[(code >> 8) as u8, code as u8]);
+                }
+            }
+            // ECI：指派号长度由首字节的前缀决定
+            7 => {
+                let first = bits.take(8)?;
+                let eci = match first {
+                    0x00..=0x7f => first,
+                    0x80..=0xbf => (first & 0x3f) << 8 | bits.take(8)?,
+                    0xc0..=0xdf => (first & 0x1f) << 16 | bits.take(16)?,
+                    _ => return Err(DeQRError::UnknownDataType),
+                };
+                stream.runs.push((Some(eci), Vec::new()));
+            }
+            // 结构化追加：4 位序号、4 位总数减一、8 位奇偶校验
+            3 => {
+                let index = bits.take(4)? as u8;
+                let total = bits.take(4)? as u8 + 1;
+                let parity = bits.take(8)? as u8;
+                if index >= total {
+                    return Err(DeQRError::DataEcc);
+                }
+                stream.structured_append = Some(StructuredAppend {
+                    index: index + 1,
+                    total,
+                    parity,
+                });
+            }
+            // FNC1 第一位置（GS1）无附加数据，第二位置带 8 位应用标识
+            5 => {}
+            9 => {
+                bits.take(8)?;
+            }
+            _ => return Err(DeQRError::UnknownDataType),
+        }
+    }
+    Ok(stream)
+}
+
+// ISO/IEC 18004 表 9，每个版本按 L M Q H 排列的每块纠错码字数
+static EC_BYTES_PER_BLOCK: [[usize; 4]; 40] = [
+    [7, 10, 13, 17],  // 1
+    [10, 16, 22, 28], // 2
+    [15, 26, 18, 22], // 3
+    [20, 18, 26, 16], // 4
+    [26, 24, 18, 22], // 5
+    [18, 16, 24, 28], // 6
+    [20, 18, 18, 26], // 7
+    [24, 22, 22, 26], // 8
+    [30, 22, 20, 24], // 9
+    [18, 26, 24, 28], // 10
+    [20, 30, 28, 24], // 11
+    [24, 22, 26, 28], // 12
+    [26, 22, 24, 22], // 13
+    [30, 24, 20, 24], // 14
+    [22, 24, 30, 24], // 15
+    [24, 28, 24, 30], // 16
+    [28, 28, 28, 28], // 17
+    [30, 26, 28, 28], // 18
+    [28, 26, 26, 26], // 19
+    [28, 26, 30, 28], // 20
+    [28, 26, 28, 30], // 21
+    [28, 28, 30, 24], // 22
+    [30, 28, 30, 30], // 23
+    [30, 28, 30, 30], // 24
+    [26, 28, 30, 30], // 25
+    [28, 28, 28, 30], // 26
+    [30, 28, 30, 30], // 27
+    [30, 28, 30, 30], // 28
+    [30, 28, 30, 30], // 29
+    [30, 28, 30, 30], // 30
+    [30, 28, 30, 30], // 31
+    [30, 28, 30, 30], // 32
+    [30, 28, 30, 30], // 33
+    [30, 28, 30, 30], // 34
+    [30, 28, 30, 30], // 35
+    [30, 28, 30, 30], // 36
+    [30, 28, 30, 30], // 37
+    [30, 28, 30, 30], // 38
+    [30, 28, 30, 30], // 39
+    [30, 28, 30, 30], // 40
+];
+
+// ISO/IEC 18004 表 9，`(第一组每块数据码字数, 块数, 第二组每块数据码字数, 块数)`
+static DATA_BYTES_PER_BLOCK: [[(usize, usize, usize, usize); 4]; 40] = [
+    [(19, 1, 0, 0), (16, 1, 0, 0), (13, 1, 0, 0), (9, 1, 0, 0)], // 1
+    [(34, 1, 0, 0), (28, 1, 0, 0), (22, 1, 0, 0), (16, 1, 0, 0)], // 2
+    [(55, 1, 0, 0), (44, 1, 0, 0), (17, 2, 0, 0), (13, 2, 0, 0)], // 3
+    [(80, 1, 0, 0), (32, 2, 0, 0), (24, 2, 0, 0), (9, 4, 0, 0)], // 4
+    [(108, 1, 0, 0), (43, 2, 0, 0), (15, 2, 16, 2), (11, 2, 12, 2)], // 5
+    [(68, 2, 0, 0), (27, 4, 0, 0), (19, 4, 0, 0), (15, 4, 0, 0)], // 6
+    [(78, 2, 0, 0), (31, 4, 0, 0), (14, 2, 15, 4), (13, 4, 14, 1)], // 7
+    [(97, 2, 0, 0), (38, 2, 39, 2), (18, 4, 19, 2), (14, 4, 15, 2)], // 8
+    [(116, 2, 0, 0), (36, 3, 37, 2), (16, 4, 17, 4), (12, 4, 13, 4)], // 9
+    [(68, 2, 69, 2), (43, 4, 44, 1), (19, 6, 20, 2), (15, 6, 16, 2)], // 10
+    [(81, 4, 0, 0), (50, 1, 51, 4), (22, 4, 23, 4), (12, 3, 13, 8)], // 11
+    [(92, 2, 93, 2), (36, 6, 37, 2), (20, 4, 21, 6), (14, 7, 15, 4)], // 12
+    [(107, 4, 0, 0), (37, 8, 38, 1), (20, 8, 21, 4), (11, 12, 12, 4)], // 13
+    [(115, 3, 116, 1), (40, 4, 41, 5), (16, 11, 17, 5), (12, 11, 13, 5)], // 14
+    [(87, 5, 88, 1), (41, 5, 42, 5), (24, 5, 25, 7), (12, 11, 13, 7)], // 15
+    [(98, 5, 99, 1), (45, 7, 46, 3), (19, 15, 20, 2), (15, 3, 16, 13)], // 16
+    [(107, 1, 108, 5), (46, 10, 47, 1), (22, 1, 23, 15), (14, 2, 15, 17)], // 17
+    [(120, 5, 121, 1), (43, 9, 44, 4), (22, 17, 23, 1), (14, 2, 15, 19)], // 18
+    [(113, 3, 114, 4), (44, 3, 45, 11), (21, 17, 22, 4), (13, 9, 14, 16)], // 19
+    [(107, 3, 108, 5), (41, 3, 42, 13), (24, 15, 25, 5), (15, 15, 16, 10)], // 20
+    [(116, 4, 117, 4), (42, 17, 0, 0), (22, 17, 23, 6), (16, 19, 17, 6)], // 21
+    [(111, 2, 112, 7), (46, 17, 0, 0), (24, 7, 25, 16), (13, 34, 0, 0)], // 22
+    [(121, 4, 122, 5), (47, 4, 48, 14), (24, 11, 25, 14), (15, 16, 16, 14)], // 23
+    [(117, 6, 118, 4), (45, 6, 46, 14), (24, 11, 25, 16), (16, 30, 17, 2)], // 24
+    [(106, 8, 107, 4), (47, 8, 48, 13), (24, 7, 25, 22), (15, 22, 16, 13)], // 25
+    [(114, 10, 115, 2), (46, 19, 47, 4), (22, 28, 23, 6), (16, 33, 17, 4)], // 26
+    [(122, 8, 123, 4), (45, 22, 46, 3), (23, 8, 24, 26), (15, 12, 16, 28)], // 27
+    [(117, 3, 118, 10), (45, 3, 46, 23), (24, 4, 25, 31), (15, 11, 16, 31)], // 28
+    [(116, 7, 117, 7), (45, 21, 46, 7), (23, 1, 24, 37), (15, 19, 16, 26)], // 29
+    [(115, 5, 116, 10), (47, 19, 48, 10), (24, 15, 25, 25), (15, 23, 16, 25)], // 30
+    [(115, 13, 116, 3), (46, 2, 47, 29), (24, 42, 25, 1), (15, 23, 16, 28)], // 31
+    [(115, 17, 0, 0), (46, 10, 47, 23), (24, 10, 25, 35), (15, 19, 16, 35)], // 32
+    [(115, 17, 116, 1), (46, 14, 47, 21), (24, 29, 25, 19), (15, 11, 16, 46)], // 33
+    [(115, 13, 116, 6), (46, 14, 47, 23), (24, 44, 25, 7), (16, 59, 17, 1)], // 34
+    [(121, 12, 122, 7), (47, 12, 48, 26), (24, 39, 25, 14), (15, 22, 16, 41)], // 35
+    [(121, 6, 122, 14), (47, 6, 48, 34), (24, 46, 25, 10), (15, 2, 16, 64)], // 36
+    [(122, 17, 123, 4), (46, 29, 47, 14), (24, 49, 25, 10), (15, 24, 16, 46)], // 37
+    [(122, 4, 123, 18), (46, 13, 47, 32), (24, 48, 25, 14), (15, 42, 16, 32)], // 38
+    [(117, 20, 118, 4), (47, 40, 48, 7), (24, 43, 25, 22), (15, 10, 16, 67)], // 39
+    [(118, 19, 119, 6), (47, 18, 48, 31), (24, 34, 25, 34), (15, 20, 16, 61)], // 40
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use qrcode::bits::Bits;
+    use qrcode::{Color, EcLevel, QrCode, Version};
+    use rqrr::SimpleGrid;
+
+    fn read_code(code: &QrCode) -> Stream {
+        let width = code.width();
+        let colors = code.to_colors();
+        let grid = Grid::new(SimpleGrid::from_func(width, |x, y| {
+            colors[y * width + x] == Color::Dark
+        }));
+        read(&grid).unwrap().1
+    }
+
+    fn pack(bits: &str) -> Vec<u8> {
+        let bits: Vec<u8> = bits.bytes().filter(|b| *b != b' ').map(|b| b - b'0').collect();
+        bits.chunks(8)
+            .map(|chunk| chunk.iter().enumerate().fold(0, |acc, (i, b)| acc | b << (7 - i)))
+            .collect()
+    }
+
+    #[test]
+    fn test_eci_segments() {
+        let mut bits = Bits::new(Version::Normal(5));
+        bits.push_numeric_data(b"0123456").unwrap();
+        bits.push_alphanumeric_data(b"AB-C").unwrap();
+        bits.push_eci_designator(20).unwrap();
+        bits.push_byte_data(&[0x82, 0xa0, 0x82, 0xa2]).unwrap();
+        bits.push_terminator(EcLevel::Q).unwrap();
+        let stream = read_code(&QrCode::with_bits(bits, EcLevel::Q).unwrap());
+
+        assert_eq!(stream.eci(), Some(20));
+        assert_eq!(stream.bytes(), b"0123456AB-C\x82\xa0\x82\xa2");
+        assert_eq!(stream.decode_text(None), ("0123456AB-Cあい".to_string(), "Shift_JIS"));
+    }
+
+    #[test]
+    fn test_kanji_and_hanzi() {
+        let mut bits = Bits::new(Version::Normal(1));
+        bits.push_kanji_data(&[0x93, 0x5f, 0xe4, 0xaa]).unwrap();
+        bits.push_terminator(EcLevel::L).unwrap();
+        let stream = read_code(&QrCode::with_bits(bits, EcLevel::L).unwrap());
+        assert!(stream.kanji);
+        assert_eq!(stream.decode_text(None).0, "点茗");
+
+        // 中文汉字模式：「啊」的 GB 2312 编码 B0A1 压缩为 0x0A * 0x60
+        let stream = parse(&pack("1101 0001 00000001 0001111000000 0000"), 1).unwrap();
+        assert_eq!(stream.decode_text(None), ("啊".to_string(), "GBK"));
+    }
+
+    #[test]
+    fn test_structured_append_header() {
+        let data = pack("0011 0001 0010 01011010 0100 00000010 01101000 01101001 0000");
+        let stream = parse(&data, 1).unwrap();
+        assert_eq!(
+            stream.structured_append,
+            Some(StructuredAppend {
+                index: 2,
+                total: 3,
+                parity: 0x5a,
+            })
+        );
+        assert_eq!(stream.bytes(), b"hi");
+        assert!(parse(&pack("0011 0011 0010 01011010 0000"), 1).is_err());
+    }
+
+    #[test]
+    fn test_detect_charset() {
+        let stream = |bytes: &[u8]| Stream {
+            runs: vec![(None, bytes.to_vec())],
+            ..Default::default()
+        };
+        assert_eq!(stream("中文".as_bytes()).decode_text(None).1, "UTF-8");
+        assert_eq!(
+            stream(&[0xd6, 0xd0, 0xce, 0xc4]).decode_text(None),
+            ("中文".to_string(), "GBK")
+        );
+        assert_eq!(stream(b"caf\xe9").decode_text(None).0, "café");
+        assert_eq!(stream(&[0x00, 0x81, 0xff]).decode_text(None).1, "binary");
+        assert_eq!(stream(&[0xd6, 0xd0]).decode_text(Some(SHIFT_JIS)).1, "Shift_JIS");
+    }
+}
@@ -0,0 +1,475 @@
+use std::fs;
+use std::io::{self, Read};
+use std::path::PathBuf;
+
+use clap::{Args, Subcommand, ValueEnum};
+use serde::Deserialize;
+
+/// 生成结构化内容的二维码
+#[derive(Subcommand)]
+pub enum Compose {
+    /// WiFi network credentials (WIFI: format)
+    Wifi(WifiArgs),
+    /// Contact card (vCard 3.0) from a JSON file
+    Vcard(VcardArgs),
+    /// Calendar event (iCalendar VEVENT)
+    Event(EventArgs),
+}
+
+#[derive(Args)]
+pub struct WifiArgs {
+    /// Network name
+    #[arg(long)]
+    ssid: String,
+
+    /// Authentication type [default: wpa, or nopass without --password]
+    #[arg(long, value_enum)]
+    auth: Option<WifiAuth>,
+
+    /// Network password
+    #[arg(long)]
+    password: Option<String>,
+
+    /// The network does not broadcast its SSID
+    #[arg(long)]
+    hidden: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum WifiAuth {
+    /// WPA/WPA2 personal
+    #[value(name = "wpa", alias = "WPA")]
+    Wpa,
+    /// WPA3 personal
+    #[value(name = "sae", alias = "SAE")]
+    Sae,
+    /// Legacy WEP
+    #[value(name = "wep", alias = "WEP")]
+    Wep,
+    /// Open network
+    #[value(name = "nopass")]
+    Nopass,
+}
+
+#[derive(Args)]
+pub struct VcardArgs {
+    /// JSON file with name, organization, title, phones, emails, urls, addresses, birthday and note ("-" for stdin)
+    file: PathBuf,
+}
+
+#[derive(Args)]
+pub struct EventArgs {
+    /// Event title
+    #[arg(long)]
+    summary: String,
+
+    /// Start as YYYY-MM-DD for all-day events, or YYYY-MM-DDTHH:MM[:SS][Z]
+    #[arg(long)]
+    start: String,
+
+    /// End, in the same form as --start
+    #[arg(long)]
+    end: Option<String>,
+
+    #[arg(long)]
+    location: Option<String>,
+
+    #[arg(long)]
+    description: Option<String>,
+
+    #[arg(long)]
+    url: Option<String>,
+}
+
+/// vCard 的 JSON 输入，字段与解码输出的 contact 一致
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Card {
+    pub name: Option<String>,
+    /// 未指定时取 name 的最后一个词为姓，其余为名
+    pub family_name: Option<String>,
+    pub given_name: Option<String>,
+    pub organization: Option<String>,
+    pub title: Option<String>,
+    pub phones: Vec<String>,
+    pub emails: Vec<String>,
+    pub urls: Vec<String>,
+    pub addresses: Vec<Address>,
+    pub birthday: Option<String>,
+    pub note: Option<String>,
+}
+
+/// 地址可以是一整行文字，也可以按组成部分填写
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Address {
+    Line(String),
+    Parts {
+        #[serde(default)]
+        street: String,
+        #[serde(default)]
+        city: String,
+        #[serde(default)]
+        region: String,
+        #[serde(default)]
+        postal_code: String,
+        #[serde(default)]
+        country: String,
+    },
+}
+
+impl Compose {
+    /// 生成二维码内容
+    pub fn build(&self) -> Result<String, String> {
+        match self {
+            Compose::Wifi(args) => wifi(args),
+            Compose::Vcard(args) => {
+                let text = read_text(&args.file).map_err(|e| format!("can not read {}: {}", args.file.display(), e))?;
+                let card: Card = serde_json::from_str(&text)
+                    .map_err(|e| format!("invalid vcard json {}: {}", args.file.display(), e))?;
+                vcard(&card)
+            }
+            Compose::Event(args) => event(args),
+        }
+    }
+}
+
+fn read_text(path: &PathBuf) -> io::Result<String> {
+    if path.as_os_str() == "-" {
+        let mut text = String::new();
+        io::stdin().read_to_string(&mut text)?;
+        Ok(text)
+    } else {
+        fs::read_to_string(path)
+    }
+}
+
+// `WIFI:T:WPA;S:ssid;P:password;H:true;;`
+fn wifi(args: &WifiArgs) -> Result<String, String> {
+    let auth = args.auth.unwrap_or(if args.password.is_some() {
+        WifiAuth::Wpa
+    } else {
+        WifiAuth::Nopass
+    });
+    match (auth, &args.password) {
+        (WifiAuth::Nopass, Some(_)) => return Err("an open network (nopass) takes no password".to_string()),
+        (WifiAuth::Wpa | WifiAuth::Sae | WifiAuth::Wep, None) => {
+            return Err("--password is required unless --auth nopass".to_string());
+        }
+        _ => {}
+    }
+    let auth = match auth {
+        WifiAuth::Wpa => "WPA",
+        WifiAuth::Sae => "SAE",
+        WifiAuth::Wep => "WEP",
+        WifiAuth::Nopass => "nopass",
+    };
+
+    let mut out = format!("WIFI:T:{};S:{};", auth, wifi_value(&args.ssid));
+    if let Some(password) = &args.password {
+        out.push_str(&format!("P:{};", wifi_value(password)));
+    }
+    if args.hidden {
+        out.push_str("H:true;");
+    }
+    out.push(';');
+    Ok(out)
+}
+
+// 转义 `\ ; , : "`，可能被当作十六进制的值加双引号
+fn wifi_value(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '\\' | ';' | ',' | ':' | '"') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    if !value.is_empty() && value.len().is_multiple_of(2) && value.bytes().all(|b| b.is_ascii_hexdigit()) {
+        format!("\"{}\"", out)
+    } else {
+        out
+    }
+}
+
+// vCard 3.0，行尾为 CRLF；内容较短，不折叠长行
+fn vcard(card: &Card) -> Result<String, String> {
+    let (family, given) = match (&card.family_name, &card.given_name) {
+        (None, None) => {
+            let name = card.name.as_deref().unwrap_or_default().trim();
+            match name.rsplit_once(char::is_whitespace) {
+                Some((given, family)) => (family.to_string(), given.trim().to_string()),
+                None => (name.to_string(), String::new()),
+            }
+        }
+        (family, given) => (family.clone().unwrap_or_default(), given.clone().unwrap_or_default()),
+    };
+    let full_name = match &card.name {
+        Some(name) => name.trim().to_string(),
+        None => [given.as_str(), family.as_str()]
+            .iter()
+            .filter(|s| !s.is_empty())
+            .copied()
+            .collect::<Vec<_>>()
+            .join(" "),
+    };
+    if full_name.is_empty() && card.organization.is_none() {
+        return Err("a vcard needs a name or an organization".to_string());
+    }
+
+    let mut lines = vec!["BEGIN:VCARD".to_string(), "VERSION:3.0".to_string()];
+    lines.push(format!("N:{};{};;;", text_value(&family), text_value(&given)));
+    // FN 为必填属性，没有姓名时使用单位名称
+    let display = match &card.organization {
+        Some(organization) if full_name.is_empty() => organization,
+        _ => &full_name,
+    };
+    lines.push(format!("FN:{}", text_value(display)));
+    let mut push = |name: &str, value: &Option<String>| {
+        if let Some(value) = value {
+            lines.push(format!("{}:{}", name, text_value(value)));
+        }
+    };
+    push("ORG", &card.organization);
+    push("TITLE", &card.title);
+    push("BDAY", &card.birthday);
+    push("NOTE", &card.note);
+    for phone in &card.phones {
+        lines.push(format!("TEL:{}", text_value(phone)));
+    }
+    for email in &card.emails {
+        lines.push(format!("EMAIL:{}", text_value(email)));
+    }
+    // URL 的值类型为 URI，不做文本转义
+    for url in &card.urls {
+        lines.push(format!("URL:{}", url));
+    }
+    for address in &card.addresses {
+        // 邮政信箱;扩展地址;街道;城市;省份;邮编;国家
+        let parts = match address {
+            Address::Line(line) => ["", "", line.as_str(), "", "", "", ""].map(text_value),
+            Address::Parts {
+                street,
+                city,
+                region,
+                postal_code,
+                country,
+            } => ["", "", street, city, region, postal_code, country].map(text_value),
+        };
+        lines.push(format!("ADR:{}", parts.join(";")));
+    }
+    lines.push("END:VCARD".to_string());
+    Ok(lines.join("\r\n"))
+}
+
+// 不带 VCALENDAR 外层的 VEVENT，与常见扫码应用生成的格式一致
+fn event(args: &EventArgs) -> Result<String, String> {
+    let start = ical_time(&args.start)?;
+    let mut lines = vec![
+        "BEGIN:VEVENT".to_string(),
+        format!("SUMMARY:{}", text_value(&args.summary)),
+    ];
+    lines.push(start.property("DTSTART"));
+    if let Some(end) = &args.end {
+        let end = ical_time(end)?;
+        if end.is_date != start.is_date || end.utc != start.utc {
+            return Err("--start and --end must use the same form".to_string());
+        }
+        if end.value < start.value {
+            return Err("--end is before --start".to_string());
+        }
+        lines.push(end.property("DTEND"));
+    }
+    for (name, value) in [("LOCATION", &args.location), ("DESCRIPTION", &args.description)] {
+        if let Some(value) = value {
+            lines.push(format!("{}:{}", name, text_value(value)));
+        }
+    }
+    // URL 的值类型为 URI，不做文本转义
+    if let Some(url) = &args.url {
+        lines.push(format!("URL:{}", url));
+    }
+    lines.push("END:VEVENT".to_string());
+    Ok(lines.join("\r\n"))
+}
+
+struct IcalTime {
+    value: String,
+    is_date: bool,
+    utc: bool,
+}
+
+impl IcalTime {
+    fn property(&self, name: &str) -> String {
+        if self.is_date {
+            format!("{};VALUE=DATE:{}", name, self.value)
+        } else {
+            format!("{}:{}", name, self.value)
+        }
+    }
+}
+
+// `2024-01-02` 或 `2024-01-02T10:00[:00][Z]`（也接受空格分隔）转为 `20240102` / `20240102T100000[Z]`
+fn ical_time(input: &str) -> Result<IcalTime, String> {
+    let invalid = || {
+        format!(
+            "invalid time '{}', expected YYYY-MM-DD or YYYY-MM-DDTHH:MM[:SS][Z]",
+            input
+        )
+    };
+    let trimmed = input.trim();
+    let (date, time) = match trimmed.split_once(['T', ' ']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (trimmed, None),
+    };
+    let numbers = |s: &str, sep: char, count: usize| -> Option<Vec<u32>> {
+        let parts: Vec<&str> = s.split(sep).collect();
+        let valid = parts.len() == count
+            && parts
+                .iter()
+                .all(|p| !p.is_empty() && p.bytes().all(|b| b.is_ascii_digit()));
+        valid.then(|| parts.iter().map(|p| p.parse().unwrap_or(0)).collect())
+    };
+
+    let date = numbers(date, '-', 3).ok_or_else(invalid)?;
+    let (year, month, day) = (date[0], date[1], date[2]);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || year > 9999 {
+        return Err(invalid());
+    }
+    let date = format!("{:04}{:02}{:02}", year, month, day);
+    let Some(time) = time else {
+        return Ok(IcalTime {
+            value: date,
+            is_date: true,
+            utc: false,
+        });
+    };
+
+    let (time, utc) = match time.strip_suffix(['Z', 'z']) {
+        Some(time) => (time, true),
+        None => (time, false),
+    };
+    let time = numbers(time, ':', 3)
+        .or_else(|| {
+            numbers(time, ':', 2).map(|mut t| {
+                t.push(0);
+                t
+            })
+        })
+        .ok_or_else(invalid)?;
+    if time[0] > 23 || time[1] > 59 || time[2] > 60 {
+        return Err(invalid());
+    }
+    Ok(IcalTime {
+        value: format!(
+            "{}T{:02}{:02}{:02}{}",
+            date,
+            time[0],
+            time[1],
+            time[2],
+            if utc { "Z" } else { "" }
+        ),
+        is_date: false,
+        utc,
+    })
+}
+
+// vCard/iCalendar 文本转义：`\` `,` `;` 及换行
+fn text_value(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' | ',' | ';' => {
+                out.push('\\');
+                out.push(c);
+            }
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\r' | '\n' => out.push_str("\\n"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::payload::{self, Payload};
+
+    #[test]
+    fn test_wifi() {
+        let args = WifiArgs {
+            ssid: r#"my;net"work"#.to_string(),
+            auth: None,
+            password: Some("p:a\\ss".to_string()),
+            hidden: true,
+        };
+        let content = wifi(&args).unwrap();
+        assert_eq!(content, r#"WIFI:T:WPA;S:my\;net\"work;P:p\:a\\ss;H:true;;"#);
+        let Payload::Wifi(parsed) = payload::classify(&content) else {
+            panic!("not wifi: {}", content);
+        };
+        assert_eq!(parsed.ssid, r#"my;net"work"#);
+        assert_eq!(parsed.password.as_deref(), Some("p:a\\ss"));
+        assert!(parsed.hidden);
+
+        // 十六进制形式的值加引号
+        assert_eq!(wifi_value("00ff"), "\"00ff\"");
+        let open = WifiArgs {
+            ssid: "cafe guest".to_string(),
+            auth: Some(WifiAuth::Nopass),
+            password: None,
+            hidden: false,
+        };
+        assert_eq!(wifi(&open).unwrap(), "WIFI:T:nopass;S:cafe guest;;");
+    }
+
+    #[test]
+    fn test_vcard() {
+        let card: Card = serde_json::from_str(
+            r#"{"name": "John Doe", "organization": "ACME, Inc.", "phones": ["+1 555 0100"],
+                "addresses": ["1 Main St; Apt 2", {"city": "Springfield", "country": "US"}],
+                "note": "line one\nline two"}"#,
+        )
+        .unwrap();
+        let content = vcard(&card).unwrap();
+        assert!(content.contains("N:Doe;John;;;\r\nFN:John Doe\r\nORG:ACME\\, Inc.\r\n"));
+        let Payload::Contact(contact) = payload::classify(&content) else {
+            panic!("not a contact: {}", content);
+        };
+        assert_eq!(contact.name.as_deref(), Some("John Doe"));
+        assert_eq!(contact.organization.as_deref(), Some("ACME, Inc."));
+        assert_eq!(contact.addresses, vec!["1 Main St; Apt 2", "Springfield, US"]);
+        assert_eq!(contact.note.as_deref(), Some("line one\nline two"));
+        assert!(serde_json::from_str::<Card>(r#"{"nmae": "typo"}"#).is_err());
+    }
+
+    #[test]
+    fn test_event() {
+        let mut args = EventArgs {
+            summary: "Launch; party".to_string(),
+            start: "2024-01-02 10:00Z".to_string(),
+            end: Some("2024-01-02T12:30:00Z".to_string()),
+            location: Some("Room 1, HQ".to_string()),
+            description: None,
+            url: None,
+        };
+        let content = event(&args).unwrap();
+        assert!(content.contains("DTSTART:20240102T100000Z\r\nDTEND:20240102T123000Z"));
+        let Payload::Event(parsed) = payload::classify(&content) else {
+            panic!("not an event: {}", content);
+        };
+        assert_eq!(parsed.summary.as_deref(), Some("Launch; party"));
+        assert_eq!(parsed.start.as_deref(), Some("2024-01-02T10:00:00Z"));
+        assert_eq!(parsed.location.as_deref(), Some("Room 1, HQ"));
+
+        args.start = "2024-01-02".to_string();
+        args.end = None;
+        assert!(event(&args).unwrap().contains("DTSTART;VALUE=DATE:20240102"));
+        args.end = Some("2024-01-01".to_string());
+        assert!(event(&args).is_err());
+        args.start = "2024-13-02".to_string();
+        assert!(event(&args).is_err());
+    }
+}
@@ -0,0 +1,636 @@
+use std::mem;
+use std::ops::{Deref, DerefMut};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use data_encoding::BASE64;
+use encoding_rs::Encoding;
+use image::{DynamicImage, GenericImageView, GrayImage, ImageBuffer, Luma, imageops};
+use rqrr::{BitGrid, DeQRError, Grid, PreparedImage};
+use serde::Serialize;
+
+use crate::bitstream::{self, Stream, StructuredAppend};
+use crate::payload::{self, Payload};
+use crate::preprocess::{self, PYRAMID, Strategy};
+use crate::safety::Report;
+use crate::symbology::{self, Symbology};
+use crate::vector::Render;
+
+// 最长边超过该尺寸时先在图像金字塔上检测
+const PYRAMID_MIN: u32 = 2048;
+// 金字塔最小一层的最长边上限
+const PYRAMID_BASE: u32 = 1024;
+// 缓冲池最多保留的缓冲区数量
+const POOL_SIZE: usize = 16;
+
+/// 单个条码的解码结果
+#[derive(Debug, Serialize)]
+pub struct Decoded {
+    pub symbology: Symbology,
+    pub content: String,
+    /// 纠错后的数据码流
+    #[serde(skip)]
+    pub stream: Stream,
+    /// 内容所用的字符集，无法识别为文本时为 `binary`
+    pub encoding: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub eci: Option<u32>,
+    /// 二进制内容的 Base64 编码
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub base64: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub structured_append: Option<StructuredAppend>,
+    /// 二维码版本，其他码制为空
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ecc_level: Option<String>,
+    pub strategy: &'static str,
+    pub payload: Payload,
+    /// 内容为网址时的风险报告
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub safety: Option<Report>,
+}
+
+impl Decoded {
+    /// 由纠错后的码流生成解码结果，`charset` 指定时忽略 ECI 声明及字符集猜测
+    pub fn new(
+        stream: Stream,
+        charset: Option<&'static Encoding>,
+        symbology: Symbology,
+        version: Option<usize>,
+        ecc_level: Option<String>,
+        strategy: &'static str,
+    ) -> Self {
+        let (content, encoding) = stream.decode_text(charset);
+        let binary = encoding == "binary";
+        Self {
+            symbology,
+            payload: if binary {
+                Payload::Binary
+            } else {
+                payload::classify(&content)
+            },
+            content,
+            base64: binary.then(|| BASE64.encode(&stream.bytes())),
+            encoding,
+            eci: stream.eci(),
+            structured_append: stream.structured_append,
+            version,
+            ecc_level,
+            strategy,
+            stream,
+            safety: None,
+        }
+    }
+}
+
+/// 扫描选项
+#[derive(Debug, Default)]
+pub struct ScanOptions {
+    /// 指定时忽略 ECI 声明及字符集猜测
+    pub charset: Option<&'static Encoding>,
+    /// 需要识别的码制，为空时识别全部码制
+    pub symbologies: Vec<Symbology>,
+    /// 只扫描图像中的该区域
+    pub crop: Option<Crop>,
+    /// 大图先在缩小的图像金字塔上检测
+    pub pyramid: bool,
+    /// 只尝试原图和 Otsu 二值化两种策略，用于实时预览及生成时的自检
+    pub quick: bool,
+    /// 单幅图像的扫描时间上限，超过后不再尝试剩余的策略；原图和 Otsu 二值化总会尝试
+    pub time_limit: Option<Duration>,
+    /// SVG 和 PDF 的渲染选项
+    pub render: Render,
+    /// 指定时 rqrr 使用缓冲池中的像素缓冲区，连续扫描视频帧时避免反复分配
+    pub buffers: Option<BufferPool>,
+}
+
+/// rqrr 二值化及检测定位图案时所用像素缓冲区的缓冲池
+#[derive(Clone, Debug, Default)]
+pub struct BufferPool(Arc<Mutex<Vec<Vec<u8>>>>);
+
+impl BufferPool {
+    // 复制图像到缓冲池中的缓冲区
+    fn image(&self, img: &GrayImage) -> ImageBuffer<Luma<u8>, Pooled> {
+        let (width, height) = img.dimensions();
+        ImageBuffer::from_raw(width, height, self.copy(img)).expect("pooled buffer has the image size")
+    }
+
+    // 对图像应用策略，结果写入缓冲池中的缓冲区；逐像素的策略不再另外分配整幅图像
+    fn apply(&self, strategy: &Strategy, img: &GrayImage) -> Option<ImageBuffer<Luma<u8>, Pooled>> {
+        let (width, height) = img.dimensions();
+        let mut pixels = self.copy(&[]);
+        pixels.pixels.resize(img.len(), 0);
+        if !strategy.apply_into(img, &mut pixels) {
+            return strategy.apply(img).map(|out| self.image(&out));
+        }
+        Some(ImageBuffer::from_raw(width, height, pixels).expect("pooled buffer has the image size"))
+    }
+
+    fn copy(&self, pixels: &[u8]) -> Pooled {
+        let mut buffer = self.0.lock().unwrap().pop().unwrap_or_default();
+        buffer.clear();
+        buffer.extend_from_slice(pixels);
+        Pooled {
+            pixels: buffer,
+            pool: self.clone(),
+        }
+    }
+}
+
+// 缓冲池中取出的像素，释放时归还缓冲池；rqrr 验证定位图案组合时会复制整幅图像
+struct Pooled {
+    pixels: Vec<u8>,
+    pool: BufferPool,
+}
+
+impl Deref for Pooled {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.pixels
+    }
+}
+
+impl DerefMut for Pooled {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.pixels
+    }
+}
+
+impl Clone for Pooled {
+    fn clone(&self) -> Self {
+        self.pool.copy(&self.pixels)
+    }
+}
+
+impl Drop for Pooled {
+    fn drop(&mut self) {
+        let mut pool = self.pool.0.lock().unwrap();
+        if pool.len() < POOL_SIZE {
+            pool.push(mem::take(&mut self.pixels));
+        }
+    }
+}
+
+/// 扫描区域，格式为 `x,y,w,h`，单位为像素
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Crop {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl FromStr for Crop {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let values = s
+            .split(',')
+            .map(|v| v.trim().parse::<u32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("invalid crop '{}': {}", s, e))?;
+        match values[..] {
+            [x, y, width, height] if width > 0 && height > 0 => Ok(Self { x, y, width, height }),
+            [_, _, _, _] => Err(format!("invalid crop '{}': width and height must be positive", s)),
+            _ => Err(format!("invalid crop '{}': expected x,y,w,h", s)),
+        }
+    }
+}
+
+impl Crop {
+    /// 裁剪图像，超出图像的部分被忽略，区域完全在图像之外时返回 None
+    pub fn apply(&self, img: &DynamicImage) -> Option<DynamicImage> {
+        let (w, h) = img.dimensions();
+        if self.x >= w || self.y >= h {
+            return None;
+        }
+        let width = self.width.min(w - self.x);
+        let height = self.height.min(h - self.y);
+        Some(img.crop_imm(self.x, self.y, width, height))
+    }
+}
+
+#[derive(Debug)]
+pub enum ScanError {
+    /// 所有策略均未检测到条码
+    NotFound,
+    /// 检测到二维码但无法解码
+    Decode(DeQRError),
+    /// 未检测到条码，且到达时间上限时还有策略未尝试
+    TimeLimit,
+}
+
+/// detect_grids 检测到的二维码位置，坐标已换算回原图
+#[derive(Debug)]
+pub struct Detection {
+    /// 四个角，依次为左上、右上、右下、左下，对应网格坐标 (0, 0) 到 (size + 1, size + 1)
+    pub bounds: [(f32, f32); 4],
+    /// 每边的模块数
+    pub size: usize,
+    /// 成功时为该二维码在结果中的序号
+    pub outcome: Result<usize, DeQRError>,
+}
+
+/// 扫描过程的调试信息，取自返回结果的策略；全部失败时取第一个检测到二维码的策略
+#[derive(Default)]
+pub struct Trace {
+    pub strategy: Option<&'static str>,
+    pub detections: Vec<Detection>,
+    /// rqrr 二值化后的图像，定位图案已被标记
+    pub prepared: Option<PreparedImage<GrayImage>>,
+    /// 各阶段耗时
+    pub timings: Vec<(String, Duration)>,
+    /// 依次尝试过的策略，以及是否同时用 rxing 识别了其他码制
+    pub tried: Vec<(&'static str, bool)>,
+}
+
+impl Trace {
+    fn time(&mut self, stage: String, started: Instant) {
+        self.timings.push((stage, started.elapsed()));
+    }
+
+    // 记录某次检测使用的策略、检测到的位置及二值化图像
+    fn record(
+        &mut self,
+        strategy: &'static str,
+        detections: Vec<Detection>,
+        prepared: Option<PreparedImage<GrayImage>>,
+    ) {
+        self.strategy = Some(strategy);
+        self.detections = detections;
+        self.prepared = prepared;
+    }
+}
+
+/// 依次尝试预处理策略，返回第一个成功解码的策略下的全部结果
+pub fn scan(img: &GrayImage, options: &ScanOptions) -> Result<Vec<Decoded>, ScanError> {
+    scan_traced(img, options, &mut Trace::default())
+}
+
+/// 与 `scan` 相同，同时记录检测过程及耗时供调试输出
+pub fn scan_traced(img: &GrayImage, options: &ScanOptions, trace: &mut Trace) -> Result<Vec<Decoded>, ScanError> {
+    let deadline = options.time_limit.map(|limit| Instant::now() + limit);
+    let mut last_error = None;
+    if options.pyramid
+        && img.width().max(img.height()) > PYRAMID_MIN
+        && let Some(results) = scan_pyramid(img, options, deadline, trace, &mut last_error)
+    {
+        return Ok(results);
+    }
+
+    let started = Instant::now();
+    let found = scan_strategies(img, options, deadline, trace, (0.0, 0.0));
+    trace.time(format!("full {}x{}", img.width(), img.height()), started);
+    match (found, last_error) {
+        (Err(ScanError::NotFound | ScanError::TimeLimit), Some(e)) => Err(ScanError::Decode(e)),
+        (found, _) => found,
+    }
+}
+
+// 依次尝试预处理策略，检测位置加上 offset 后记录到 trace；超过 deadline 后只尝试快速策略
+fn scan_strategies(
+    img: &GrayImage,
+    options: &ScanOptions,
+    deadline: Option<Instant>,
+    trace: &mut Trace,
+    offset: (f32, f32),
+) -> Result<Vec<Decoded>, ScanError> {
+    let mut last_error = None;
+    let mut skipped = false;
+    for strategy in preprocess::strategies(options.quick) {
+        if !strategy.is_quick() && deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            skipped = true;
+            continue;
+        }
+        let prepared = match &options.buffers {
+            Some(pool) => pool.apply(strategy, img).map(Prepared::Pooled),
+            None => strategy.apply(img).map(Prepared::Owned),
+        };
+        let Some(prepared) = prepared else {
+            continue;
+        };
+        let attempt = scan_with(prepared, strategy, options, trace, &mut last_error);
+        if !attempt.results.is_empty() || (trace.strategy.is_none() && !attempt.detections.is_empty()) {
+            let unmap = |point: rqrr::Point| {
+                let (x, y) = strategy.unmap(img.dimensions(), (point.x as f32, point.y as f32));
+                (x + offset.0, y + offset.1)
+            };
+            let detections = attempt
+                .detections
+                .into_iter()
+                .map(|(bounds, size, outcome)| Detection {
+                    bounds: bounds.map(unmap),
+                    size,
+                    outcome,
+                })
+                .collect();
+            trace.record(strategy.name, detections, attempt.prepared);
+        }
+        if !attempt.results.is_empty() {
+            return Ok(attempt.results);
+        }
+    }
+
+    match last_error {
+        Some(e) => Err(ScanError::Decode(e)),
+        None if skipped => Err(ScanError::TimeLimit),
+        None => Err(ScanError::NotFound),
+    }
+}
+
+// 从金字塔最小的一层开始检测，二维码无法解码时在原图的对应区域重试全部策略；
+// 所有层都没有结果时返回 None，由调用方扫描整幅原图
+fn scan_pyramid(
+    img: &GrayImage,
+    options: &ScanOptions,
+    deadline: Option<Instant>,
+    trace: &mut Trace,
+    last_error: &mut Option<DeQRError>,
+) -> Option<Vec<Decoded>> {
+    let started = Instant::now();
+    let mut levels: Vec<GrayImage> = Vec::new();
+    let (mut w, mut h) = img.dimensions();
+    while w.max(h) > PYRAMID_BASE {
+        (w, h) = ((w / 2).max(1), (h / 2).max(1));
+        let level = imageops::thumbnail(levels.last().unwrap_or(img), w, h);
+        levels.push(level);
+    }
+    trace.time("pyramid".to_string(), started);
+
+    for level in levels.iter().rev() {
+        let started = Instant::now();
+        let scale = img.width() as f32 / level.width() as f32;
+        let prepared = match &options.buffers {
+            Some(pool) => Prepared::Pooled(pool.image(level)),
+            None => Prepared::Owned(level.clone()),
+        };
+        let attempt = scan_with(prepared, &PYRAMID, options, trace, last_error);
+        let mut results = attempt.results;
+        let mut detections = Vec::new();
+        let mut refined = Vec::new();
+        for (bounds, size, outcome) in attempt.detections {
+            let bounds = bounds.map(|p| (p.x as f32 * scale, p.y as f32 * scale));
+            if outcome.is_ok() {
+                detections.push(Detection { bounds, size, outcome });
+            } else {
+                refined.push(Detection { bounds, size, outcome });
+            }
+        }
+        trace.time(format!("pyramid {}x{}", level.width(), level.height()), started);
+
+        for candidate in refined {
+            let (x, y, w, h) = candidate_region(&candidate.bounds, img.dimensions());
+            let started = Instant::now();
+            let region = imageops::crop_imm(img, x, y, w, h).to_image();
+            let mut local = Trace::default();
+            match scan_strategies(&region, options, deadline, &mut local, (x as f32, y as f32)) {
+                Ok(found) => {
+                    let base = results.len();
+                    detections.extend(local.detections.into_iter().map(|d| Detection {
+                        outcome: d.outcome.map(|index| base + index),
+                        ..d
+                    }));
+                    results.extend(found);
+                }
+                Err(e) => {
+                    if let ScanError::Decode(e) = e {
+                        *last_error = Some(e);
+                    }
+                    detections.push(candidate);
+                }
+            }
+            trace.tried.append(&mut local.tried);
+            trace.time(format!("refine {}x{}", w, h), started);
+        }
+
+        if !results.is_empty() || (trace.strategy.is_none() && !detections.is_empty()) {
+            trace.record(PYRAMID.name, detections, attempt.prepared);
+        }
+        if !results.is_empty() {
+            return Some(results);
+        }
+    }
+    None
+}
+
+// 候选二维码在原图中的外接矩形，四周留出四分之一边长的余量
+fn candidate_region(bounds: &[(f32, f32); 4], (width, height): (u32, u32)) -> (u32, u32, u32, u32) {
+    let (mut left, mut top, mut right, mut bottom) = (f32::MAX, f32::MAX, f32::MIN, f32::MIN);
+    for &(x, y) in bounds {
+        left = left.min(x);
+        top = top.min(y);
+        right = right.max(x);
+        bottom = bottom.max(y);
+    }
+    let margin = (right - left).max(bottom - top) / 4.0;
+    let clamp = |v: f32, max: u32| v.clamp(0.0, max as f32) as u32;
+    let (x, y) = (clamp(left - margin, width), clamp(top - margin, height));
+    let (r, b) = (clamp(right + margin, width), clamp(bottom + margin, height));
+    (x, y, (r - x).max(1), (b - y).max(1))
+}
+
+// 单个策略的扫描结果，检测位置为预处理后图像中的坐标
+struct Attempt {
+    results: Vec<Decoded>,
+    detections: Vec<([rqrr::Point; 4], usize, Result<usize, DeQRError>)>,
+    prepared: Option<PreparedImage<GrayImage>>,
+}
+
+// 预处理后的图像，使用缓冲池时像素保存在缓冲池的缓冲区中
+enum Prepared {
+    Owned(GrayImage),
+    Pooled(ImageBuffer<Luma<u8>, Pooled>),
+}
+
+// rxing 每次都要复制整幅图像并尝试全部码制，开销远大于 rqrr，只在快速策略上运行
+fn scan_with(
+    img: Prepared,
+    strategy: &Strategy,
+    options: &ScanOptions,
+    trace: &mut Trace,
+    last_error: &mut Option<DeQRError>,
+) -> Attempt {
+    let mut attempt = Attempt {
+        results: Vec::new(),
+        detections: Vec::new(),
+        prepared: None,
+    };
+    let mut barcodes = Vec::new();
+    let rxing = strategy.is_quick() && symbology::scans_barcodes(options);
+    if rxing {
+        barcodes = match &img {
+            Prepared::Owned(img) => symbology::scan_barcodes(img, strategy, options),
+            Prepared::Pooled(img) => symbology::scan_barcodes(img, strategy, options),
+        };
+    }
+    trace.tried.push((strategy.name, rxing));
+    if symbology::enabled(options, Symbology::Qr) {
+        match img {
+            Prepared::Pooled(img) => {
+                let mut prepared = PreparedImage::prepare(img);
+                read_grids(prepared.detect_grids(), strategy, options, &mut attempt, last_error);
+            }
+            Prepared::Owned(img) => {
+                let mut prepared = PreparedImage::prepare(img);
+                read_grids(prepared.detect_grids(), strategy, options, &mut attempt, last_error);
+                attempt.prepared = Some(prepared);
+            }
+        }
+    }
+    attempt.results.extend(barcodes);
+    attempt
+}
+
+// 读取检测到的二维码，结果及检测位置记录到 attempt
+fn read_grids<G: BitGrid>(
+    grids: Vec<Grid<G>>,
+    strategy: &Strategy,
+    options: &ScanOptions,
+    attempt: &mut Attempt,
+    last_error: &mut Option<DeQRError>,
+) {
+    for grid in grids {
+        let outcome = match bitstream::read(&grid) {
+            Ok((meta, stream)) => {
+                attempt.results.push(Decoded::new(
+                    stream,
+                    options.charset,
+                    Symbology::Qr,
+                    Some(meta.version.0),
+                    Some(ecc_level_name(meta.ecc_level).to_string()),
+                    strategy.name,
+                ));
+                Ok(attempt.results.len() - 1)
+            }
+            Err(e) => {
+                *last_error = Some(e);
+                Err(e)
+            }
+        };
+        attempt.detections.push((grid.bounds, grid.grid.size(), outcome));
+    }
+}
+
+// 格式信息中的纠错等级编码：01=L 00=M 11=Q 10=H
+fn ecc_level_name(level: u16) -> &'static str {
+    match level {
+        0 => "M",
+        1 => "L",
+        2 => "H",
+        _ => "Q",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Luma, Rgba};
+
+    use super::*;
+    use crate::encode::build_code;
+    use crate::render::{self, Matrix, Style};
+
+    #[test]
+    fn test_parse_crop() {
+        let crop: Crop = "10, 20,300,400".parse().unwrap();
+        assert_eq!(
+            crop,
+            Crop {
+                x: 10,
+                y: 20,
+                width: 300,
+                height: 400
+            }
+        );
+        assert!("10,20,300".parse::<Crop>().is_err());
+        assert!("10,20,0,400".parse::<Crop>().is_err());
+        assert!("-1,20,300,400".parse::<Crop>().is_err());
+
+        let img = DynamicImage::new_luma8(100, 80);
+        assert_eq!(crop.apply(&img).unwrap().dimensions(), (90, 60));
+        assert!("100,0,10,10".parse::<Crop>().unwrap().apply(&img).is_none());
+    }
+
+    #[test]
+    fn test_pyramid() {
+        let code = build_code(b"pyramid", qrcode::EcLevel::M, None).unwrap();
+        let style = Style {
+            quiet_zone: 4,
+            module_size: 12,
+            foreground: Rgba([0, 0, 0, 255]),
+            background: Rgba([255, 255, 255, 255]),
+        };
+        let symbol = DynamicImage::ImageRgba8(render::render_image(&Matrix::from_code(&code), &style, None)).to_luma8();
+        let mut img = GrayImage::from_pixel(4000, 3000, Luma([255]));
+        imageops::overlay(&mut img, &symbol, 2500, 1800);
+
+        let options = ScanOptions {
+            symbologies: vec![Symbology::Qr],
+            pyramid: true,
+            ..Default::default()
+        };
+        let mut trace = Trace::default();
+        let results = scan_traced(&img, &options, &mut trace).unwrap();
+        assert_eq!(results[0].content, "pyramid");
+        assert_eq!(results[0].strategy, "pyramid");
+        assert!(trace.timings.iter().all(|(stage, _)| !stage.starts_with("full")));
+
+        // 检测位置换算回原图坐标
+        let (x, y) = trace.detections[0].bounds[0];
+        assert!((x - 2548.0).abs() <= 16.0 && (y - 1848.0).abs() <= 16.0);
+    }
+
+    // 照片大小、没有条码的图像，亮度渐变叠加噪点
+    fn photo(width: u32, height: u32) -> GrayImage {
+        let mut state = 12345u32;
+        GrayImage::from_fn(width, height, |x, y| {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            let noise = (state >> 16) as u8 % 32;
+            Luma([((x + y) * 160 / (width + height)) as u8 + noise])
+        })
+    }
+
+    #[test]
+    fn test_barcodes_on_quick_strategies() {
+        let mut trace = Trace::default();
+        let found = scan_traced(&photo(1920, 1080), &ScanOptions::default(), &mut trace);
+        assert!(matches!(found, Err(ScanError::NotFound)));
+
+        // 全部策略都用 rqrr 尝试过，rxing 只在原图和 Otsu 二值化上运行
+        assert_eq!(trace.tried.len(), preprocess::STRATEGIES.len());
+        let rxing: Vec<&str> = trace
+            .tried
+            .iter()
+            .filter(|(_, rxing)| *rxing)
+            .map(|(name, _)| *name)
+            .collect();
+        assert_eq!(rxing, ["original", "otsu"]);
+    }
+
+    #[test]
+    fn test_time_limit() {
+        let options = ScanOptions {
+            time_limit: Some(Duration::ZERO),
+            ..Default::default()
+        };
+        let mut trace = Trace::default();
+        let found = scan_traced(&photo(1920, 1080), &options, &mut trace);
+        assert!(matches!(found, Err(ScanError::TimeLimit)));
+        let tried: Vec<&str> = trace.tried.iter().map(|(name, _)| *name).collect();
+        assert_eq!(tried, ["original", "otsu"]);
+    }
+
+    // 耗时与机器有关，需要时以 `cargo test --release -- --ignored` 运行
+    #[test]
+    #[ignore]
+    fn bench_photo_without_code() {
+        let started = Instant::now();
+        assert!(scan(&photo(1920, 1080), &ScanOptions::default()).is_err());
+        let elapsed = started.elapsed();
+        println!("1920x1080 without code: {:?}", elapsed);
+        assert!(elapsed < Duration::from_secs(5));
+    }
+}
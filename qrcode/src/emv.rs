@@ -0,0 +1,297 @@
+use serde::Serialize;
+
+/// EMVCo 商户主扫支付码（Merchant-Presented Mode）
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct EmvPayment {
+    pub payload_format: String,
+    pub initiation: Option<&'static str>,
+    pub merchant_accounts: Vec<MerchantAccount>,
+    pub merchant_category_code: Option<String>,
+    pub currency: Option<String>,
+    pub currency_code: Option<&'static str>,
+    pub amount: Option<String>,
+    pub tip_indicator: Option<String>,
+    pub convenience_fee_fixed: Option<String>,
+    pub convenience_fee_percentage: Option<String>,
+    pub country: Option<String>,
+    pub merchant_name: Option<String>,
+    pub merchant_city: Option<String>,
+    pub postal_code: Option<String>,
+    pub additional_data: Vec<EmvField>,
+    pub language_template: Vec<EmvField>,
+    pub unreserved_templates: Vec<EmvField>,
+    pub crc: String,
+    pub crc_expected: String,
+    pub crc_valid: bool,
+}
+
+/// 商户账户信息（ID 02-51）
+#[derive(Debug, PartialEq, Serialize)]
+pub struct MerchantAccount {
+    pub id: String,
+    pub network: Option<&'static str>,
+    pub globally_unique_id: Option<String>,
+    pub value: Option<String>,
+    pub fields: Vec<EmvField>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct EmvField {
+    pub id: String,
+    pub name: Option<&'static str>,
+    pub value: String,
+    pub fields: Vec<EmvField>,
+}
+
+// 商户账户信息中的网络标识，ID 02-25 为 EMVCo 分配的卡组织
+const NETWORKS: &[(u8, u8, &str)] = &[
+    (2, 3, "Visa"),
+    (4, 5, "Mastercard"),
+    (6, 8, "EMVCo"),
+    (9, 10, "Discover"),
+    (11, 12, "Amex"),
+    (13, 14, "JCB"),
+    (15, 16, "UnionPay"),
+    (17, 25, "EMVCo"),
+];
+
+// ID 26-51 模板中子字段 00 的全局唯一标识
+const GLOBAL_IDS: &[(&str, &str)] = &[
+    ("br.gov.bcb.pix", "PIX"),
+    ("A000000677010111", "PromptPay"),
+    ("A000000677010112", "PromptPay"),
+    ("A000000677010113", "PromptPay"),
+    ("A000000677010114", "PromptPay"),
+    ("SG.PAYNOW", "PayNow"),
+    ("SG.COM.NETS", "NETS"),
+    ("ID.CO.QRIS.WWW", "QRIS"),
+    ("hk.com.hkicl", "FPS"),
+    ("com.p2pqrpay", "QR Ph"),
+    ("A000000727", "NAPAS"),
+    ("A0000006150001", "DuitNow"),
+    ("A000000615000101", "DuitNow"),
+    ("A000000333010101", "UnionPay"),
+    ("A000000333010102", "UnionPay"),
+];
+
+const CURRENCIES: &[(&str, &str)] = &[
+    ("036", "AUD"),
+    ("124", "CAD"),
+    ("156", "CNY"),
+    ("344", "HKD"),
+    ("356", "INR"),
+    ("360", "IDR"),
+    ("392", "JPY"),
+    ("410", "KRW"),
+    ("446", "MOP"),
+    ("458", "MYR"),
+    ("608", "PHP"),
+    ("702", "SGD"),
+    ("704", "VND"),
+    ("764", "THB"),
+    ("826", "GBP"),
+    ("840", "USD"),
+    ("901", "TWD"),
+    ("978", "EUR"),
+    ("986", "BRL"),
+];
+
+const ADDITIONAL_DATA_NAMES: &[(&str, &str)] = &[
+    ("01", "bill_number"),
+    ("02", "mobile_number"),
+    ("03", "store_label"),
+    ("04", "loyalty_number"),
+    ("05", "reference_label"),
+    ("06", "customer_label"),
+    ("07", "terminal_label"),
+    ("08", "purpose_of_transaction"),
+    ("09", "additional_consumer_data_request"),
+    ("10", "merchant_tax_id"),
+    ("11", "merchant_channel"),
+];
+
+const LANGUAGE_TEMPLATE_NAMES: &[(&str, &str)] = &[
+    ("00", "language_preference"),
+    ("01", "merchant_name"),
+    ("02", "merchant_city"),
+];
+
+/// 按 EMVCo MPM 规范解析 `ID(2) + 长度(2) + 值` 结构，不是合法支付码时返回 None
+pub fn parse(content: &str) -> Option<EmvPayment> {
+    if !content.starts_with("000201") {
+        return None;
+    }
+    let tlvs = parse_tlv(content)?;
+    let (crc_id, crc) = tlvs.last()?;
+    if *crc_id != "63" || crc.len() != 4 {
+        return None;
+    }
+
+    // 校验范围为 CRC 值之前的全部内容，包括 `6304`
+    let checked = &content[..content.len() - 4];
+    let crc_expected = format!("{:04X}", crc16(checked.as_bytes()));
+
+    let mut payment = EmvPayment {
+        crc: crc.to_string(),
+        crc_valid: crc.eq_ignore_ascii_case(&crc_expected),
+        crc_expected,
+        ..Default::default()
+    };
+    for (id, value) in tlvs {
+        let num: u8 = id.parse().ok()?;
+        let text = Some(value.to_string());
+        match num {
+            0 => payment.payload_format = value.to_string(),
+            1 => {
+                payment.initiation = match value {
+                    "11" => Some("static"),
+                    "12" => Some("dynamic"),
+                    _ => None,
+                }
+            }
+            2..=51 => payment.merchant_accounts.push(merchant_account(id, num, value)),
+            52 => payment.merchant_category_code = text,
+            53 => {
+                payment.currency_code = lookup(CURRENCIES, value);
+                payment.currency = text;
+            }
+            54 => payment.amount = text,
+            55 => payment.tip_indicator = text,
+            56 => payment.convenience_fee_fixed = text,
+            57 => payment.convenience_fee_percentage = text,
+            58 => payment.country = text,
+            59 => payment.merchant_name = text,
+            60 => payment.merchant_city = text,
+            61 => payment.postal_code = text,
+            62 => payment.additional_data = template_fields(value, ADDITIONAL_DATA_NAMES),
+            64 => payment.language_template = template_fields(value, LANGUAGE_TEMPLATE_NAMES),
+            80..=99 => payment.unreserved_templates.push(EmvField {
+                id: id.to_string(),
+                name: None,
+                value: value.to_string(),
+                fields: template_fields(value, &[]),
+            }),
+            _ => {}
+        }
+    }
+    Some(payment)
+}
+
+fn lookup(table: &[(&str, &'static str)], key: &str) -> Option<&'static str> {
+    table.iter().find(|(k, _)| k.eq_ignore_ascii_case(key)).map(|(_, v)| *v)
+}
+
+// 长度按字符计算，商户名等字段可能包含非 ASCII 字符
+fn parse_tlv(data: &str) -> Option<Vec<(&str, &str)>> {
+    let mut tlvs = Vec::new();
+    let mut rest = data;
+    while !rest.is_empty() {
+        let id = rest.get(..2)?;
+        let len = rest.get(2..4)?;
+        // 长度必须是两位数字，str::parse 还会接受 "+5" 这样的写法
+        if !id.bytes().all(|b| b.is_ascii_digit()) || !len.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let len: usize = len.parse().ok()?;
+        let body = &rest[4..];
+        let end = body.char_indices().nth(len).map(|(i, _)| i).unwrap_or(body.len());
+        if body[..end].chars().count() != len {
+            return None;
+        }
+        tlvs.push((id, &body[..end]));
+        rest = &body[end..];
+    }
+    Some(tlvs)
+}
+
+fn merchant_account(id: &str, num: u8, value: &str) -> MerchantAccount {
+    // ID 02-25 为卡组织的原始值，26-51 为包含子字段的模板
+    if num < 26 {
+        return MerchantAccount {
+            id: id.to_string(),
+            network: NETWORKS
+                .iter()
+                .find(|(lo, hi, _)| (*lo..=*hi).contains(&num))
+                .map(|(_, _, name)| *name),
+            globally_unique_id: None,
+            value: Some(value.to_string()),
+            fields: Vec::new(),
+        };
+    }
+
+    let fields = template_fields(value, &[("00", "globally_unique_id")]);
+    let globally_unique_id = fields.iter().find(|f| f.id == "00").map(|f| f.value.clone());
+    MerchantAccount {
+        id: id.to_string(),
+        network: globally_unique_id.as_deref().and_then(|gui| lookup(GLOBAL_IDS, gui)),
+        globally_unique_id,
+        value: if fields.is_empty() {
+            Some(value.to_string())
+        } else {
+            None
+        },
+        fields,
+    }
+}
+
+// 模板值无法按 TLV 解析时返回空列表
+fn template_fields(value: &str, names: &[(&str, &'static str)]) -> Vec<EmvField> {
+    parse_tlv(value)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(id, value)| EmvField {
+            id: id.to_string(),
+            name: lookup(names, id),
+            value: value.to_string(),
+            fields: Vec::new(),
+        })
+        .collect()
+}
+
+/// CRC-16/CCITT-FALSE：多项式 0x1021，初始值 0xFFFF
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xffff;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PIX: &str = "00020126580014br.gov.bcb.pix0136123e4567-e12b-12d1-a456-4266554400005204000053039865802BR5913Fulano de Tal6008BRASILIA62070503***63041D3D";
+
+    #[test]
+    fn test_pix() {
+        let payment = parse(PIX).unwrap();
+        assert!(payment.crc_valid);
+        assert_eq!(payment.currency_code, Some("BRL"));
+        assert_eq!(payment.merchant_name.as_deref(), Some("Fulano de Tal"));
+        assert_eq!(payment.merchant_accounts[0].network, Some("PIX"));
+        assert_eq!(
+            payment.merchant_accounts[0].fields[1].value,
+            "123e4567-e12b-12d1-a456-426655440000"
+        );
+        assert_eq!(payment.additional_data[0].name, Some("reference_label"));
+    }
+
+    #[test]
+    fn test_bad_crc() {
+        let tampered = PIX.replace("Fulano", "Fulana");
+        let payment = parse(&tampered).unwrap();
+        assert!(!payment.crc_valid);
+        assert_eq!(payment.crc, "1D3D");
+        assert!(parse("00020101021").is_none());
+        assert_eq!(parse_tlv("00020101+5abcde"), None);
+        assert_eq!(parse_tlv("000201"), Some(vec![("00", "01")]));
+    }
+}
@@ -0,0 +1,416 @@
+use std::error::Error;
+use std::fs;
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+
+use clap::{Args, ValueEnum};
+use image::{Rgba, RgbaImage};
+use qrcode::{EcLevel, QrCode, Version};
+
+use crate::compose::Compose;
+use crate::decode::{self, ScanOptions};
+use crate::render::{self, Backdrop, Logo, Matrix, Style};
+use crate::rmqr;
+use crate::symbology::Symbology;
+
+// 徽标遮挡超过该比例时要求使用 H 级纠错
+const LOGO_ECC_H_COVERAGE: f32 = 0.05;
+
+#[derive(Args)]
+pub struct EncodeArgs {
+    #[command(subcommand)]
+    compose: Option<Compose>,
+
+    /// Text to encode; reads from --input or stdin when omitted
+    text: Option<String>,
+
+    /// Read the payload bytes from a file ("-" for stdin)
+    #[arg(short, long, conflicts_with = "text")]
+    input: Option<PathBuf>,
+
+    /// Output file; writes to stdout when omitted
+    #[arg(short, long, global = true)]
+    output: Option<PathBuf>,
+
+    /// Output format; guessed from the output file extension when omitted
+    #[arg(short, long, value_enum, global = true)]
+    format: Option<Format>,
+
+    /// Error correction level [default: m, or h with --logo]
+    #[arg(short, long, value_enum, global = true)]
+    ecc: Option<Ecc>,
+
+    /// Symbol type
+    #[arg(long, value_enum, default_value_t = Symbol::Qr, global = true)]
+    symbol: Symbol,
+
+    /// Symbol version (QR 1-40, Micro QR 1-4 for M1-M4, rMQR 1-32 for R7x43-R17x139);
+    /// the smallest version that fits when omitted
+    #[arg(short, long, value_parser = clap::value_parser!(i16).range(1..=40), global = true)]
+    version: Option<i16>,
+
+    /// Quiet zone width in modules
+    #[arg(long, default_value_t = 4, value_parser = clap::value_parser!(u32).range(0..=100), global = true)]
+    quiet_zone: u32,
+
+    /// Module size in pixels (PNG) or points (SVG, EPS, PDF)
+    #[arg(long, default_value_t = 8, value_parser = clap::value_parser!(u32).range(1..=100), global = true)]
+    module_size: u32,
+
+    /// Foreground colour (#RRGGBB or #RRGGBBAA)
+    #[arg(long, default_value = "#000000", value_parser = parse_color, global = true)]
+    foreground: Rgba<u8>,
+
+    /// Background colour (#RRGGBB or #RRGGBBAA)
+    #[arg(long, default_value = "#ffffff", value_parser = parse_color, global = true)]
+    background: Rgba<u8>,
+
+    /// Logo image placed at the centre of the symbol (PNG and SVG output only)
+    #[arg(long, global = true)]
+    logo: Option<PathBuf>,
+
+    /// Logo size as a fraction of the symbol width
+    #[arg(long, default_value_t = 0.2, value_parser = parse_ratio, requires = "logo", global = true)]
+    logo_ratio: f32,
+
+    /// Blank margin around the logo in modules
+    #[arg(long, default_value_t = 1.0, requires = "logo", global = true)]
+    logo_padding: f32,
+
+    /// Backdrop drawn behind the logo in the background colour
+    #[arg(long, value_enum, default_value_t = Backdrop::Square, requires = "logo", global = true)]
+    logo_backdrop: Backdrop,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum Format {
+    Png,
+    Svg,
+    Eps,
+    Pdf,
+    /// Unicode half blocks for terminals
+    Unicode,
+    /// Unicode half blocks with 24-bit ANSI colours
+    Ansi,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum Symbol {
+    Qr,
+    /// Micro QR (M1-M4), error correction l, m or q
+    MicroQr,
+    /// Rectangular Micro QR, error correction m or h
+    Rmqr,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum Ecc {
+    L,
+    M,
+    Q,
+    H,
+}
+
+impl From<Ecc> for EcLevel {
+    fn from(ecc: Ecc) -> Self {
+        match ecc {
+            Ecc::L => EcLevel::L,
+            Ecc::M => EcLevel::M,
+            Ecc::Q => EcLevel::Q,
+            Ecc::H => EcLevel::H,
+        }
+    }
+}
+
+fn parse_color(s: &str) -> Result<Rgba<u8>, String> {
+    let hex = s.trim_start_matches('#');
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|e| format!("{}: {}", s, e));
+    match hex.len() {
+        6 if hex.is_ascii() => Ok(Rgba([channel(0)?, channel(2)?, channel(4)?, 255])),
+        8 if hex.is_ascii() => Ok(Rgba([channel(0)?, channel(2)?, channel(4)?, channel(6)?])),
+        _ => Err(format!("invalid colour '{}', expected #RRGGBB or #RRGGBBAA", s)),
+    }
+}
+
+fn parse_ratio(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(r) if r > 0.0 && r <= 0.4 => Ok(r),
+        _ => Err(format!("invalid ratio '{}', expected a number in (0, 0.4]", s)),
+    }
+}
+
+fn guess_format(output: Option<&Path>) -> Format {
+    let ext = output
+        .and_then(|p| p.extension())
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+    match ext.as_deref() {
+        Some("png") => Format::Png,
+        Some("svg") => Format::Svg,
+        Some("eps") | Some("ps") => Format::Eps,
+        Some("pdf") => Format::Pdf,
+        Some(_) => Format::Unicode,
+        None if output.is_some() => Format::Png,
+        None => Format::Unicode,
+    }
+}
+
+// 优先使用结构化内容和命令行文本，其次是 --input 指定的文件，最后读取标准输入
+fn read_payload(args: &EncodeArgs) -> Result<Vec<u8>, Box<dyn Error>> {
+    if let Some(compose) = &args.compose {
+        if args.text.is_some() || args.input.is_some() {
+            return Err("text and --input can not be combined with a payload subcommand".into());
+        }
+        return Ok(compose.build()?.into_bytes());
+    }
+    if let Some(text) = &args.text {
+        return Ok(text.as_bytes().to_vec());
+    }
+    match &args.input {
+        Some(path) if path.as_os_str() != "-" => Ok(fs::read(path)?),
+        _ => {
+            let mut stdin = io::stdin();
+            if args.input.is_none() && stdin.is_terminal() {
+                return Err("no text to encode".into());
+            }
+            let mut data = Vec::new();
+            stdin.read_to_end(&mut data)?;
+            Ok(data)
+        }
+    }
+}
+
+pub fn build_code(data: &[u8], ecc: EcLevel, version: Option<i16>) -> qrcode::QrResult<QrCode> {
+    match version {
+        Some(v) => QrCode::with_version(data, Version::Normal(v), ecc),
+        None => QrCode::with_error_correction_level(data, ecc),
+    }
+}
+
+/// 按符号类型生成模块矩阵，Micro QR 未指定版本时选择可容纳数据的最小版本
+pub fn build_matrix(data: &[u8], symbol: Symbol, ecc: EcLevel, version: Option<i16>) -> Result<Matrix, String> {
+    let too_long = |e| format!("can not encode {} bytes: {}", data.len(), e);
+    match symbol {
+        Symbol::Qr => build_code(data, ecc, version)
+            .map(|code| Matrix::from_code(&code))
+            .map_err(too_long),
+        Symbol::MicroQr => {
+            if ecc == EcLevel::H {
+                return Err("micro qr supports error correction levels l, m and q".to_string());
+            }
+            let versions = match version {
+                Some(v @ 1..=4) => v..=v,
+                Some(v) => return Err(format!("invalid micro qr version {}, expected 1-4", v)),
+                None => 1..=4,
+            };
+            let mut last = None;
+            for v in versions {
+                // qrcode 库把 M3-L 的最后一个数据码字按整字节摆放，生成的符号无法识别
+                if v == 3 && ecc == EcLevel::L {
+                    if version.is_some() {
+                        return Err("micro qr version 3 does not support error correction level l".to_string());
+                    }
+                    continue;
+                }
+                match QrCode::with_version(data, Version::Micro(v), ecc) {
+                    Ok(code) => return Ok(Matrix::from_code(&code)),
+                    Err(e) => last = Some(e),
+                }
+            }
+            Err(too_long(last.expect("at least one micro qr version tried")))
+        }
+        Symbol::Rmqr => rmqr::encode(data, ecc, version),
+    }
+}
+
+pub fn run(args: EncodeArgs) -> Result<(), Box<dyn Error>> {
+    let data = read_payload(&args)?;
+    let format = args.format.unwrap_or_else(|| guess_format(args.output.as_deref()));
+    let logo = match &args.logo {
+        Some(_) if !matches!(format, Format::Png | Format::Svg) => {
+            return Err("logo overlay requires png or svg output".into());
+        }
+        Some(_) if args.symbol != Symbol::Qr => {
+            return Err("logo overlay requires a qr symbol".into());
+        }
+        Some(path) => Some(Logo {
+            image: image::open(path)
+                .map_err(|e| format!("can not open logo: {} {}", path.display(), e))?
+                .to_rgba8(),
+            ratio: args.logo_ratio,
+            padding: args.logo_padding,
+            backdrop: args.logo_backdrop,
+        }),
+        None => None,
+    };
+
+    let ecc = args.ecc.unwrap_or(if logo.is_some() { Ecc::H } else { Ecc::M });
+    let matrix = build_matrix(&data, args.symbol, ecc.into(), args.version)?;
+    let style = Style {
+        quiet_zone: args.quiet_zone,
+        module_size: args.module_size,
+        foreground: args.foreground,
+        background: args.background,
+    };
+    if style.size(&matrix).is_none() {
+        return Err("the output is too large, try a smaller --module-size or --quiet-zone".into());
+    }
+
+    if let Some(logo) = &logo {
+        let coverage = logo.coverage(&matrix);
+        if coverage > LOGO_ECC_H_COVERAGE && ecc != Ecc::H {
+            return Err(format!(
+                "logo covers {:.1}% of the symbol, error correction level h is required",
+                coverage * 100.0
+            )
+            .into());
+        }
+    }
+
+    let image = match format {
+        Format::Png => Some(render::render_image(&matrix, &style, logo.as_ref())),
+        _ => None,
+    };
+    // 叠加徽标后用解码流程自检，无法识别时拒绝输出
+    if let Some(logo) = &logo {
+        let check = match &image {
+            Some(image) => image.clone(),
+            None => render::render_image(
+                &matrix,
+                &Style {
+                    module_size: 8,
+                    ..style
+                },
+                Some(logo),
+            ),
+        };
+        if !scans_as(&check, &data) {
+            return Err("the code does not scan with this logo, try a smaller --logo-ratio".into());
+        }
+    }
+
+    let bytes = match format {
+        Format::Png => render::encode_png(image.as_ref().expect("png rendered above"))?,
+        Format::Svg => render::render_svg(&matrix, &style, logo.as_ref())?.into_bytes(),
+        Format::Eps => render::render_eps(&matrix, &style).into_bytes(),
+        Format::Pdf => render::render_pdf(&matrix, &style),
+        Format::Unicode => render::render_unicode(&matrix, &style).into_bytes(),
+        Format::Ansi => render::render_ansi(&matrix, &style).into_bytes(),
+    };
+
+    match &args.output {
+        Some(path) => fs::write(path, bytes)?,
+        None => io::stdout().write_all(&bytes)?,
+    }
+    Ok(())
+}
+
+// 透明背景按白色合成后再转灰度；只用原图和 Otsu 两种策略，普通扫码器未必有更多的预处理手段
+fn scans_as(img: &RgbaImage, data: &[u8]) -> bool {
+    let mut flat = RgbaImage::from_pixel(img.width(), img.height(), Rgba([255, 255, 255, 255]));
+    image::imageops::overlay(&mut flat, img, 0, 0);
+    let gray = image::DynamicImage::ImageRgba8(flat).to_luma8();
+    let options = ScanOptions {
+        symbologies: vec![Symbology::Qr],
+        quick: true,
+        ..Default::default()
+    };
+    decode::scan(&gray, &options).is_ok_and(|results| results.iter().any(|r| r.stream.bytes() == data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(parse_color("#112233").unwrap(), Rgba([0x11, 0x22, 0x33, 255]));
+        assert_eq!(parse_color("11223380").unwrap(), Rgba([0x11, 0x22, 0x33, 0x80]));
+        assert!(parse_color("#12345").is_err());
+        assert!(parse_color("#gg0000").is_err());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let code = build_code(b"hello world", EcLevel::Q, Some(3)).unwrap();
+        let style = Style {
+            quiet_zone: 4,
+            module_size: 4,
+            foreground: Rgba([0, 0, 0, 255]),
+            background: Rgba([255, 255, 255, 255]),
+        };
+        let img =
+            image::DynamicImage::ImageRgba8(render::render_image(&Matrix::from_code(&code), &style, None)).to_luma8();
+        let results = crate::decode::scan(&img, &ScanOptions::default()).ok().unwrap();
+        assert_eq!(results[0].content, "hello world");
+    }
+
+    #[test]
+    fn test_output_size() {
+        let matrix = build_matrix(b"hi", Symbol::Qr, EcLevel::M, None).unwrap();
+        let mut style = Style {
+            quiet_zone: 4,
+            module_size: 8,
+            foreground: Rgba([0, 0, 0, 255]),
+            background: Rgba([255, 255, 255, 255]),
+        };
+        assert_eq!(style.size(&matrix), Some((232, 232)));
+        style.quiet_zone = 4_000_000_000;
+        assert_eq!(style.size(&matrix), None);
+
+        #[derive(clap::Parser)]
+        struct Cli {
+            #[command(flatten)]
+            args: EncodeArgs,
+        }
+        use clap::Parser;
+        assert!(Cli::try_parse_from(["qrcode", "hi", "--quiet-zone", "100"]).is_ok());
+        assert!(Cli::try_parse_from(["qrcode", "hi", "--quiet-zone", "4000000000"]).is_err());
+    }
+
+    #[test]
+    fn test_micro_qr() {
+        let matrix = build_matrix(b"LABEL-7", Symbol::MicroQr, EcLevel::L, None).unwrap();
+        assert_eq!(matrix.width, 17);
+        let style = Style {
+            quiet_zone: 2,
+            module_size: 6,
+            foreground: Rgba([0, 0, 0, 255]),
+            background: Rgba([255, 255, 255, 255]),
+        };
+        let img = image::DynamicImage::ImageRgba8(render::render_image(&matrix, &style, None)).to_luma8();
+        let results = crate::decode::scan(&img, &ScanOptions::default()).ok().unwrap();
+        assert_eq!(results[0].symbology, Symbology::MicroQr);
+        assert_eq!(results[0].content, "LABEL-7");
+
+        assert!(build_matrix(b"1", Symbol::MicroQr, EcLevel::H, None).is_err());
+        assert!(build_matrix(b"1", Symbol::MicroQr, EcLevel::M, Some(5)).is_err());
+        assert!(build_matrix(b"1", Symbol::MicroQr, EcLevel::L, Some(3)).is_err());
+        assert!(build_matrix(&[b'a'; 40], Symbol::MicroQr, EcLevel::L, None).is_err());
+    }
+
+    #[test]
+    fn test_logo_self_check() {
+        let data = b"https://example.com/logo";
+        let code = build_code(data, EcLevel::H, None).unwrap();
+        let matrix = Matrix::from_code(&code);
+        let style = Style {
+            quiet_zone: 4,
+            module_size: 6,
+            foreground: Rgba([0, 0, 0, 255]),
+            background: Rgba([255, 255, 255, 255]),
+        };
+        let mut logo = Logo {
+            image: RgbaImage::from_pixel(10, 10, Rgba([200, 0, 0, 255])),
+            ratio: 0.2,
+            padding: 1.0,
+            backdrop: Backdrop::Rounded,
+        };
+        assert!(logo.coverage(&matrix) > LOGO_ECC_H_COVERAGE);
+        assert!(scans_as(&render::render_image(&matrix, &style, Some(&logo)), data));
+
+        logo.ratio = 0.4;
+        logo.padding = 4.0;
+        assert!(!scans_as(&render::render_image(&matrix, &style, Some(&logo)), data));
+    }
+}
@@ -0,0 +1,147 @@
+use std::io::Cursor;
+
+use image::codecs::gif::GifDecoder;
+use image::codecs::png::PngDecoder;
+use image::codecs::webp::WebPDecoder;
+use image::error::{DecodingError, ImageFormatHint};
+use image::{AnimationDecoder, DynamicImage, ImageBuffer, ImageError, ImageFormat, ImageResult};
+use tiff::ColorType;
+use tiff::decoder::{Decoder, DecodingResult};
+
+use crate::vector::{self, Render};
+
+/// 解码图片的所有帧：GIF/APNG/WebP 动画逐帧展开，多页 TIFF 及 PDF 逐页展开，其余格式只有一帧
+pub fn load(bytes: &[u8], render: &Render) -> ImageResult<Vec<DynamicImage>> {
+    // image 不识别矢量格式，先按文件头判断
+    if vector::is_pdf(bytes) {
+        return vector::render_pdf(bytes, render);
+    }
+    if vector::is_svg(bytes) {
+        return Ok(vec![vector::render_svg(bytes, render)?]);
+    }
+
+    match image::guess_format(bytes)? {
+        ImageFormat::Gif => collect(GifDecoder::new(Cursor::new(bytes))?),
+        ImageFormat::Png => {
+            let decoder = PngDecoder::new(Cursor::new(bytes))?;
+            if decoder.is_apng()? {
+                collect(decoder.apng()?)
+            } else {
+                Ok(vec![DynamicImage::from_decoder(decoder)?])
+            }
+        }
+        ImageFormat::WebP => {
+            let decoder = WebPDecoder::new(Cursor::new(bytes))?;
+            if decoder.has_animation() {
+                collect(decoder)
+            } else {
+                Ok(vec![DynamicImage::from_decoder(decoder)?])
+            }
+        }
+        ImageFormat::Tiff => load_tiff(bytes),
+        // 默认构建没有 AVIF 解码器，错误信息中直接说明原因及解决办法，不只写在 --help 里
+        #[cfg(not(feature = "avif"))]
+        ImageFormat::Avif => Err(ImageError::Decoding(DecodingError::new(
+            ImageFormatHint::Exact(ImageFormat::Avif),
+            "this build has no AVIF decoder, install the dav1d library and rebuild with `--features avif`",
+        ))),
+        _ => Ok(vec![image::load_from_memory(bytes)?]),
+    }
+}
+
+// 动画帧已合成到完整画布上
+fn collect<'a>(decoder: impl AnimationDecoder<'a>) -> ImageResult<Vec<DynamicImage>> {
+    decoder
+        .into_frames()
+        .map(|frame| frame.map(|frame| DynamicImage::ImageRgba8(frame.into_buffer())))
+        .collect()
+}
+
+// image 只读取 TIFF 的第一页，多页时直接使用 tiff 解码
+fn load_tiff(bytes: &[u8]) -> ImageResult<Vec<DynamicImage>> {
+    let mut decoder = Decoder::new(Cursor::new(bytes)).map_err(tiff_error)?;
+    let mut pages = Vec::new();
+    loop {
+        pages.push(read_page(&mut decoder)?);
+        if !decoder.more_images() {
+            return Ok(pages);
+        }
+        decoder.next_image().map_err(tiff_error)?;
+    }
+}
+
+fn read_page(decoder: &mut Decoder<Cursor<&[u8]>>) -> ImageResult<DynamicImage> {
+    let (width, height) = decoder.dimensions().map_err(tiff_error)?;
+    let color = decoder.colortype().map_err(tiff_error)?;
+    let page = match (color, decoder.read_image().map_err(tiff_error)?) {
+        (ColorType::Gray(8), DecodingResult::U8(data)) => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageLuma8)
+        }
+        (ColorType::GrayA(8), DecodingResult::U8(data)) => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageLumaA8)
+        }
+        (ColorType::RGB(8), DecodingResult::U8(data)) => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgb8)
+        }
+        (ColorType::RGBA(8), DecodingResult::U8(data)) => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgba8)
+        }
+        (ColorType::Gray(16), DecodingResult::U16(data)) => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageLuma16)
+        }
+        (ColorType::RGB(16), DecodingResult::U16(data)) => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgb16)
+        }
+        (ColorType::RGBA(16), DecodingResult::U16(data)) => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgba16)
+        }
+        (color, _) => return Err(tiff_error(format!("unsupported tiff color type {:?}", color))),
+    };
+    page.ok_or_else(|| tiff_error("tiff page size does not match its dimensions"))
+}
+
+fn tiff_error(e: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> ImageError {
+    ImageError::Decoding(DecodingError::new(ImageFormatHint::Exact(ImageFormat::Tiff), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::codecs::gif::GifEncoder;
+    use image::{Delay, Frame, Rgba, RgbaImage};
+    use tiff::encoder::{TiffEncoder, colortype};
+
+    use super::*;
+
+    #[test]
+    fn test_gif_frames() {
+        let mut bytes = Vec::new();
+        {
+            let mut encoder = GifEncoder::new(&mut bytes);
+            for shade in [0, 128, 255] {
+                let img = RgbaImage::from_pixel(4, 4, Rgba([shade, shade, shade, 255]));
+                encoder
+                    .encode_frame(Frame::from_parts(img, 0, 0, Delay::from_numer_denom_ms(100, 1)))
+                    .unwrap();
+            }
+        }
+        let frames = load(&bytes, &Render::default()).unwrap();
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[1].to_luma8().get_pixel(0, 0).0, [128]);
+    }
+
+    #[test]
+    fn test_tiff_pages() {
+        let mut bytes = Cursor::new(Vec::new());
+        {
+            let mut encoder = TiffEncoder::new(&mut bytes).unwrap();
+            encoder.write_image::<colortype::Gray8>(2, 2, &[0; 4]).unwrap();
+            encoder.write_image::<colortype::RGB8>(3, 1, &[255; 9]).unwrap();
+        }
+        let pages = load(bytes.get_ref(), &Render::default()).unwrap();
+        assert_eq!(pages.len(), 2);
+        assert_eq!((pages[1].width(), pages[1].height()), (3, 1));
+        assert_eq!(pages[1].to_luma8().get_pixel(2, 0).0, [255]);
+    }
+}
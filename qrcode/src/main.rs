@@ -1,42 +1,383 @@
-use std::env;
+mod annotate;
+mod batch;
+mod bitstream;
+mod compose;
+mod decode;
+mod emv;
+mod encode;
+mod frames;
+mod otpauth;
+mod payload;
+mod preprocess;
+mod render;
+mod rmqr;
+mod safety;
+mod sequence;
+mod serve;
+mod source;
+mod symbology;
+mod vector;
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 use std::process;
+use std::time::Duration;
 
-use rqrr::PreparedImage;
+use clap::{Args, Parser, Subcommand};
+use encoding_rs::Encoding;
+use image::DynamicImage;
+use rayon::prelude::*;
+use serde_json::json;
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
+use crate::batch::{Failure, Filter, Scanned};
+use crate::decode::{Crop, Decoded, ScanError, ScanOptions, Trace};
+use crate::encode::EncodeArgs;
+use crate::safety::{Rules, Severity};
+use crate::serve::ServeArgs;
+use crate::symbology::Symbology;
+use crate::vector::{Pages, Render};
 
-    if args.len() != 2 {
-        eprintln!("Usage: {} <qrcode image path>", args[0]);
-        process::exit(1);
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    decode: DecodeArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Generate a QR code
+    Encode(Box<EncodeArgs>),
+    /// Decode frames streamed on stdin, answering each with a JSON line
+    Serve(ServeArgs),
+}
+
+#[derive(Args)]
+struct DecodeArgs {
+    /// Images to decode: file paths, directories (scanned recursively), "-" for stdin or data: URIs;
+    /// Structured Append parts are combined across images; AVIF needs a build with `--features avif`
+    #[arg(required = true)]
+    paths: Vec<PathBuf>,
+
+    /// Only scan files in directories matching this glob (repeatable) [default: common image extensions]
+    #[arg(long, value_name = "GLOB")]
+    include: Vec<String>,
+
+    /// Skip files in directories matching this glob (repeatable)
+    #[arg(long, value_name = "GLOB")]
+    exclude: Vec<String>,
+
+    /// Treat file and stdin contents as base64 text (a data: URI is also accepted)
+    #[arg(long)]
+    base64: bool,
+
+    /// Print results as JSON, including the preprocessing strategy that succeeded
+    #[arg(long)]
+    json: bool,
+
+    /// Draw detected QR grids, finder patterns and result indices onto a copy of the image (single input only)
+    #[arg(long, value_name = "FILE", conflicts_with = "ndjson")]
+    annotate: Option<PathBuf>,
+
+    /// Write the thresholded image the QR detector worked on (single input only)
+    #[arg(long, value_name = "FILE", conflicts_with = "ndjson")]
+    threshold_image: Option<PathBuf>,
+
+    /// Print one JSON line per image frame as soon as it is scanned, errors included
+    #[arg(long, conflicts_with_all = ["json", "raw"])]
+    ndjson: bool,
+
+    /// Print the current TOTP code of otpauth payloads
+    #[arg(long)]
+    totp: bool,
+
+    /// Show OTP secrets instead of redacting them
+    #[arg(long)]
+    show_secrets: bool,
+
+    /// Write the exact payload bytes to FILE ("-" for stdout) instead of printing text; OTP payloads need --show-secrets
+    #[arg(long, value_name = "FILE")]
+    raw: Option<PathBuf>,
+
+    /// Decode byte content with this charset (e.g. gbk, shift_jis), ignoring ECI and detection
+    #[arg(long, value_parser = parse_charset)]
+    charset: Option<&'static Encoding>,
+
+    /// Only look for these symbologies (comma separated), all of them by default
+    #[arg(long, value_enum, value_delimiter = ',')]
+    symbologies: Vec<Symbology>,
+
+    /// Only scan this region of each image, in pixels
+    #[arg(long, value_name = "X,Y,W,H")]
+    crop: Option<Crop>,
+
+    /// Scan large images at full resolution instead of trying downscaled copies first
+    #[arg(long)]
+    no_pyramid: bool,
+
+    /// Stop trying further preprocessing strategies on an image after this many seconds, 0 for no limit
+    #[arg(long, value_name = "SECONDS", default_value_t = 10)]
+    time_limit: u64,
+
+    /// Only render these PDF pages, e.g. "1,3-5" [default: all pages]
+    #[arg(long, value_name = "PAGES")]
+    pages: Option<Pages>,
+
+    /// Resolution for rendering SVG and PDF input [default: 200]
+    #[arg(long)]
+    dpi: Option<f32>,
+
+    /// Load URL safety rules from this JSON file instead of the built-in ones
+    #[arg(long, value_name = "FILE")]
+    url_rules: Option<PathBuf>,
+
+    /// Report how long loading, grayscale conversion and each detection stage took
+    #[arg(long)]
+    timings: bool,
+}
+
+fn parse_charset(label: &str) -> Result<&'static Encoding, String> {
+    Encoding::for_label(label.as_bytes()).ok_or_else(|| format!("unknown charset '{}'", label))
+}
+
+fn main() {
+    let cli = Cli::parse();
+    match cli.command {
+        Some(Command::Encode(args)) => {
+            if let Err(e) = encode::run(*args) {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
+        }
+        Some(Command::Serve(args)) => {
+            let rules = load_rules(args.url_rules.as_deref());
+            if let Err(e) = serve::run(args, &rules) {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
+        }
+        None => decode_image(cli.decode),
     }
+}
 
-    let path = &args[1];
+// 加载网址安全检查规则，未指定文件时使用内置规则
+fn load_rules(path: Option<&Path>) -> Rules {
+    match path {
+        Some(path) => Rules::load(path).unwrap_or_else(|e| {
+            eprintln!("can not load url rules: {} {}", path.display(), e);
+            process::exit(1);
+        }),
+        None => Rules::builtin(),
+    }
+}
 
-    // 读取图片
-    let img = match image::open(path) {
-        Ok(img) => img,
+fn decode_image(args: DecodeArgs) {
+    let options = ScanOptions {
+        charset: args.charset,
+        symbologies: args.symbologies.clone(),
+        crop: args.crop,
+        pyramid: !args.no_pyramid,
+        quick: false,
+        time_limit: (args.time_limit > 0).then(|| Duration::from_secs(args.time_limit)),
+        render: Render {
+            pages: args.pages.clone(),
+            dpi: args.dpi,
+        },
+        buffers: None,
+    };
+    let filter = match Filter::new(&args.include, &args.exclude) {
+        Ok(filter) => filter,
         Err(e) => {
-            eprintln!("can not open image: {} {}", path, e);
+            eprintln!("invalid glob: {}", e);
             process::exit(1);
         }
     };
+    let rules = load_rules(args.url_rules.as_deref());
+    let inputs = batch::expand(&args.paths, &filter);
+    if args.ndjson {
+        return print_ndjson(&inputs, &args, &options, &rules);
+    }
 
-    let img = img.to_luma8();
-    let mut img = PreparedImage::prepare(img);
+    let debug = args.annotate.is_some() || args.threshold_image.is_some();
+    if debug && inputs.len() != 1 {
+        eprintln!("--annotate and --threshold-image require a single image");
+        process::exit(1);
+    }
+
+    let mut scanned: Vec<Vec<Scanned>> = inputs
+        .par_iter()
+        .map(|path| batch::scan_input(path, args.base64, &options, debug))
+        .collect();
+    let mut failed = false;
+    if args.timings {
+        for scanned in scanned.iter().flatten().filter(|s| !s.timings.is_empty()) {
+            eprintln!("{}", scanned.timings_report());
+        }
+    }
+    if let Some((img, trace)) = scanned.iter_mut().flatten().find_map(|s| s.trace.take()) {
+        failed |= !write_debug_images(&args, &img, &trace);
+    }
 
-    let grids = img.detect_grids();
-    if grids.is_empty() {
-        eprintln!("can not find qrcode in image: {}", path);
+    let mut results = Vec::new();
+    for frames in scanned {
+        match collect_frames(frames) {
+            Ok(found) => results.extend(found),
+            Err(message) => {
+                eprintln!("{}", message);
+                failed = true;
+            }
+        }
+    }
+    if results.is_empty() {
         process::exit(1);
     }
 
-    let (_, content) = match grids[0].decode() {
-        Ok(decoded) => decoded,
-        Err(e) => {
-            eprintln!("can not decode qrcode in image: {} {}", path, e);
+    let mut sequences = sequence::reassemble(&results, args.charset);
+    for sequence in &sequences {
+        let list = |parts: &[u8]| parts.iter().map(u8::to_string).collect::<Vec<_>>().join(", ");
+        if !sequence.is_complete() {
+            eprintln!(
+                "structured append {:02X} incomplete: found parts {} of {}, missing {}",
+                sequence.parity,
+                list(&sequence.found),
+                sequence.total,
+                list(&sequence.missing)
+            );
+        } else if sequence.parity_valid == Some(false) {
+            eprintln!("structured append {:02X} parity mismatch", sequence.parity);
+        }
+    }
+
+    let messages = sequences.iter_mut().filter_map(|s| s.message.as_mut());
+    for result in results.iter_mut().chain(messages) {
+        otpauth::protect(result, args.show_secrets, args.totp);
+        safety::inspect(result, &rules);
+    }
+
+    // 完整序列的符号以拼接后的消息代替，位置取第一个出现的符号
+    let mut outputs: Vec<&Decoded> = Vec::new();
+    let mut emitted = vec![false; sequences.len()];
+    for result in &results {
+        match sequences.iter().position(|s| s.is_complete() && s.contains(result)) {
+            Some(i) if !emitted[i] => {
+                emitted[i] = true;
+                outputs.extend(sequences[i].message.as_ref());
+            }
+            Some(_) => {}
+            None => outputs.push(result),
+        }
+    }
+
+    // 原始字节不经过字符集转换，无法隐藏密钥，含 OTP 密钥时须明确指定 --show-secrets
+    if let Some(raw) = &args.raw {
+        if !args.show_secrets && outputs.iter().any(|r| otpauth::holds_secret(&r.payload)) {
+            eprintln!("the payload contains OTP secrets, add --show-secrets to write it with --raw");
             process::exit(1);
         }
-    };
-    println!("{}", content);
+        let bytes: Vec<u8> = outputs.iter().flat_map(|r| r.stream.bytes()).collect();
+        let written = if raw.as_os_str() == "-" {
+            io::stdout().write_all(&bytes)
+        } else {
+            fs::write(raw, &bytes)
+        };
+        if let Err(e) = written {
+            eprintln!("can not write raw payload: {} {}", raw.display(), e);
+            process::exit(1);
+        }
+    } else if args.json {
+        if sequences.is_empty() {
+            println!("{}", json!({ "results": results }));
+        } else {
+            println!("{}", json!({ "results": results, "structured_append": sequences }));
+        }
+    } else {
+        for result in outputs {
+            println!("{}", result.content);
+            for (label, code) in otpauth::codes(&result.payload) {
+                println!("{}: {}", label, code);
+            }
+            // 警告输出到标准错误，不影响管道中的内容
+            let findings = result.safety.iter().flat_map(|r| &r.findings);
+            for finding in findings.filter(|f| f.severity > Severity::Low) {
+                eprintln!("warning: {} risk url: {}", finding.severity, finding.detail);
+            }
+        }
+    }
+
+    if failed {
+        process::exit(1);
+    }
+}
+
+// 写出标注图和二值化图像，失败时输出错误信息
+fn write_debug_images(args: &DecodeArgs, img: &DynamicImage, trace: &Trace) -> bool {
+    let mut ok = true;
+    if let Some(path) = &args.annotate
+        && let Err(e) = annotate::draw(img, trace).save(path)
+    {
+        eprintln!("can not write annotated image: {} {}", path.display(), e);
+        ok = false;
+    }
+    if let Some(path) = &args.threshold_image
+        && let Err(e) = annotate::write_threshold(trace, path)
+    {
+        eprintln!("can not write threshold image: {} {}", path.display(), e);
+        ok = false;
+    }
+    ok
+}
+
+// 合并同一输入各帧的结果，只要有一帧识别成功就不视为失败
+fn collect_frames(frames: Vec<Scanned>) -> Result<Vec<Decoded>, String> {
+    if let [single] = frames.as_slice()
+        && let Err(e) = &single.outcome
+    {
+        return Err(e.report(&single.location()));
+    }
+
+    let mut results = Vec::new();
+    let mut failure = None;
+    let mut file = String::new();
+    for scanned in frames {
+        file = scanned.file;
+        match scanned.outcome {
+            Ok(found) => results.extend(found),
+            // 解码错误比未找到更有参考价值
+            Err(e) => {
+                let decode_error = |f: &Failure| matches!(f, Failure::Scan(ScanError::Decode(_)));
+                if !failure.as_ref().is_some_and(decode_error) {
+                    failure = Some(e);
+                }
+            }
+        }
+    }
+    match failure {
+        Some(e) if results.is_empty() => Err(e.report(&file)),
+        _ => Ok(results),
+    }
+}
+
+// 每个输入扫描完成后立即输出，行的顺序与输入顺序无关
+fn print_ndjson(inputs: &[PathBuf], args: &DecodeArgs, options: &ScanOptions, rules: &Rules) {
+    inputs.par_iter().for_each(|path| {
+        let mut frames = batch::scan_input(path, args.base64, options, false);
+        let mut lines = String::new();
+        for scanned in &mut frames {
+            if let Ok(results) = &mut scanned.outcome {
+                for result in results {
+                    otpauth::protect(result, args.show_secrets, args.totp);
+                    safety::inspect(result, rules);
+                }
+            }
+            lines.push_str(&scanned.to_json(args.timings).to_string());
+            lines.push('\n');
+        }
+        // 整行写入，避免并行输出交错
+        let _ = io::stdout().lock().write_all(lines.as_bytes());
+    });
 }
@@ -0,0 +1,405 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use data_encoding::{BASE32_NOPAD, BASE64, BASE64_NOPAD};
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+use url::Url;
+
+use crate::decode::Decoded;
+use crate::payload::Payload;
+
+const REDACTED: &str = "REDACTED";
+
+/// `otpauth://` 中的一个账号
+#[derive(Debug, PartialEq, Serialize)]
+pub struct OtpAccount {
+    pub kind: String,
+    pub issuer: Option<String>,
+    pub account: String,
+    /// Base32 编码的密钥，默认输出时被隐藏
+    pub secret: String,
+    pub algorithm: String,
+    pub digits: u32,
+    pub period: Option<u64>,
+    pub counter: Option<u64>,
+    pub code: Option<String>,
+}
+
+/// Google Authenticator 导出的 `otpauth-migration://offline?data=` 内容
+#[derive(Debug, PartialEq, Serialize)]
+pub struct OtpMigration {
+    pub version: u64,
+    pub batch_size: u64,
+    pub batch_index: u64,
+    pub batch_id: u64,
+    pub accounts: Vec<OtpAccount>,
+}
+
+/// 解析 `otpauth://TYPE/LABEL?secret=..&issuer=..`
+pub fn parse_uri(content: &str) -> Option<OtpAccount> {
+    let url = Url::parse(content).ok()?;
+    let kind = url.host_str()?.to_ascii_lowercase();
+    if kind != "totp" && kind != "hotp" {
+        return None;
+    }
+
+    // 标签格式为 `Issuer:account`，冒号后可能带空格
+    let label = percent_encoding::percent_decode_str(url.path().trim_start_matches('/'))
+        .decode_utf8_lossy()
+        .into_owned();
+    let (label_issuer, account) = match label.split_once(':') {
+        Some((issuer, account)) => (Some(issuer.trim().to_string()), account.trim().to_string()),
+        None => (None, label.trim().to_string()),
+    };
+
+    let mut secret = None;
+    let mut issuer = None;
+    let mut algorithm = "SHA1".to_string();
+    let mut digits = 6;
+    let mut period = 30;
+    let mut counter = None;
+    for (key, value) in url.query_pairs() {
+        match key.to_ascii_lowercase().as_str() {
+            "secret" => secret = Some(value.replace(' ', "").trim_end_matches('=').to_ascii_uppercase()),
+            "issuer" => issuer = Some(value.into_owned()),
+            "algorithm" => algorithm = value.to_ascii_uppercase(),
+            "digits" => digits = value.parse().ok()?,
+            "period" => period = value.parse().ok()?,
+            "counter" => counter = value.parse().ok(),
+            _ => {}
+        }
+    }
+
+    let is_totp = kind == "totp";
+    Some(OtpAccount {
+        kind,
+        issuer: issuer.or(label_issuer).filter(|s| !s.is_empty()),
+        account,
+        secret: secret?,
+        algorithm,
+        digits,
+        period: is_totp.then_some(period),
+        counter: if is_totp { None } else { counter },
+        code: None,
+    })
+}
+
+/// 解析 `otpauth-migration://offline?data=`，data 为 Base64 编码的 protobuf
+pub fn parse_migration(content: &str) -> Option<OtpMigration> {
+    let url = Url::parse(content).ok()?;
+    let data = url.query_pairs().find(|(k, _)| k == "data").map(|(_, v)| v)?;
+    // 未转义的 `+` 会被当作空格解码
+    let data = data.replace(' ', "+");
+    let bytes = BASE64
+        .decode(data.as_bytes())
+        .or_else(|_| BASE64_NOPAD.decode(data.trim_end_matches('=').as_bytes()))
+        .ok()?;
+
+    let mut migration = OtpMigration {
+        version: 0,
+        batch_size: 0,
+        batch_index: 0,
+        batch_id: 0,
+        accounts: Vec::new(),
+    };
+    let mut reader = ProtoReader::new(&bytes);
+    while let Some((field, value)) = reader.next_field()? {
+        match (field, value) {
+            (1, Value::Bytes(params)) => migration.accounts.push(parse_otp_parameters(params)?),
+            (2, Value::Varint(v)) => migration.version = v,
+            (3, Value::Varint(v)) => migration.batch_size = v,
+            (4, Value::Varint(v)) => migration.batch_index = v,
+            (5, Value::Varint(v)) => migration.batch_id = v,
+            _ => {}
+        }
+    }
+    Some(migration)
+}
+
+// MigrationPayload.OtpParameters
+fn parse_otp_parameters(bytes: &[u8]) -> Option<OtpAccount> {
+    let mut secret = Vec::new();
+    let mut name = String::new();
+    let mut issuer = String::new();
+    let mut algorithm = 0;
+    let mut digits = 0;
+    let mut kind = 0;
+    let mut counter = 0;
+    let mut reader = ProtoReader::new(bytes);
+    while let Some((field, value)) = reader.next_field()? {
+        match (field, value) {
+            (1, Value::Bytes(v)) => secret = v.to_vec(),
+            (2, Value::Bytes(v)) => name = String::from_utf8_lossy(v).into_owned(),
+            (3, Value::Bytes(v)) => issuer = String::from_utf8_lossy(v).into_owned(),
+            (4, Value::Varint(v)) => algorithm = v,
+            (5, Value::Varint(v)) => digits = v,
+            (6, Value::Varint(v)) => kind = v,
+            (7, Value::Varint(v)) => counter = v,
+            _ => {}
+        }
+    }
+
+    // name 通常为 `Issuer:account`
+    let account = match name.split_once(':') {
+        Some((prefix, account)) if prefix.trim() == issuer => account.trim().to_string(),
+        _ => name,
+    };
+    let is_hotp = kind == 1;
+    Some(OtpAccount {
+        kind: if is_hotp { "hotp" } else { "totp" }.to_string(),
+        issuer: if issuer.is_empty() { None } else { Some(issuer) },
+        account,
+        secret: BASE32_NOPAD.encode(&secret),
+        algorithm: match algorithm {
+            2 => "SHA256",
+            3 => "SHA512",
+            4 => "MD5",
+            _ => "SHA1",
+        }
+        .to_string(),
+        digits: if digits == 2 { 8 } else { 6 },
+        period: (!is_hotp).then_some(30),
+        counter: is_hotp.then_some(counter),
+        code: None,
+    })
+}
+
+enum Value<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+    Fixed,
+}
+
+/// 仅支持解析迁移数据所需的 protobuf 线格式
+struct ProtoReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ProtoReader<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    fn varint(&mut self) -> Option<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = *self.buf.get(self.pos)?;
+            self.pos += 1;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.buf.get(self.pos..self.pos.checked_add(len)?)?;
+        self.pos += len;
+        Some(bytes)
+    }
+
+    /// 读取下一个字段，数据结束时返回 `Some(None)`，格式错误时返回 `None`
+    fn next_field(&mut self) -> Option<Option<(u64, Value<'a>)>> {
+        if self.pos >= self.buf.len() {
+            return Some(None);
+        }
+        let key = self.varint()?;
+        let value = match key & 7 {
+            0 => Value::Varint(self.varint()?),
+            1 => {
+                self.take(8)?;
+                Value::Fixed
+            }
+            2 => {
+                let len = self.varint()? as usize;
+                Value::Bytes(self.take(len)?)
+            }
+            5 => {
+                self.take(4)?;
+                Value::Fixed
+            }
+            _ => return None,
+        };
+        Some(Some((key >> 3, value)))
+    }
+}
+
+/// 计算 TOTP 动态码，参见 RFC 6238
+pub fn totp(account: &OtpAccount, unix_time: u64) -> Option<String> {
+    let secret = BASE32_NOPAD.decode(account.secret.as_bytes()).ok()?;
+    let period = account.period.filter(|&p| p > 0)?;
+    let counter = (unix_time / period).to_be_bytes();
+    let hash = match account.algorithm.as_str() {
+        "SHA1" => hmac::<Hmac<Sha1>>(&secret, &counter)?,
+        "SHA256" => hmac::<Hmac<Sha256>>(&secret, &counter)?,
+        "SHA512" => hmac::<Hmac<Sha512>>(&secret, &counter)?,
+        _ => return None,
+    };
+
+    // 动态截取，参见 RFC 4226 5.3
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+    let digits = account.digits.clamp(1, 9);
+    Some(format!(
+        "{:0width$}",
+        binary % 10u32.pow(digits),
+        width = digits as usize
+    ))
+}
+
+fn hmac<M: Mac + hmac::digest::KeyInit>(key: &[u8], data: &[u8]) -> Option<Vec<u8>> {
+    let mut mac = <M as hmac::digest::KeyInit>::new_from_slice(key).ok()?;
+    mac.update(data);
+    Some(mac.finalize().into_bytes().to_vec())
+}
+
+/// 按命令行选项为 OTP 结果计算当前动态码，并默认隐藏密钥
+pub fn protect(decoded: &mut Decoded, show_secrets: bool, with_code: bool) {
+    let accounts = match &mut decoded.payload {
+        Payload::Otp(account) => std::slice::from_mut(account),
+        Payload::OtpMigration(migration) => migration.accounts.as_mut_slice(),
+        _ => return,
+    };
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    for account in accounts.iter_mut() {
+        if with_code && account.kind == "totp" {
+            account.code = totp(account, now);
+        }
+        if !show_secrets {
+            account.secret = REDACTED.to_string();
+        }
+    }
+    if !show_secrets {
+        decoded.content = redact_uri(&decoded.content);
+    }
+}
+
+/// 内容中是否带有 OTP 密钥，原始字节无法隐藏密钥，只能整体拒绝输出
+pub fn holds_secret(payload: &Payload) -> bool {
+    matches!(payload, Payload::Otp(_) | Payload::OtpMigration(_))
+}
+
+/// 已计算出的动态码，标签为 `Issuer (account)`
+pub fn codes(payload: &Payload) -> Vec<(String, &str)> {
+    let accounts = match payload {
+        Payload::Otp(account) => std::slice::from_ref(account),
+        Payload::OtpMigration(migration) => migration.accounts.as_slice(),
+        _ => return Vec::new(),
+    };
+    accounts
+        .iter()
+        .filter_map(|account| {
+            let code = account.code.as_deref()?;
+            let label = match &account.issuer {
+                Some(issuer) => format!("{} ({})", issuer, account.account),
+                None => account.account.clone(),
+            };
+            Some((label, code))
+        })
+        .collect()
+}
+
+// 将原始内容中的 `secret`/`data` 参数替换为占位符
+fn redact_uri(content: &str) -> String {
+    let Some((base, query)) = content.split_once('?') else {
+        return content.to_string();
+    };
+    let query = query
+        .split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some((key, _)) if key.eq_ignore_ascii_case("secret") || key.eq_ignore_ascii_case("data") => {
+                format!("{}={}", key, REDACTED)
+            }
+            _ => pair.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("&");
+    format!("{}?{}", base, query)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitstream::Stream;
+    use crate::symbology::Symbology;
+
+    #[test]
+    fn test_parse_uri() {
+        let account =
+            parse_uri("otpauth://totp/ACME%20Co:john@example.com?secret=JBSWY3DPEHPK3PXP&issuer=ACME%20Co&digits=8")
+                .unwrap();
+        assert_eq!(account.issuer.as_deref(), Some("ACME Co"));
+        assert_eq!(account.account, "john@example.com");
+        assert_eq!(account.secret, "JBSWY3DPEHPK3PXP");
+        assert_eq!(account.digits, 8);
+        assert_eq!(account.period, Some(30));
+        assert_eq!(
+            redact_uri("otpauth://totp/x?secret=JBSWY3DPEHPK3PXP&issuer=y"),
+            "otpauth://totp/x?secret=REDACTED&issuer=y"
+        );
+    }
+
+    #[test]
+    fn test_parse_migration() {
+        // secret "Hello!\xde\xad\xbe\xef", name "Example:alice@google.com", issuer "Example", SHA1, 6 位, TOTP
+        let migration = parse_migration(
+            "otpauth-migration://offline?data=CjUKCkhlbGxvId6tvu8SGEV4YW1wbGU6YWxpY2VAZ29vZ2xlLmNvbRoHRXhhbXBsZSABKAEwAhABGAEgACh7",
+        )
+        .unwrap();
+        assert_eq!(migration.version, 1);
+        assert_eq!(migration.batch_id, 123);
+        assert_eq!(migration.accounts.len(), 1);
+        let account = &migration.accounts[0];
+        assert_eq!(account.kind, "totp");
+        assert_eq!(account.issuer.as_deref(), Some("Example"));
+        assert_eq!(account.account, "alice@google.com");
+        assert_eq!(account.secret, "JBSWY3DPEHPK3PXP");
+    }
+
+    #[test]
+    fn test_holds_secret() {
+        let decoded = |text: &str| {
+            let stream = Stream {
+                runs: vec![(None, text.as_bytes().to_vec())],
+                ..Default::default()
+            };
+            Decoded::new(stream, None, Symbology::Qr, None, None, "original")
+        };
+        assert!(holds_secret(
+            &decoded("otpauth://totp/x?secret=JBSWY3DPEHPK3PXP").payload
+        ));
+        assert!(holds_secret(&decoded("otpauth-migration://offline?data=CjUKCkhlbGxvId6tvu8SGEV4YW1wbGU6YWxpY2VAZ29vZ2xlLmNvbRoHRXhhbXBsZSABKAEwAhABGAEgACh7").payload));
+        assert!(!holds_secret(&decoded("https://example.com/").payload));
+    }
+
+    #[test]
+    fn test_totp() {
+        // RFC 6238 附录 B 测试向量
+        let account = OtpAccount {
+            kind: "totp".to_string(),
+            issuer: None,
+            account: String::new(),
+            secret: BASE32_NOPAD.encode(b"12345678901234567890"),
+            algorithm: "SHA1".to_string(),
+            digits: 8,
+            period: Some(30),
+            counter: None,
+            code: None,
+        };
+        assert_eq!(totp(&account, 59).as_deref(), Some("94287082"));
+        assert_eq!(totp(&account, 1111111109).as_deref(), Some("07081804"));
+    }
+}
@@ -0,0 +1,695 @@
+use percent_encoding::percent_decode_str;
+use serde::Serialize;
+use url::Url;
+
+use crate::emv::{self, EmvPayment};
+use crate::otpauth::{self, OtpAccount, OtpMigration};
+
+/// 二维码内容的结构化解析结果
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Payload {
+    Wifi(Wifi),
+    Contact(Contact),
+    Geo(Geo),
+    Sms(Sms),
+    Email(Email),
+    Phone {
+        number: String,
+    },
+    Event(Event),
+    Url {
+        url: String,
+        /// MEBKM 书签的标题，即扫码应用显示给用户的文字
+        #[serde(skip_serializing_if = "Option::is_none")]
+        title: Option<String>,
+    },
+    Otp(OtpAccount),
+    OtpMigration(OtpMigration),
+    Payment(Box<EmvPayment>),
+    Text,
+    /// 无法识别为文本的二进制数据
+    Binary,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct Wifi {
+    pub ssid: String,
+    pub auth: Option<String>,
+    pub password: Option<String>,
+    pub hidden: bool,
+    pub eap_method: Option<String>,
+    pub identity: Option<String>,
+    pub anonymous_identity: Option<String>,
+    pub phase2: Option<String>,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct Contact {
+    pub format: &'static str,
+    pub name: Option<String>,
+    pub organization: Option<String>,
+    pub title: Option<String>,
+    pub phones: Vec<String>,
+    pub emails: Vec<String>,
+    pub urls: Vec<String>,
+    pub addresses: Vec<String>,
+    pub birthday: Option<String>,
+    pub note: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Geo {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub altitude: Option<f64>,
+    pub query: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Sms {
+    pub recipients: Vec<String>,
+    pub body: Option<String>,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct Email {
+    pub to: Vec<String>,
+    pub cc: Vec<String>,
+    pub bcc: Vec<String>,
+    pub subject: Option<String>,
+    pub body: Option<String>,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct Event {
+    pub summary: Option<String>,
+    pub start: Option<String>,
+    pub end: Option<String>,
+    pub location: Option<String>,
+    pub description: Option<String>,
+    pub url: Option<String>,
+}
+
+/// 识别常见的二维码内容格式，无法识别时返回 `Payload::Text`
+pub fn classify(content: &str) -> Payload {
+    let trimmed = content.trim();
+    let parsed = if let Some(body) = strip_prefix_ci(trimmed, "WIFI:") {
+        parse_wifi(body).map(Payload::Wifi)
+    } else if let Some(body) = strip_prefix_ci(trimmed, "MECARD:") {
+        Some(Payload::Contact(parse_mecard(body)))
+    } else if strip_prefix_ci(trimmed, "BEGIN:VCARD").is_some() {
+        Some(Payload::Contact(parse_vcard(trimmed)))
+    } else if trimmed.to_ascii_uppercase().contains("BEGIN:VEVENT") {
+        Some(Payload::Event(parse_event(trimmed)))
+    } else if let Some(body) = strip_prefix_ci(trimmed, "geo:") {
+        parse_geo(body).map(Payload::Geo)
+    } else if let Some(body) = strip_prefix_ci(trimmed, "smsto:").or_else(|| strip_prefix_ci(trimmed, "mmsto:")) {
+        Some(Payload::Sms(parse_smsto(body)))
+    } else if let Some(body) = strip_prefix_ci(trimmed, "sms:") {
+        Some(Payload::Sms(parse_sms(body)))
+    } else if let Some(body) = strip_prefix_ci(trimmed, "mailto:") {
+        Some(Payload::Email(parse_mailto(body)))
+    } else if let Some(body) = strip_prefix_ci(trimmed, "MATMSG:") {
+        Some(Payload::Email(parse_matmsg(body)))
+    } else if let Some(body) = strip_prefix_ci(trimmed, "tel:") {
+        Some(Payload::Phone {
+            number: percent_decode(body),
+        })
+    } else if strip_prefix_ci(trimmed, "otpauth://").is_some() {
+        otpauth::parse_uri(trimmed).map(Payload::Otp)
+    } else if strip_prefix_ci(trimmed, "otpauth-migration://").is_some() {
+        otpauth::parse_migration(trimmed).map(Payload::OtpMigration)
+    } else if trimmed.starts_with("000201") {
+        emv::parse(trimmed).map(|payment| Payload::Payment(Box::new(payment)))
+    } else if let Some(body) = strip_prefix_ci(trimmed, "MEBKM:") {
+        parse_bookmark(body)
+    } else if URL_SCHEMES
+        .iter()
+        .any(|scheme| strip_prefix_ci(trimmed, scheme).is_some())
+    {
+        Url::parse(trimmed).ok().map(|url| Payload::Url {
+            url: url.to_string(),
+            title: None,
+        })
+    } else {
+        None
+    };
+    parsed.unwrap_or(Payload::Text)
+}
+
+// 识别为网址的协议，除 http(s) 外都是打开后会直接执行或加载内容的协议，由安全检查标记
+const URL_SCHEMES: &[&str] = &["http://", "https://", "javascript:", "data:", "vbscript:", "file:"];
+
+fn strip_prefix_ci<'a>(s: &'a str, prefix: &str) -> Option<&'a str> {
+    match s.get(..prefix.len()) {
+        Some(head) if head.eq_ignore_ascii_case(prefix) => Some(&s[prefix.len()..]),
+        _ => None,
+    }
+}
+
+fn non_empty(s: String) -> Option<String> {
+    if s.is_empty() { None } else { Some(s) }
+}
+
+fn percent_decode(s: &str) -> String {
+    percent_decode_str(s).decode_utf8_lossy().into_owned()
+}
+
+// 解析 `a=1&b=2` 形式的查询串，mailto/sms 中的 `+` 不表示空格
+fn query_pairs(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (key.to_ascii_lowercase(), percent_decode(value))
+        })
+        .collect()
+}
+
+// 按未被反斜杠转义的分隔符切分，保留转义字符
+fn split_unescaped(s: &str, sep: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == sep {
+            parts.push(&s[start..i]);
+            start = i + c.len_utf8();
+        }
+    }
+    parts.push(&s[start..]);
+    parts
+}
+
+// MeCard/WIFI 转义：`\;` `\,` `\:` `\\` `\"`
+fn unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            if let Some(next) = chars.next() {
+                out.push(next);
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+// `WIFI:` 及 `MECARD:` 格式的 `KEY:value;` 字段
+fn mecard_fields(body: &str) -> Vec<(String, String)> {
+    split_unescaped(body, ';')
+        .into_iter()
+        .filter_map(|field| field.split_once(':'))
+        .map(|(key, value)| (key.trim().to_ascii_uppercase(), value.to_string()))
+        .collect()
+}
+
+fn parse_wifi(body: &str) -> Option<Wifi> {
+    let mut wifi = Wifi::default();
+    let mut ssid = None;
+    for (key, raw) in mecard_fields(body) {
+        // 十六进制形式的 SSID/密码可能被双引号包裹
+        let raw = match raw.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+            Some(inner) => inner,
+            None => raw.as_str(),
+        };
+        let value = unescape(raw);
+        match key.as_str() {
+            "S" => ssid = Some(value),
+            "T" => wifi.auth = non_empty(value),
+            "P" => wifi.password = non_empty(value),
+            "H" => wifi.hidden = value.eq_ignore_ascii_case("true"),
+            "E" => wifi.eap_method = non_empty(value),
+            "I" => wifi.identity = non_empty(value),
+            "A" => wifi.anonymous_identity = non_empty(value),
+            "PH2" => wifi.phase2 = non_empty(value),
+            _ => {}
+        }
+    }
+    wifi.ssid = ssid?;
+    Some(wifi)
+}
+
+// `MEBKM:TITLE:标题;URL:网址;;`
+fn parse_bookmark(body: &str) -> Option<Payload> {
+    let mut title = None;
+    let mut url = None;
+    for (key, raw) in mecard_fields(body) {
+        match key.as_str() {
+            "TITLE" => title = non_empty(unescape(&raw)),
+            "URL" => url = Url::parse(&unescape(&raw)).ok(),
+            _ => {}
+        }
+    }
+    url.map(|url| Payload::Url {
+        url: url.to_string(),
+        title,
+    })
+}
+
+fn parse_mecard(body: &str) -> Contact {
+    let mut contact = Contact {
+        format: "mecard",
+        ..Default::default()
+    };
+    for (key, raw) in mecard_fields(body) {
+        match key.as_str() {
+            // `N:姓,名`
+            "N" => {
+                let parts: Vec<String> = split_unescaped(&raw, ',').into_iter().map(unescape).collect();
+                let name = parts
+                    .into_iter()
+                    .rev()
+                    .filter(|p| !p.is_empty())
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                contact.name = non_empty(name);
+            }
+            "TEL" | "TEL-AV" => contact.phones.push(unescape(&raw)),
+            "EMAIL" => contact.emails.push(unescape(&raw)),
+            "URL" => contact.urls.push(unescape(&raw)),
+            "ADR" => contact.addresses.push(unescape(&raw)),
+            "ORG" => contact.organization = non_empty(unescape(&raw)),
+            "TITLE" => contact.title = non_empty(unescape(&raw)),
+            "BDAY" => contact.birthday = non_empty(unescape(&raw)),
+            "NOTE" => contact.note = non_empty(unescape(&raw)),
+            _ => {}
+        }
+    }
+    contact
+}
+
+/// vCard/iCalendar 的一行属性
+struct Property {
+    name: String,
+    params: Vec<(String, String)>,
+    value: String,
+}
+
+impl Property {
+    fn param(&self, key: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.as_str())
+    }
+
+    // vCard 2.1 中常见的 quoted-printable 编码
+    fn decoded_value(&self) -> String {
+        let is_qp = self
+            .param("ENCODING")
+            .is_some_and(|v| v.eq_ignore_ascii_case("QUOTED-PRINTABLE"))
+            || self
+                .params
+                .iter()
+                .any(|(k, v)| v.is_empty() && k.eq_ignore_ascii_case("QUOTED-PRINTABLE"));
+        if is_qp {
+            decode_quoted_printable(&self.value)
+        } else {
+            self.value.clone()
+        }
+    }
+}
+
+fn decode_quoted_printable(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'=' {
+            let hex = s.get(i + 1..i + 3).and_then(|h| u8::from_str_radix(h, 16).ok());
+            if let Some(b) = hex {
+                out.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+// 展开折叠行并拆分为属性，参见 RFC 6350 3.2 / RFC 5545 3.1
+fn content_lines(content: &str) -> Vec<Property> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in content.lines() {
+        let line = line.trim_end_matches('\r');
+        match lines.last_mut() {
+            Some(last) if line.starts_with(' ') || line.starts_with('\t') => last.push_str(&line[1..]),
+            // quoted-printable 软换行
+            Some(last) if last.ends_with('=') && last.to_ascii_uppercase().contains("QUOTED-PRINTABLE") => {
+                last.pop();
+                last.push_str(line);
+            }
+            _ => lines.push(line.to_string()),
+        }
+    }
+
+    lines
+        .iter()
+        .filter_map(|line| {
+            // 参数值可能带引号并包含冒号
+            let mut in_quotes = false;
+            let colon = line.char_indices().find_map(|(i, c)| match c {
+                '"' => {
+                    in_quotes = !in_quotes;
+                    None
+                }
+                ':' if !in_quotes => Some(i),
+                _ => None,
+            })?;
+            let (head, value) = (&line[..colon], &line[colon + 1..]);
+            let mut parts = head.split(';');
+            let name = parts.next()?;
+            // 去掉 `item1.TEL` 中的分组前缀
+            let name = name.rsplit('.').next().unwrap_or(name).trim().to_ascii_uppercase();
+            let params = parts
+                .map(|p| {
+                    let (k, v) = p.split_once('=').unwrap_or((p, ""));
+                    (k.trim().to_string(), v.trim_matches('"').to_string())
+                })
+                .collect();
+            Some(Property {
+                name,
+                params,
+                value: value.to_string(),
+            })
+        })
+        .collect()
+}
+
+// vCard/iCalendar 文本转义：`\n` `\,` `\;` `\\`
+fn unescape_text(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('n') | Some('N') => out.push('\n'),
+                Some(next) => out.push(next),
+                None => {}
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+// 结构化值（N、ADR）按 `;` 拆分后去掉空组件
+fn structured_components(value: &str) -> Vec<String> {
+    split_unescaped(value, ';')
+        .into_iter()
+        .map(unescape_text)
+        .map(|s| s.trim().to_string())
+        .collect()
+}
+
+fn parse_vcard(content: &str) -> Contact {
+    let mut contact = Contact {
+        format: "vcard",
+        ..Default::default()
+    };
+    let mut structured_name = None;
+    for prop in content_lines(content) {
+        let value = prop.decoded_value();
+        match prop.name.as_str() {
+            "FN" => contact.name = non_empty(unescape_text(&value)),
+            "N" => {
+                // 姓;名;中间名;前缀;后缀
+                let c = structured_components(&value);
+                let order = [3, 1, 2, 0, 4];
+                let name = order
+                    .iter()
+                    .filter_map(|&i| c.get(i))
+                    .filter(|s| !s.is_empty())
+                    .cloned()
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                structured_name = non_empty(name);
+            }
+            "ORG" => {
+                let org = structured_components(&value).into_iter().filter(|s| !s.is_empty());
+                contact.organization = non_empty(org.collect::<Vec<_>>().join(", "));
+            }
+            "TITLE" => contact.title = non_empty(unescape_text(&value)),
+            "TEL" => contact.phones.push(unescape_text(&value)),
+            "EMAIL" => contact.emails.push(unescape_text(&value)),
+            "URL" => contact.urls.push(unescape_text(&value)),
+            "ADR" => {
+                let adr = structured_components(&value).into_iter().filter(|s| !s.is_empty());
+                if let Some(adr) = non_empty(adr.collect::<Vec<_>>().join(", ")) {
+                    contact.addresses.push(adr);
+                }
+            }
+            "BDAY" => contact.birthday = non_empty(unescape_text(&value)),
+            "NOTE" => contact.note = non_empty(unescape_text(&value)),
+            _ => {}
+        }
+    }
+    if contact.name.is_none() {
+        contact.name = structured_name;
+    }
+    contact
+}
+
+fn parse_event(content: &str) -> Event {
+    let mut event = Event::default();
+    let mut in_event = false;
+    for prop in content_lines(content) {
+        let value = prop.decoded_value();
+        match prop.name.as_str() {
+            "BEGIN" if value.eq_ignore_ascii_case("VEVENT") => in_event = true,
+            "END" if value.eq_ignore_ascii_case("VEVENT") => break,
+            _ if !in_event => {}
+            "SUMMARY" => event.summary = non_empty(unescape_text(&value)),
+            "DTSTART" => event.start = non_empty(ical_datetime(&value)),
+            "DTEND" => event.end = non_empty(ical_datetime(&value)),
+            "LOCATION" => event.location = non_empty(unescape_text(&value)),
+            "DESCRIPTION" => event.description = non_empty(unescape_text(&value)),
+            "URL" => event.url = non_empty(value),
+            _ => {}
+        }
+    }
+    event
+}
+
+// `20240102T030405Z` 转为 ISO 8601 的 `2024-01-02T03:04:05Z`，无法识别时原样返回
+fn ical_datetime(value: &str) -> String {
+    let value = value.trim();
+    let digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    let (date, rest) = value.split_once('T').unwrap_or((value, ""));
+    if date.len() != 8 || !digits(date) {
+        return value.to_string();
+    }
+    let date = format!("{}-{}-{}", &date[..4], &date[4..6], &date[6..8]);
+    if rest.is_empty() {
+        return date;
+    }
+    let (time, utc) = match rest.strip_suffix('Z') {
+        Some(time) => (time, "Z"),
+        None => (rest, ""),
+    };
+    if time.len() != 6 || !digits(time) {
+        return value.to_string();
+    }
+    format!("{}T{}:{}:{}{}", date, &time[..2], &time[2..4], &time[4..6], utc)
+}
+
+// `geo:纬度,经度[,海拔][;参数][?q=查询]`
+fn parse_geo(body: &str) -> Option<Geo> {
+    let (coords, query) = body.split_once('?').unwrap_or((body, ""));
+    let coords = coords.split(';').next().unwrap_or_default();
+    let mut numbers = coords.split(',').map(|s| s.trim().parse::<f64>());
+    let latitude = numbers.next()?.ok()?;
+    let longitude = numbers.next()?.ok()?;
+    let altitude = numbers.next().and_then(|n| n.ok());
+    if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+        return None;
+    }
+    let query = query_pairs(query).into_iter().find(|(k, _)| k == "q").map(|(_, v)| v);
+    Some(Geo {
+        latitude,
+        longitude,
+        altitude,
+        query,
+    })
+}
+
+// `sms:号码[,号码][?body=内容]`，iOS 生成的链接使用 `&body=`
+fn parse_sms(body: &str) -> Sms {
+    let split = body.find(['?', '&']).unwrap_or(body.len());
+    let (numbers, query) = (&body[..split], body.get(split + 1..).unwrap_or_default());
+    Sms {
+        recipients: numbers
+            .split(',')
+            .map(percent_decode)
+            .filter(|n| !n.is_empty())
+            .collect(),
+        body: query_pairs(query)
+            .into_iter()
+            .find(|(k, _)| k == "body")
+            .map(|(_, v)| v),
+    }
+}
+
+// `SMSTO:号码:内容`
+fn parse_smsto(body: &str) -> Sms {
+    let (number, text) = body.split_once(':').unwrap_or((body, ""));
+    Sms {
+        recipients: vec![number.to_string()],
+        body: non_empty(text.to_string()),
+    }
+}
+
+// `mailto:地址[,地址][?cc=..&bcc=..&subject=..&body=..]`，参见 RFC 6068
+fn parse_mailto(body: &str) -> Email {
+    let (to, query) = body.split_once('?').unwrap_or((body, ""));
+    let addresses = |s: &str| -> Vec<String> {
+        s.split(',')
+            .map(|a| percent_decode(a).trim().to_string())
+            .filter(|a| !a.is_empty())
+            .collect()
+    };
+    let mut email = Email {
+        to: addresses(to),
+        ..Default::default()
+    };
+    for (key, value) in query_pairs(query) {
+        match key.as_str() {
+            "to" => email.to.extend(addresses(&value)),
+            "cc" => email.cc.extend(addresses(&value)),
+            "bcc" => email.bcc.extend(addresses(&value)),
+            "subject" => email.subject = non_empty(value),
+            "body" => email.body = non_empty(value),
+            _ => {}
+        }
+    }
+    email
+}
+
+// `MATMSG:TO:地址;SUB:主题;BODY:内容;;`
+fn parse_matmsg(body: &str) -> Email {
+    let mut email = Email::default();
+    for (key, raw) in mecard_fields(body) {
+        let value = unescape(&raw);
+        match key.as_str() {
+            "TO" => email.to.push(value),
+            "SUB" => email.subject = non_empty(value),
+            "BODY" => email.body = non_empty(value),
+            _ => {}
+        }
+    }
+    email
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wifi() {
+        let payload = classify(r#"WIFI:T:WPA;S:my\;net\\work;P:"p\:a\"ss";H:true;;"#);
+        assert_eq!(
+            payload,
+            Payload::Wifi(Wifi {
+                ssid: r"my;net\work".to_string(),
+                auth: Some("WPA".to_string()),
+                password: Some(r#"p:a"ss"#.to_string()),
+                hidden: true,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_vcard() {
+        let payload = classify(
+            "BEGIN:VCARD\r\nVERSION:3.0\r\nN:Doe;John;;Dr.;\r\nitem1.TEL;TYPE=CELL:+1 555 0100\r\n\
+             EMAIL:john@example.com\r\nADR;TYPE=WORK:;;1 Main St;Springfield;;12345;US\r\n\
+             NOTE:line one\\nline\r\n  two\r\nEND:VCARD",
+        );
+        let Payload::Contact(contact) = payload else {
+            panic!("not a contact: {:?}", payload);
+        };
+        assert_eq!(contact.format, "vcard");
+        assert_eq!(contact.name.as_deref(), Some("Dr. John Doe"));
+        assert_eq!(contact.phones, vec!["+1 555 0100"]);
+        assert_eq!(contact.addresses, vec!["1 Main St, Springfield, 12345, US"]);
+        assert_eq!(contact.note.as_deref(), Some("line one\nline two"));
+    }
+
+    #[test]
+    fn test_mecard() {
+        let Payload::Contact(contact) = classify("MECARD:N:Doe,John;TEL:123;EMAIL:a@b.c;URL:http\\://x.y;;") else {
+            panic!("not a contact");
+        };
+        assert_eq!(contact.name.as_deref(), Some("John Doe"));
+        assert_eq!(contact.urls, vec!["http://x.y"]);
+    }
+
+    #[test]
+    fn test_event() {
+        let Payload::Event(event) = classify(
+            "BEGIN:VCALENDAR\nBEGIN:VEVENT\nSUMMARY:Team sync\\, weekly\nDTSTART:20240102T030405Z\nDTEND;VALUE=DATE:20240103\nEND:VEVENT\nEND:VCALENDAR",
+        ) else {
+            panic!("not an event");
+        };
+        assert_eq!(event.summary.as_deref(), Some("Team sync, weekly"));
+        assert_eq!(event.start.as_deref(), Some("2024-01-02T03:04:05Z"));
+        assert_eq!(event.end.as_deref(), Some("2024-01-03"));
+    }
+
+    #[test]
+    fn test_uri_schemes() {
+        assert_eq!(
+            classify("geo:37.786971,-122.399677?q=Moscone%20Center"),
+            Payload::Geo(Geo {
+                latitude: 37.786971,
+                longitude: -122.399677,
+                altitude: None,
+                query: Some("Moscone Center".to_string()),
+            })
+        );
+        assert_eq!(
+            classify("SMSTO:+15550100:hello there"),
+            Payload::Sms(Sms {
+                recipients: vec!["+15550100".to_string()],
+                body: Some("hello there".to_string()),
+            })
+        );
+        assert_eq!(
+            classify("mailto:a+b@example.com?cc=c@example.com&subject=Hi%20there"),
+            Payload::Email(Email {
+                to: vec!["a+b@example.com".to_string()],
+                cc: vec!["c@example.com".to_string()],
+                subject: Some("Hi there".to_string()),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            classify("tel:+1-555-0100"),
+            Payload::Phone {
+                number: "+1-555-0100".to_string()
+            }
+        );
+        assert_eq!(
+            classify("MEBKM:TITLE:My Bank;URL:https\\://example.com/login;;"),
+            Payload::Url {
+                url: "https://example.com/login".to_string(),
+                title: Some("My Bank".to_string()),
+            }
+        );
+        assert!(matches!(classify("javascript:alert(1)"), Payload::Url { .. }));
+        assert_eq!(classify("just some text"), Payload::Text);
+    }
+}
@@ -0,0 +1,240 @@
+use image::imageops::{self, FilterType};
+use image::{GrayImage, Luma};
+use imageproc::contrast::{ThresholdType, equalize_histogram, otsu_level, threshold_mut};
+use imageproc::filter::{median_filter, sharpen3x3};
+use imageproc::geometric_transformations::{Interpolation, rotate_about_center};
+use imageproc::integral_image::{integral_image, sum_image_pixels};
+
+// 放大后的最长边上限，避免 4K 截图放大后占用过多内存
+const UPSCALE_LIMIT: u32 = 4096;
+// 缩小后的最长边
+const DOWNSCALE_SIZE: u32 = 1024;
+// 自适应二值化时像素比邻域均值暗过该值才视为黑色；平坦区域否则会变成一半黑一半白的噪点，
+// rqrr 会在噪点中找到大量候选定位图案，并为每组候选复制整幅图像，1080p 的照片要几十秒
+const ADAPTIVE_BIAS: u32 = 4;
+
+#[derive(Clone, Copy, Debug)]
+enum Step {
+    Invert,
+    Equalize,
+    Otsu,
+    Adaptive,
+    Upscale,
+    Downscale,
+    Sharpen,
+    Denoise,
+    Rotate(f32),
+}
+
+/// 预处理策略，原图检测失败后按顺序逐个重试
+#[derive(Debug)]
+pub struct Strategy {
+    pub name: &'static str,
+    steps: &'static [Step],
+}
+
+pub const STRATEGIES: &[Strategy] = &[
+    Strategy {
+        name: "original",
+        steps: &[],
+    },
+    // 深色模式截图
+    Strategy {
+        name: "inverted",
+        steps: &[Step::Invert],
+    },
+    // 低对比度照片
+    Strategy {
+        name: "equalized",
+        steps: &[Step::Equalize],
+    },
+    Strategy {
+        name: "otsu",
+        steps: &[Step::Otsu],
+    },
+    Strategy {
+        name: "adaptive",
+        steps: &[Step::Adaptive],
+    },
+    Strategy {
+        name: "inverted-adaptive",
+        steps: &[Step::Adaptive, Step::Invert],
+    },
+    // 大图中的小尺寸二维码
+    Strategy {
+        name: "upscaled",
+        steps: &[Step::Upscale],
+    },
+    Strategy {
+        name: "downscaled",
+        steps: &[Step::Downscale],
+    },
+    // 模糊图像
+    Strategy {
+        name: "sharpened",
+        steps: &[Step::Sharpen],
+    },
+    Strategy {
+        name: "sharpened-otsu",
+        steps: &[Step::Sharpen, Step::Otsu],
+    },
+    // 噪点较多的图像
+    Strategy {
+        name: "denoised-otsu",
+        steps: &[Step::Denoise, Step::Otsu],
+    },
+    // 倾斜拍摄
+    Strategy {
+        name: "rotated-15",
+        steps: &[Step::Rotate(15.0)],
+    },
+    Strategy {
+        name: "rotated-345",
+        steps: &[Step::Rotate(-15.0)],
+    },
+    Strategy {
+        name: "rotated-45",
+        steps: &[Step::Rotate(45.0)],
+    },
+];
+
+// 快速扫描只尝试原图和 Otsu 二值化，耗时约为全部策略的七分之一
+const QUICK: &[&str] = &["original", "otsu"];
+
+/// 需要依次尝试的策略，quick 时只保留原图和 Otsu 二值化
+pub fn strategies(quick: bool) -> impl Iterator<Item = &'static Strategy> {
+    STRATEGIES.iter().filter(move |strategy| !quick || strategy.is_quick())
+}
+
+/// 大图先在缩小的图像金字塔上检测，金字塔某一层识别成功时结果标记为该策略
+pub const PYRAMID: Strategy = Strategy {
+    name: "pyramid",
+    steps: &[],
+};
+
+impl Strategy {
+    /// 是否为原图或 Otsu 二值化这类开销小的策略；金字塔各层同样不做预处理，也算在内
+    pub fn is_quick(&self) -> bool {
+        self.name == PYRAMID.name || QUICK.contains(&self.name)
+    }
+
+    /// 对灰度图应用该策略，策略不适用于当前图像时返回 None
+    pub fn apply(&self, img: &GrayImage) -> Option<GrayImage> {
+        let mut out = img.clone();
+        for step in self.steps {
+            out = step.apply(out)?;
+        }
+        Some(out)
+    }
+
+    /// 原图、反色及 Otsu 二值化逐像素写入 out，不分配新图像；其他策略不处理并返回 false
+    pub fn apply_into(&self, img: &GrayImage, out: &mut [u8]) -> bool {
+        let pixels = out.iter_mut().zip(img.as_raw());
+        match self.steps {
+            [] => out.copy_from_slice(img.as_raw()),
+            [Step::Invert] => pixels.for_each(|(out, &pixel)| *out = 255 - pixel),
+            [Step::Otsu] => {
+                let level = otsu_level(img);
+                pixels.for_each(|(out, &pixel)| *out = if pixel > level { 255 } else { 0 });
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// 将预处理后图像中的坐标换算回原图坐标
+    pub fn unmap(&self, original: (u32, u32), point: (f32, f32)) -> (f32, f32) {
+        let mut sizes = vec![original];
+        for step in self.steps {
+            let last = sizes[sizes.len() - 1];
+            sizes.push(step.output_size(last));
+        }
+        self.steps
+            .iter()
+            .zip(sizes.windows(2))
+            .rev()
+            .fold(point, |(x, y), (step, pair)| {
+                let ((w, h), (nw, nh)) = (pair[0], pair[1]);
+                match step {
+                    // 与 rotate_about_center 相同的中心，反向旋转
+                    Step::Rotate(degrees) => {
+                        let (sin, cos) = (-degrees.to_radians()).sin_cos();
+                        let (cx, cy) = (w as f32 / 2.0, h as f32 / 2.0);
+                        let (dx, dy) = (x - cx, y - cy);
+                        (cx + dx * cos - dy * sin, cy + dx * sin + dy * cos)
+                    }
+                    _ => (x * w as f32 / nw as f32, y * h as f32 / nh as f32),
+                }
+            })
+    }
+}
+
+// 缩小后的尺寸，最长边为 DOWNSCALE_SIZE
+fn downscaled_size(w: u32, h: u32) -> (u32, u32) {
+    let scale = DOWNSCALE_SIZE as f32 / w.max(h) as f32;
+    (((w as f32 * scale) as u32).max(1), ((h as f32 * scale) as u32).max(1))
+}
+
+impl Step {
+    fn output_size(self, (w, h): (u32, u32)) -> (u32, u32) {
+        match self {
+            Step::Upscale => (w * 2, h * 2),
+            Step::Downscale => downscaled_size(w, h),
+            _ => (w, h),
+        }
+    }
+
+    fn apply(self, mut img: GrayImage) -> Option<GrayImage> {
+        let (w, h) = img.dimensions();
+        let max_side = w.max(h);
+        let out = match self {
+            Step::Invert => {
+                imageops::invert(&mut img);
+                img
+            }
+            Step::Equalize => equalize_histogram(&img),
+            Step::Otsu => {
+                let level = otsu_level(&img);
+                threshold_mut(&mut img, level, ThresholdType::Binary);
+                img
+            }
+            Step::Adaptive => adaptive_threshold(&img, (w.min(h) / 16).clamp(8, 64)),
+            Step::Upscale => {
+                if max_side * 2 > UPSCALE_LIMIT {
+                    return None;
+                }
+                imageops::resize(&img, w * 2, h * 2, FilterType::CatmullRom)
+            }
+            Step::Downscale => {
+                if max_side <= DOWNSCALE_SIZE {
+                    return None;
+                }
+                let (nw, nh) = downscaled_size(w, h);
+                imageops::resize(&img, nw, nh, FilterType::Triangle)
+            }
+            Step::Sharpen => sharpen3x3(&img),
+            Step::Denoise => median_filter(&img, 1, 1),
+            Step::Rotate(degrees) => {
+                rotate_about_center(&img, degrees.to_radians(), Interpolation::Bilinear, Luma([255]))
+            }
+        };
+        Some(out)
+    }
+}
+
+// 与 imageproc 的 adaptive_threshold 相同，以 (2 * radius + 1) 见方的邻域均值为阈值，但留出 ADAPTIVE_BIAS 的余量
+fn adaptive_threshold(img: &GrayImage, radius: u32) -> GrayImage {
+    let integral = integral_image::<_, u32>(img);
+    let (w, h) = img.dimensions();
+    GrayImage::from_fn(w, h, |x, y| {
+        let (left, top) = (x.saturating_sub(radius), y.saturating_sub(radius));
+        let (right, bottom) = ((x + radius).min(w - 1), (y + radius).min(h - 1));
+        let count = (right - left + 1) * (bottom - top + 1);
+        let mean = sum_image_pixels(&integral, left, top, right, bottom)[0] / count;
+        Luma([if img.get_pixel(x, y)[0] as u32 + ADAPTIVE_BIAS >= mean {
+            255
+        } else {
+            0
+        }])
+    })
+}
@@ -0,0 +1,391 @@
+use std::fmt::Write;
+use std::io::Cursor;
+
+use clap::ValueEnum;
+use data_encoding::BASE64;
+use image::imageops::{self, FilterType};
+use image::{ImageFormat, Rgba, RgbaImage};
+use qrcode::{Color, QrCode};
+
+/// 编码后的模块矩阵，`true` 表示深色模块
+pub struct Matrix {
+    pub width: usize,
+    pub height: usize,
+    modules: Vec<bool>,
+}
+
+impl Matrix {
+    /// 由逐行排列的模块生成矩阵
+    pub fn new(width: usize, height: usize, modules: Vec<bool>) -> Self {
+        assert_eq!(modules.len(), width * height, "module count matches the size");
+        Self { width, height, modules }
+    }
+
+    pub fn from_code(code: &QrCode) -> Self {
+        let width = code.width();
+        Self {
+            width,
+            height: width,
+            modules: code.to_colors().into_iter().map(|c| c == Color::Dark).collect(),
+        }
+    }
+
+    /// 静区及矩阵外的位置视为浅色
+    pub fn is_dark(&self, x: i64, y: i64) -> bool {
+        if x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64 {
+            return false;
+        }
+        self.modules[y as usize * self.width + x as usize]
+    }
+
+    // 每行连续深色模块的区间 `(行, 起始列, 长度)`
+    fn dark_runs(&self) -> Vec<(usize, usize, usize)> {
+        let mut runs = Vec::new();
+        for y in 0..self.height {
+            let mut x = 0;
+            while x < self.width {
+                if self.is_dark(x as i64, y as i64) {
+                    let start = x;
+                    while x < self.width && self.is_dark(x as i64, y as i64) {
+                        x += 1;
+                    }
+                    runs.push((y, start, x - start));
+                } else {
+                    x += 1;
+                }
+            }
+        }
+        runs
+    }
+}
+
+/// 渲染样式
+pub struct Style {
+    /// 静区宽度（模块数）
+    pub quiet_zone: u32,
+    /// 单个模块的边长，位图为像素，矢量格式为点
+    pub module_size: u32,
+    pub foreground: Rgba<u8>,
+    pub background: Rgba<u8>,
+}
+
+impl Style {
+    /// 含静区的输出尺寸，溢出 u32 时返回 None
+    pub fn size(&self, matrix: &Matrix) -> Option<(u32, u32)> {
+        let qz = self.quiet_zone.checked_mul(2)?;
+        let side = |modules: usize| {
+            u32::try_from(modules)
+                .ok()?
+                .checked_add(qz)?
+                .checked_mul(self.module_size)
+        };
+        Some((side(matrix.width)?, side(matrix.height)?))
+    }
+
+    // 渲染前由调用方用 size 检查过尺寸
+    fn checked_size(&self, matrix: &Matrix) -> (u32, u32) {
+        self.size(matrix).expect("output size checked before rendering")
+    }
+}
+
+/// 徽标背板形状
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum Backdrop {
+    /// 不绘制背板，徽标直接覆盖在模块上
+    None,
+    Square,
+    Rounded,
+}
+
+/// 叠加在二维码中心的徽标
+pub struct Logo {
+    pub image: RgbaImage,
+    /// 徽标长边占符号宽度（不含静区）的比例
+    pub ratio: f32,
+    /// 徽标与背板边缘的留白（模块数）
+    pub padding: f32,
+    pub backdrop: Backdrop,
+}
+
+// 徽标与背板的位置，单位为模块，原点为含静区图像的左上角
+struct Placement {
+    logo: [f32; 4],
+    backdrop: [f32; 4],
+    radius: f32,
+}
+
+impl Logo {
+    fn placement(&self, matrix: &Matrix, style: &Style) -> Placement {
+        let (iw, ih) = self.image.dimensions();
+        let scale = self.ratio * matrix.width as f32 / iw.max(ih).max(1) as f32;
+        let (w, h) = (iw as f32 * scale, ih as f32 * scale);
+        let cx = style.quiet_zone as f32 + matrix.width as f32 / 2.0;
+        let cy = style.quiet_zone as f32 + matrix.height as f32 / 2.0;
+        let pad = if self.backdrop == Backdrop::None {
+            0.0
+        } else {
+            self.padding
+        };
+        let (bw, bh) = (w + pad * 2.0, h + pad * 2.0);
+        Placement {
+            logo: [cx - w / 2.0, cy - h / 2.0, w, h],
+            backdrop: [cx - bw / 2.0, cy - bh / 2.0, bw, bh],
+            radius: match self.backdrop {
+                Backdrop::Rounded => bw.min(bh) / 4.0,
+                _ => 0.0,
+            },
+        }
+    }
+
+    /// 徽标及背板遮挡的符号面积比例
+    pub fn coverage(&self, matrix: &Matrix) -> f32 {
+        let style = Style {
+            quiet_zone: 0,
+            module_size: 1,
+            foreground: Rgba([0, 0, 0, 255]),
+            background: Rgba([255, 255, 255, 255]),
+        };
+        let [_, _, w, h] = self.placement(matrix, &style).backdrop;
+        (w * h / (matrix.width * matrix.height) as f32).min(1.0)
+    }
+}
+
+pub fn render_image(matrix: &Matrix, style: &Style, logo: Option<&Logo>) -> RgbaImage {
+    let (w, h) = style.checked_size(matrix);
+    let (size, qz) = (style.module_size, style.quiet_zone as i64);
+    let mut img = RgbaImage::from_fn(w, h, |x, y| {
+        let mx = (x / size) as i64 - qz;
+        let my = (y / size) as i64 - qz;
+        if matrix.is_dark(mx, my) {
+            style.foreground
+        } else {
+            style.background
+        }
+    });
+    if let Some(logo) = logo {
+        overlay_logo(&mut img, matrix, style, logo);
+    }
+    img
+}
+
+fn overlay_logo(img: &mut RgbaImage, matrix: &Matrix, style: &Style, logo: &Logo) {
+    let placement = logo.placement(matrix, style);
+    let px = |v: f32| (v * style.module_size as f32).round() as u32;
+
+    if logo.backdrop != Backdrop::None {
+        let [bx, by, bw, bh] = placement.backdrop.map(px);
+        let r = placement.radius * style.module_size as f32;
+        for y in by..(by + bh).min(img.height()) {
+            for x in bx..(bx + bw).min(img.width()) {
+                // 圆角外的像素到最近圆心的距离超过半径
+                let dx = (r - (x - bx) as f32 - 0.5)
+                    .max((x - bx) as f32 + 0.5 - (bw as f32 - r))
+                    .max(0.0);
+                let dy = (r - (y - by) as f32 - 0.5)
+                    .max((y - by) as f32 + 0.5 - (bh as f32 - r))
+                    .max(0.0);
+                if dx * dx + dy * dy <= r * r {
+                    img.put_pixel(x, y, style.background);
+                }
+            }
+        }
+    }
+
+    let [lx, ly, lw, lh] = placement.logo.map(px);
+    let resized = imageops::resize(&logo.image, lw.max(1), lh.max(1), FilterType::Lanczos3);
+    imageops::overlay(img, &resized, lx as i64, ly as i64);
+}
+
+pub fn encode_png(img: &RgbaImage) -> image::ImageResult<Vec<u8>> {
+    let mut data = Vec::new();
+    img.write_to(&mut Cursor::new(&mut data), ImageFormat::Png)?;
+    Ok(data)
+}
+
+fn hex_color(color: Rgba<u8>) -> String {
+    format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2])
+}
+
+// PostScript/PDF 的 0-1 浮点颜色分量，不支持透明度
+fn float_color(color: Rgba<u8>) -> String {
+    let c = |v: u8| format!("{:.3}", v as f32 / 255.0);
+    format!("{} {} {}", c(color[0]), c(color[1]), c(color[2]))
+}
+
+pub fn render_svg(matrix: &Matrix, style: &Style, logo: Option<&Logo>) -> image::ImageResult<String> {
+    let (w, h) = style.checked_size(matrix);
+    let qz = style.quiet_zone as usize;
+    let opacity = |color: Rgba<u8>| {
+        if color[3] == 255 {
+            String::new()
+        } else {
+            format!(r#" fill-opacity="{:.3}""#, color[3] as f32 / 255.0)
+        }
+    };
+
+    let mut path = String::new();
+    for (y, x, len) in matrix.dark_runs() {
+        let _ = write!(path, "M{} {}h{}v1h-{}z", x + qz, y + qz, len, len);
+    }
+
+    let mut svg = String::new();
+    let _ = writeln!(svg, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" version="1.1" width="{}" height="{}" viewBox="0 0 {} {}" shape-rendering="crispEdges">"#,
+        w,
+        h,
+        matrix.width + qz * 2,
+        matrix.height + qz * 2
+    );
+    let _ = writeln!(
+        svg,
+        r#"<rect width="100%" height="100%" fill="{}"{}/>"#,
+        hex_color(style.background),
+        opacity(style.background)
+    );
+    let _ = writeln!(
+        svg,
+        r#"<path fill="{}"{} d="{}"/>"#,
+        hex_color(style.foreground),
+        opacity(style.foreground),
+        path
+    );
+    if let Some(logo) = logo {
+        let placement = logo.placement(matrix, style);
+        if logo.backdrop != Backdrop::None {
+            let [x, y, w, h] = placement.backdrop;
+            let _ = writeln!(
+                svg,
+                r#"<rect x="{}" y="{}" width="{}" height="{}" rx="{}" fill="{}"{}/>"#,
+                x,
+                y,
+                w,
+                h,
+                placement.radius,
+                hex_color(style.background),
+                opacity(style.background)
+            );
+        }
+        // 徽标以 PNG data URI 内嵌，保持文件自包含
+        let [x, y, w, h] = placement.logo;
+        let _ = writeln!(
+            svg,
+            r#"<image x="{}" y="{}" width="{}" height="{}" href="data:image/png;base64,{}"/>"#,
+            x,
+            y,
+            w,
+            h,
+            BASE64.encode(&encode_png(&logo.image)?)
+        );
+    }
+    svg.push_str("</svg>\n");
+    Ok(svg)
+}
+
+// PostScript 与 PDF 共用的矩形填充指令，坐标原点在左下角
+fn vector_rects(matrix: &Matrix, style: &Style, rect_op: &str) -> String {
+    let (_, h) = style.checked_size(matrix);
+    let (size, qz) = (style.module_size as usize, style.quiet_zone as usize);
+    let mut out = String::new();
+    for (y, x, len) in matrix.dark_runs() {
+        let px = (x + qz) * size;
+        let py = h as usize - (y + qz + 1) * size;
+        let _ = writeln!(out, "{} {} {} {} {}", px, py, len * size, size, rect_op);
+    }
+    out
+}
+
+pub fn render_eps(matrix: &Matrix, style: &Style) -> String {
+    let (w, h) = style.checked_size(matrix);
+    let mut eps = String::new();
+    let _ = writeln!(eps, "%!PS-Adobe-3.0 EPSF-3.0");
+    let _ = writeln!(eps, "%%BoundingBox: 0 0 {} {}", w, h);
+    let _ = writeln!(eps, "%%Creator: qrcode");
+    let _ = writeln!(eps, "%%EndComments");
+    let _ = writeln!(eps, "{} setrgbcolor", float_color(style.background));
+    let _ = writeln!(eps, "0 0 {} {} rectfill", w, h);
+    let _ = writeln!(eps, "{} setrgbcolor", float_color(style.foreground));
+    eps.push_str(&vector_rects(matrix, style, "rectfill"));
+    eps.push_str("%%EOF\n");
+    eps
+}
+
+pub fn render_pdf(matrix: &Matrix, style: &Style) -> Vec<u8> {
+    let (w, h) = style.checked_size(matrix);
+    let mut content = String::new();
+    let _ = writeln!(content, "{} rg", float_color(style.background));
+    let _ = writeln!(content, "0 0 {} {} re f", w, h);
+    let _ = writeln!(content, "{} rg", float_color(style.foreground));
+    content.push_str(&vector_rects(matrix, style, "re"));
+    content.push_str("f\n");
+
+    let objects = [
+        "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+        "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+        format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Contents 4 0 R /Resources << >> >>",
+            w, h
+        ),
+        format!("<< /Length {} >>\nstream\n{}endstream", content.len(), content),
+    ];
+
+    // 交叉引用表需要记录每个对象的字节偏移
+    let mut pdf = String::from("%PDF-1.4\n");
+    let mut offsets = Vec::new();
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        let _ = writeln!(pdf, "{} 0 obj\n{}\nendobj", i + 1, object);
+    }
+    let xref = pdf.len();
+    let _ = writeln!(pdf, "xref\n0 {}", objects.len() + 1);
+    pdf.push_str("0000000000 65535 f \n");
+    for offset in offsets {
+        let _ = writeln!(pdf, "{:010} 00000 n ", offset);
+    }
+    let _ = writeln!(pdf, "trailer\n<< /Size {} /Root 1 0 R >>", objects.len() + 1);
+    let _ = writeln!(pdf, "startxref\n{}\n%%EOF", xref);
+    pdf.into_bytes()
+}
+
+/// 终端半块字符输出，浅色模块绘制为方块，适合深色背景的终端
+pub fn render_unicode(matrix: &Matrix, style: &Style) -> String {
+    let qz = style.quiet_zone as i64;
+    let (w, h) = (matrix.width as i64 + qz * 2, matrix.height as i64 + qz * 2);
+    let mut out = String::new();
+    for y in (0..h).step_by(2) {
+        for x in 0..w {
+            let top = !matrix.is_dark(x - qz, y - qz);
+            let bottom = y + 1 < h && !matrix.is_dark(x - qz, y + 1 - qz);
+            out.push(match (top, bottom) {
+                (true, true) => '█',
+                (true, false) => '▀',
+                (false, true) => '▄',
+                (false, false) => ' ',
+            });
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// 使用 24 位 ANSI 颜色的半块字符输出，上半块为前景色、下半块为背景色
+pub fn render_ansi(matrix: &Matrix, style: &Style) -> String {
+    let qz = style.quiet_zone as i64;
+    let (w, h) = (matrix.width as i64 + qz * 2, matrix.height as i64 + qz * 2);
+    let color = |dark: bool| if dark { style.foreground } else { style.background };
+    let mut out = String::new();
+    for y in (0..h).step_by(2) {
+        for x in 0..w {
+            let top = color(matrix.is_dark(x - qz, y - qz));
+            let bottom = color(y + 1 < h && matrix.is_dark(x - qz, y + 1 - qz));
+            let _ = write!(
+                out,
+                "\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m▀",
+                top[0], top[1], top[2], bottom[0], bottom[1], bottom[2]
+            );
+        }
+        out.push_str("\x1b[0m\n");
+    }
+    out
+}
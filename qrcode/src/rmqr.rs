@@ -0,0 +1,435 @@
+use qrcode::EcLevel;
+use qrcode::ec::create_error_correction_code;
+
+use crate::render::Matrix;
+
+// 各版本的 (高, 宽)，下标加一为版本号，依 ISO/IEC 23941 的 R7x43 到 R17x139
+const SIZES: [(usize, usize); 32] = [
+    (7, 43),
+    (7, 59),
+    (7, 77),
+    (7, 99),
+    (7, 139),
+    (9, 43),
+    (9, 59),
+    (9, 77),
+    (9, 99),
+    (9, 139),
+    (11, 27),
+    (11, 43),
+    (11, 59),
+    (11, 77),
+    (11, 99),
+    (11, 139),
+    (13, 27),
+    (13, 43),
+    (13, 59),
+    (13, 77),
+    (13, 99),
+    (13, 139),
+    (15, 43),
+    (15, 59),
+    (15, 77),
+    (15, 99),
+    (15, 139),
+    (17, 43),
+    (17, 59),
+    (17, 77),
+    (17, 99),
+    (17, 139),
+];
+
+// 纠错块划分：(每块纠错码字数, 块数, 每块数据码字数, 块数, 每块数据码字数)
+type Blocks = (usize, usize, usize, usize, usize);
+
+// 各版本依次为 M、H 级的纠错块划分
+#[rustfmt::skip]
+const BLOCKS: [[Blocks; 2]; 32] = [
+    [(7, 1, 6, 0, 0), (10, 1, 3, 0, 0)],
+    [(9, 1, 12, 0, 0), (14, 1, 7, 0, 0)],
+    [(12, 1, 20, 0, 0), (22, 1, 10, 0, 0)],
+    [(16, 1, 28, 0, 0), (30, 1, 14, 0, 0)],
+    [(24, 1, 44, 0, 0), (22, 2, 12, 0, 0)],
+    [(9, 1, 12, 0, 0), (14, 1, 7, 0, 0)],
+    [(12, 1, 21, 0, 0), (22, 1, 11, 0, 0)],
+    [(18, 1, 31, 0, 0), (16, 1, 8, 1, 9)],
+    [(24, 1, 42, 0, 0), (22, 2, 11, 0, 0)],
+    [(18, 1, 31, 1, 32), (22, 3, 11, 0, 0)],
+    [(8, 1, 7, 0, 0), (10, 1, 5, 0, 0)],
+    [(12, 1, 19, 0, 0), (20, 1, 11, 0, 0)],
+    [(16, 1, 31, 0, 0), (16, 1, 7, 1, 8)],
+    [(24, 1, 43, 0, 0), (22, 1, 11, 1, 12)],
+    [(16, 1, 28, 1, 29), (30, 1, 14, 1, 15)],
+    [(24, 2, 42, 0, 0), (30, 3, 14, 0, 0)],
+    [(9, 1, 12, 0, 0), (14, 1, 7, 0, 0)],
+    [(14, 1, 27, 0, 0), (28, 1, 13, 0, 0)],
+    [(22, 1, 38, 0, 0), (20, 2, 10, 0, 0)],
+    [(16, 1, 26, 1, 27), (28, 1, 14, 1, 15)],
+    [(20, 1, 36, 1, 37), (26, 1, 11, 2, 12)],
+    [(20, 2, 35, 1, 36), (28, 2, 13, 2, 14)],
+    [(18, 1, 33, 0, 0), (18, 1, 7, 1, 8)],
+    [(26, 1, 48, 0, 0), (24, 2, 13, 0, 0)],
+    [(18, 1, 33, 1, 34), (24, 2, 10, 1, 11)],
+    [(24, 2, 44, 0, 0), (22, 4, 12, 0, 0)],
+    [(24, 2, 42, 1, 43), (26, 1, 13, 4, 14)],
+    [(22, 1, 39, 0, 0), (20, 1, 10, 1, 11)],
+    [(16, 2, 28, 0, 0), (30, 2, 14, 0, 0)],
+    [(22, 2, 39, 0, 0), (28, 1, 12, 2, 13)],
+    [(20, 2, 33, 1, 34), (26, 4, 14, 0, 0)],
+    [(20, 4, 38, 0, 0), (26, 2, 12, 4, 13)],
+];
+
+// 字符数指示符的位数
+#[rustfmt::skip]
+const NUMERIC_BITS: [usize; 32] = [4, 5, 6, 7, 7, 5, 6, 7, 7, 8, 4, 6, 7, 7, 8, 8, 5, 6, 7, 7, 8, 8, 7, 7, 8, 8, 9, 7, 8, 8, 8, 9];
+#[rustfmt::skip]
+const ALPHANUMERIC_BITS: [usize; 32] = [3, 5, 5, 6, 6, 5, 5, 6, 6, 7, 4, 5, 6, 6, 7, 7, 5, 6, 6, 7, 7, 8, 6, 7, 7, 7, 8, 6, 7, 7, 8, 8];
+#[rustfmt::skip]
+const BYTE_BITS: [usize; 32] = [3, 4, 5, 5, 6, 4, 5, 5, 6, 6, 3, 5, 5, 6, 6, 7, 4, 5, 6, 6, 7, 7, 6, 6, 7, 7, 7, 6, 6, 7, 7, 8];
+
+const ALPHANUMERIC: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ $%*+-./:";
+
+// 格式信息的 BCH 生成多项式及两侧的掩码
+const FORMAT_GENERATOR: u32 = 0x1f25;
+const FORMAT_MASK: u32 = 0x1fab2;
+const FORMAT_MASK_SUB: u32 = 0x20a7b;
+
+#[derive(Clone, Copy)]
+enum Mode {
+    Numeric,
+    Alphanumeric,
+    Byte,
+}
+
+impl Mode {
+    // 整段内容使用能容纳全部字符的最紧凑模式
+    fn for_data(data: &[u8]) -> Self {
+        if data.iter().all(u8::is_ascii_digit) {
+            Mode::Numeric
+        } else if data.iter().all(|b| ALPHANUMERIC.contains(b)) {
+            Mode::Alphanumeric
+        } else {
+            Mode::Byte
+        }
+    }
+
+    fn indicator(self) -> u32 {
+        match self {
+            Mode::Numeric => 0b001,
+            Mode::Alphanumeric => 0b010,
+            Mode::Byte => 0b011,
+        }
+    }
+
+    fn count_bits(self, index: usize) -> usize {
+        match self {
+            Mode::Numeric => NUMERIC_BITS[index],
+            Mode::Alphanumeric => ALPHANUMERIC_BITS[index],
+            Mode::Byte => BYTE_BITS[index],
+        }
+    }
+}
+
+/// 版本号对应的尺寸名称，如 `R11x43`
+pub fn size_name(version: usize) -> String {
+    let (height, width) = SIZES[version - 1];
+    format!("R{}x{}", height, width)
+}
+
+/// 生成 rMQR 符号，只支持 M、H 级纠错；未指定版本时选择面积最小的可容纳版本
+pub fn encode(data: &[u8], ecc: EcLevel, version: Option<i16>) -> Result<Matrix, String> {
+    let level = match ecc {
+        EcLevel::M => 0,
+        EcLevel::H => 1,
+        _ => return Err("rmqr only supports error correction levels m and h".to_string()),
+    };
+    let mut candidates: Vec<usize> = match version {
+        Some(v @ 1..=32) => vec![v as usize - 1],
+        Some(v) => return Err(format!("invalid rmqr version {}, expected 1-32", v)),
+        None => (0..SIZES.len()).collect(),
+    };
+    candidates.sort_by_key(|&i| (SIZES[i].0 * SIZES[i].1, SIZES[i].0));
+
+    let mode = Mode::for_data(data);
+    for index in candidates {
+        if let Some(codewords) = codewords(data, mode, index, level) {
+            return Ok(place(index, level, &codewords));
+        }
+    }
+    Err(match version {
+        Some(v) => format!("data too long for rmqr version {} ({})", v, size_name(v as usize)),
+        None => "data too long for rmqr".to_string(),
+    })
+}
+
+// 编码数据并交织纠错码字，放不下时返回 None
+fn codewords(data: &[u8], mode: Mode, index: usize, level: usize) -> Option<Vec<u8>> {
+    let (ec, n1, d1, n2, d2) = BLOCKS[index][level];
+    let capacity = (n1 * d1 + n2 * d2) * 8;
+    let count_bits = mode.count_bits(index);
+    if data.len() >= 1 << count_bits {
+        return None;
+    }
+
+    let mut bits = Bits::default();
+    bits.push(mode.indicator(), 3);
+    bits.push(data.len() as u32, count_bits);
+    match mode {
+        Mode::Numeric => {
+            for chunk in data.chunks(3) {
+                let value = chunk.iter().fold(0, |v, b| v * 10 + (b - b'0') as u32);
+                bits.push(value, chunk.len() * 3 + 1);
+            }
+        }
+        Mode::Alphanumeric => {
+            let value = |b: &u8| ALPHANUMERIC.iter().position(|c| c == b).unwrap() as u32;
+            for chunk in data.chunks(2) {
+                match chunk {
+                    [a, b] => bits.push(value(a) * 45 + value(b), 11),
+                    [a] => bits.push(value(a), 6),
+                    _ => unreachable!(),
+                }
+            }
+        }
+        Mode::Byte => data.iter().for_each(|&b| bits.push(b as u32, 8)),
+    }
+    if bits.len() > capacity {
+        return None;
+    }
+
+    // 终止符最多 3 位，补齐到字节后交替填充 0xEC、0x11
+    bits.push(0, (capacity - bits.len()).min(3));
+    bits.push(0, (8 - bits.len() % 8) % 8);
+    let mut data = bits.into_bytes();
+    for pad in [0xec, 0x11].into_iter().cycle() {
+        if data.len() * 8 >= capacity {
+            break;
+        }
+        data.push(pad);
+    }
+
+    let mut blocks = Vec::new();
+    let mut rest = data.as_slice();
+    for len in std::iter::repeat_n(d1, n1).chain(std::iter::repeat_n(d2, n2)) {
+        let (block, tail) = rest.split_at(len);
+        blocks.push((block, create_error_correction_code(block, ec)));
+        rest = tail;
+    }
+    let mut out = Vec::new();
+    for i in 0..d1.max(d2) {
+        out.extend(blocks.iter().filter_map(|(block, _)| block.get(i)));
+    }
+    for i in 0..ec {
+        out.extend(blocks.iter().map(|(_, ecc)| ecc[i]));
+    }
+    Some(out)
+}
+
+#[derive(Default)]
+struct Bits(Vec<bool>);
+
+impl Bits {
+    fn push(&mut self, value: u32, len: usize) {
+        self.0.extend((0..len).rev().map(|i| value >> i & 1 == 1));
+    }
+
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn into_bytes(self) -> Vec<u8> {
+        self.0
+            .chunks(8)
+            .map(|byte| byte.iter().fold(0, |v, &bit| v << 1 | bit as u8))
+            .collect()
+    }
+}
+
+// 模块矩阵及功能图案占用的位置
+struct Grid {
+    width: usize,
+    dark: Vec<bool>,
+    function: Vec<bool>,
+}
+
+impl Grid {
+    fn set(&mut self, x: usize, y: usize, dark: bool) {
+        let i = y * self.width + x;
+        self.dark[i] = dark;
+        self.function[i] = true;
+    }
+}
+
+// 绘制功能图案、格式信息，并按两列一组、自右向左蛇形放置码字
+fn place(index: usize, level: usize, codewords: &[u8]) -> Matrix {
+    let (height, width) = SIZES[index];
+    let mut grid = Grid {
+        width,
+        dark: vec![false; width * height],
+        function: vec![false; width * height],
+    };
+
+    // 四边的定时图案
+    for x in 0..width {
+        grid.set(x, 0, x % 2 == 0);
+        grid.set(x, height - 1, x % 2 == 0);
+    }
+    for y in 0..height {
+        grid.set(0, y, y % 2 == 0);
+        grid.set(width - 1, y, y % 2 == 0);
+    }
+
+    // 对齐图案及其间的竖向定时图案
+    for &cx in alignment_centers(width) {
+        for y in 3..height - 3 {
+            grid.set(cx, y, y % 2 == 0);
+        }
+        for (dx, dy) in (0..3).flat_map(|dx| (0..3).map(move |dy| (dx, dy))) {
+            let ring = dx != 1 || dy != 1;
+            grid.set(cx + dx - 1, dy, ring);
+            grid.set(cx + dx - 1, height - 3 + dy, ring);
+        }
+    }
+
+    // 左上角定位图案，R7 的底边与下边缘重合
+    for y in 0..7.min(height) {
+        for x in 0..7 {
+            let ring = (x as i32 - 3).abs().max((y as i32 - 3).abs());
+            grid.set(x, y, ring != 2);
+        }
+    }
+    // 右下角的定位子图案
+    for y in 0..5 {
+        for x in 0..5 {
+            let ring = (x as i32 - 2).abs().max((y as i32 - 2).abs());
+            grid.set(width - 5 + x, height - 5 + y, ring != 1);
+        }
+    }
+    // 另外两角的角落图案
+    grid.set(width - 2, 0, true);
+    grid.set(width - 2, 1, true);
+    grid.set(width - 1, 1, true);
+    grid.set(0, height - 2, true);
+    grid.set(1, height - 2, false);
+    grid.set(1, height - 1, true);
+    // 定位图案的分隔符
+    for y in 0..8.min(height) {
+        grid.set(7, y, false);
+    }
+    if height > 7 {
+        for x in 0..8 {
+            grid.set(x, 7, false);
+        }
+    }
+
+    // 格式信息：第 5 位为纠错等级，低 5 位为版本号减一
+    let format = bch((level << 5 | index) as u32);
+    for i in 0..18 {
+        // 前 15 位两侧均为 3 列 5 行，其余 3 位在定位图案一侧竖排、在子图案一侧横排
+        let ((x, y), (sx, sy)) = if i < 15 {
+            ((8 + i / 5, 1 + i % 5), (width - 8 + i / 5, height - 6 + i % 5))
+        } else {
+            ((11, 1 + i - 15), (width - 5 + i - 15, height - 6))
+        };
+        grid.set(x, y, (format ^ FORMAT_MASK) >> i & 1 == 1);
+        grid.set(sx, sy, (format ^ FORMAT_MASK_SUB) >> i & 1 == 1);
+    }
+
+    let bits = codewords
+        .iter()
+        .flat_map(|&b| (0..8).rev().map(move |i| b >> i & 1 == 1));
+    let mut bits = bits.chain(std::iter::repeat(false));
+    let mut upward = true;
+    for right in (1..width - 1).rev().step_by(2) {
+        for row in 0..height {
+            let y = if upward { height - 1 - row } else { row };
+            for x in [right, right - 1] {
+                let i = y * width + x;
+                if !grid.function[i] {
+                    // 固定使用 (y / 2 + x / 3) % 2 == 0 的掩码
+                    grid.dark[i] = bits.next().unwrap() ^ ((y / 2 + x / 3) % 2 == 0);
+                }
+            }
+        }
+        upward = !upward;
+    }
+    Matrix::new(width, height, grid.dark)
+}
+
+fn alignment_centers(width: usize) -> &'static [usize] {
+    match width {
+        43 => &[21],
+        59 => &[19, 39],
+        77 => &[25, 51],
+        99 => &[23, 49, 75],
+        139 => &[27, 55, 83, 111],
+        _ => &[],
+    }
+}
+
+// 6 位数据加 12 位 BCH 校验
+fn bch(data: u32) -> u32 {
+    let mut rem = data << 12;
+    for i in (12..18).rev() {
+        if rem >> i & 1 == 1 {
+            rem ^= FORMAT_GENERATOR << (i - 12);
+        }
+    }
+    data << 12 | rem
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{DynamicImage, Rgba};
+
+    use super::*;
+    use crate::decode::ScanOptions;
+    use crate::preprocess::STRATEGIES;
+    use crate::render::{self, Style};
+    use crate::symbology::{self, Symbology};
+
+    fn scan(matrix: &Matrix) -> Vec<String> {
+        let style = Style {
+            quiet_zone: 2,
+            module_size: 4,
+            foreground: Rgba([0, 0, 0, 255]),
+            background: Rgba([255, 255, 255, 255]),
+        };
+        let img = DynamicImage::ImageRgba8(render::render_image(matrix, &style, None)).to_luma8();
+        let options = ScanOptions {
+            symbologies: vec![Symbology::Rmqr],
+            ..Default::default()
+        };
+        symbology::scan_barcodes(&img, &STRATEGIES[0], &options)
+            .into_iter()
+            .map(|r| r.content)
+            .collect()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for version in 1..=32 {
+            for ecc in [EcLevel::M, EcLevel::H] {
+                let matrix = encode(b"7", ecc, Some(version)).unwrap();
+                assert_eq!((matrix.height, matrix.width), SIZES[version as usize - 1]);
+                assert_eq!(scan(&matrix), ["7"], "{}", size_name(version as usize));
+            }
+        }
+        let text = "https://example.com/label?id=42";
+        assert_eq!(scan(&encode(text.as_bytes(), EcLevel::H, None).unwrap()), [text]);
+    }
+
+    #[test]
+    fn test_version_selection() {
+        // 数字模式比字节模式紧凑，选用更小的符号
+        let numeric = encode(b"0123456789012", EcLevel::M, None).unwrap();
+        let bytes = encode(b"abcdefghijklm", EcLevel::M, None).unwrap();
+        assert_eq!((numeric.height, numeric.width), (11, 27));
+        assert!(bytes.width * bytes.height > numeric.width * numeric.height);
+
+        assert!(encode(b"1", EcLevel::L, None).is_err());
+        assert!(encode(b"1", EcLevel::M, Some(33)).is_err());
+        assert!(encode(&[b'a'; 20], EcLevel::M, Some(1)).is_err());
+        assert!(encode(&[b'a'; 400], EcLevel::H, None).is_err());
+    }
+}
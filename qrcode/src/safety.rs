@@ -0,0 +1,344 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use url::{Host, Url};
+
+use crate::decode::Decoded;
+use crate::payload::Payload;
+
+// 编译进程序的默认规则，可用 --url-rules 指定更新后的规则文件
+const BUILTIN_RULES: &str = include_str!("../rules/url_safety.json");
+
+/// 网址安全检查规则
+#[derive(Debug, Deserialize)]
+pub struct Rules {
+    /// 打开后直接执行或加载内容的协议，不含冒号
+    pub dangerous_schemes: Vec<String>,
+    /// 短链接域名，子域名同样匹配
+    pub shorteners: Vec<String>,
+    /// 常被用于钓鱼的顶级域名，不含点
+    pub suspicious_tlds: Vec<String>,
+}
+
+impl Rules {
+    pub fn builtin() -> Self {
+        serde_json::from_str(BUILTIN_RULES).expect("builtin url rules are valid")
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        serde_json::from_str(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    None,
+    Low,
+    Medium,
+    High,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::None => "none",
+            Severity::Low => "low",
+            Severity::Medium => "medium",
+            Severity::High => "high",
+        })
+    }
+}
+
+/// 单项检查发现的问题
+#[derive(Debug, Serialize)]
+pub struct Finding {
+    pub check: &'static str,
+    pub severity: Severity,
+    pub detail: String,
+}
+
+/// 网址的风险报告，`level` 为所有问题中最高的严重程度
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub level: Severity,
+    pub findings: Vec<Finding>,
+}
+
+/// 内容为网址时附加风险报告
+pub fn inspect(decoded: &mut Decoded, rules: &Rules) {
+    if let Payload::Url { url, title } = &decoded.payload
+        && let Ok(url) = Url::parse(url)
+    {
+        decoded.safety = Some(analyze(&url, title.as_deref(), rules));
+    }
+}
+
+/// 离线检查网址，`title` 为扫码应用显示给用户的文字
+pub fn analyze(url: &Url, title: Option<&str>, rules: &Rules) -> Report {
+    let mut findings = Vec::new();
+    let mut add = |check, severity, detail: String| {
+        findings.push(Finding {
+            check,
+            severity,
+            detail,
+        })
+    };
+
+    let scheme = url.scheme();
+    if rules.dangerous_schemes.iter().any(|s| s.eq_ignore_ascii_case(scheme)) {
+        add(
+            "dangerous_scheme",
+            Severity::High,
+            format!("{}: urls run or load content when opened", scheme),
+        );
+    }
+
+    let host = url
+        .host_str()
+        .unwrap_or_default()
+        .trim_end_matches('.')
+        .to_ascii_lowercase();
+    if !url.username().is_empty() || url.password().is_some() {
+        add(
+            "credentials",
+            Severity::High,
+            format!("url contains user info '{}', the real host is {}", url.username(), host),
+        );
+    }
+
+    match url.host() {
+        Some(Host::Ipv4(_) | Host::Ipv6(_)) => {
+            add(
+                "ip_host",
+                Severity::Medium,
+                format!("host is a raw ip address {}", host),
+            );
+        }
+        Some(Host::Domain(_)) => {
+            check_idn(&host, &mut add);
+            if let Some(shortener) = rules.shorteners.iter().find(|d| is_same_or_subdomain(&host, d)) {
+                add(
+                    "shortener",
+                    Severity::Medium,
+                    format!("{} is a url shortener hiding the destination", shortener),
+                );
+            }
+            let tld = host.rsplit('.').next().unwrap_or_default();
+            if rules.suspicious_tlds.iter().any(|t| t.eq_ignore_ascii_case(tld)) {
+                add(
+                    "suspicious_tld",
+                    Severity::Medium,
+                    format!(".{} is frequently used for phishing", tld),
+                );
+            }
+        }
+        None => {}
+    }
+
+    if let Some(title) = title.filter(|_| !host.is_empty()) {
+        for shown in domains_in(title) {
+            if !is_same_or_subdomain(&host, &shown) && !is_same_or_subdomain(&shown, &host) {
+                add(
+                    "display_mismatch",
+                    Severity::High,
+                    format!("title shows {} but the link opens {}", shown, host),
+                );
+            }
+        }
+    }
+
+    Report {
+        level: findings.iter().map(|f| f.severity).max().unwrap_or(Severity::None),
+        findings,
+    }
+}
+
+// 检查 punycode 主机名解码后的文字：混用多种文字或整段使用与拉丁字母形似的字母
+fn check_idn(host: &str, add: &mut impl FnMut(&'static str, Severity, String)) {
+    if !host.split('.').any(|label| label.starts_with("xn--")) {
+        return;
+    }
+    let (unicode, _) = idna::domain_to_unicode(host);
+    let mut suspicious = false;
+    for label in unicode.split('.') {
+        let scripts: BTreeSet<Script> = label.chars().filter_map(script).collect();
+        let skeleton: String = label.chars().map(|c| latin_lookalike(c).unwrap_or(c)).collect();
+        if !is_allowed_mix(&scripts) {
+            suspicious = true;
+            add(
+                "mixed_scripts",
+                Severity::High,
+                format!("'{}' mixes {:?} letters", label, scripts),
+            );
+        } else if !scripts.contains(&Script::Latin) && skeleton != label && skeleton.is_ascii() {
+            suspicious = true;
+            add(
+                "homograph",
+                Severity::High,
+                format!("'{}' imitates the latin '{}'", label, skeleton),
+            );
+        }
+    }
+    if !suspicious {
+        add(
+            "idn",
+            Severity::Low,
+            format!("internationalized host {} is shown as {}", host, unicode),
+        );
+    }
+}
+
+// host 与 domain 相同或为其子域名
+fn is_same_or_subdomain(host: &str, domain: &str) -> bool {
+    let domain = domain.trim_start_matches("www.");
+    let host = host.trim_start_matches("www.");
+    host.eq_ignore_ascii_case(domain)
+        || (host.len() > domain.len()
+            && host[host.len() - domain.len()..].eq_ignore_ascii_case(domain)
+            && host.as_bytes()[host.len() - domain.len() - 1] == b'.')
+}
+
+// 文字中形如域名的片段，顶级域名至少两个字母
+fn domains_in(text: &str) -> Vec<String> {
+    text.split(|c: char| c.is_whitespace() || "()[]<>\"',;|/".contains(c))
+        .filter_map(|word| {
+            let word = word.trim_matches(|c: char| c == '.' || c == ':').to_ascii_lowercase();
+            let labels: Vec<&str> = word.split('.').collect();
+            let tld = labels.last()?;
+            let valid = labels.len() >= 2
+                && tld.len() >= 2
+                && tld.chars().all(|c| c.is_ascii_alphabetic())
+                && labels
+                    .iter()
+                    .all(|l| !l.is_empty() && l.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'));
+            valid.then_some(word)
+        })
+        .collect()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Script {
+    Latin,
+    Greek,
+    Cyrillic,
+    Armenian,
+    Hebrew,
+    Arabic,
+    Thai,
+    Hangul,
+    Hiragana,
+    Katakana,
+    Han,
+    Other,
+}
+
+// 字母所属的文字，数字和连字符等通用字符返回 None
+fn script(c: char) -> Option<Script> {
+    let script = match c as u32 {
+        _ if !c.is_alphabetic() => return None,
+        0x41..=0x5a | 0x61..=0x7a | 0xc0..=0x24f | 0x1e00..=0x1eff => Script::Latin,
+        0x370..=0x3ff | 0x1f00..=0x1fff => Script::Greek,
+        0x400..=0x52f | 0x2de0..=0x2dff | 0xa640..=0xa69f => Script::Cyrillic,
+        0x530..=0x58f => Script::Armenian,
+        0x590..=0x5ff => Script::Hebrew,
+        0x600..=0x6ff | 0x750..=0x77f => Script::Arabic,
+        0xe00..=0xe7f => Script::Thai,
+        0x1100..=0x11ff | 0x3130..=0x318f | 0xac00..=0xd7af => Script::Hangul,
+        0x3040..=0x309f => Script::Hiragana,
+        0x30a0..=0x30ff => Script::Katakana,
+        0x3400..=0x4dbf | 0x4e00..=0x9fff | 0x20000..=0x2a6df => Script::Han,
+        _ => Script::Other,
+    };
+    Some(script)
+}
+
+// 与 UTS #39 的 Highly Restrictive 级别一致：单一文字，或拉丁字母与中日韩文字的常见组合
+fn is_allowed_mix(scripts: &BTreeSet<Script>) -> bool {
+    use Script::*;
+    scripts.len() <= 1
+        || scripts.iter().all(|s| matches!(s, Latin | Han | Hiragana | Katakana))
+        || scripts.iter().all(|s| matches!(s, Latin | Han | Hangul))
+}
+
+// 与拉丁小写字母形似的西里尔、希腊字母
+fn latin_lookalike(c: char) -> Option<char> {
+    let latin = match c {
+        'а' | 'α' => 'a',
+        'с' | 'ϲ' => 'c',
+        'ԁ' => 'd',
+        'е' | 'ε' => 'e',
+        'һ' => 'h',
+        'і' | 'ι' | 'ӏ' => 'i',
+        'ј' => 'j',
+        'к' | 'κ' => 'k',
+        'м' => 'm',
+        'п' | 'η' => 'n',
+        'о' | 'ο' | 'σ' => 'o',
+        'р' | 'ρ' => 'p',
+        'ԛ' => 'q',
+        'г' => 'r',
+        'ѕ' => 's',
+        'τ' => 't',
+        'υ' => 'u',
+        'ν' | 'ѵ' => 'v',
+        'ԝ' | 'ω' => 'w',
+        'х' | 'χ' => 'x',
+        'у' | 'γ' => 'y',
+        _ => return None,
+    };
+    Some(latin)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checks(url: &str, title: Option<&str>) -> Vec<&'static str> {
+        let report = analyze(&Url::parse(url).unwrap(), title, &Rules::builtin());
+        report.findings.iter().map(|f| f.check).collect()
+    }
+
+    #[test]
+    fn test_clean_url() {
+        let report = analyze(
+            &Url::parse("https://www.example.com/a").unwrap(),
+            None,
+            &Rules::builtin(),
+        );
+        assert_eq!(report.level, Severity::None);
+        assert!(report.findings.is_empty());
+    }
+
+    #[test]
+    fn test_heuristics() {
+        assert_eq!(checks("javascript:alert(1)", None), ["dangerous_scheme"]);
+        assert_eq!(checks("https://paypal.com@evil.example/", None), ["credentials"]);
+        // WHATWG 解析会把十进制 IP 转换为点分形式
+        assert_eq!(checks("http://3232235777/login", None), ["ip_host"]);
+        assert_eq!(checks("https://bit.ly/3abc", None), ["shortener"]);
+        assert_eq!(checks("https://secure-login.zip/", None), ["suspicious_tld"]);
+        assert_eq!(
+            checks("https://evil.example/", Some("Login at www.mybank.com")),
+            ["display_mismatch"]
+        );
+        assert!(checks("https://login.mybank.com/", Some("mybank.com")).is_empty());
+    }
+
+    #[test]
+    fn test_idn() {
+        // 西里尔字母拼出的 "раураl"
+        assert_eq!(checks("https://раураl.com/", None), ["mixed_scripts"]);
+        assert_eq!(checks("https://рсо.com/", None), ["homograph"]);
+        assert_eq!(checks("https://例え.jp/", None), ["idn"]);
+        assert_eq!(
+            analyze(&Url::parse("https://аррӏе.com/").unwrap(), None, &Rules::builtin()).level,
+            Severity::High
+        );
+    }
+}
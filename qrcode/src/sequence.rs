@@ -0,0 +1,152 @@
+use std::collections::BTreeMap;
+
+use encoding_rs::Encoding;
+use serde::Serialize;
+
+use crate::decode::Decoded;
+
+/// 结构化追加序列，同一条消息的符号具有相同的总数和奇偶校验值
+#[derive(Debug, Serialize)]
+pub struct Sequence {
+    pub parity: u8,
+    pub total: u8,
+    /// 已找到的符号序号，从 1 开始
+    pub found: Vec<u8>,
+    pub missing: Vec<u8>,
+    /// 拼接后的内容与奇偶校验值是否一致，序列不完整时为空
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parity_valid: Option<bool>,
+    /// 拼接后的完整消息，版本、纠错等级与预处理策略取自第一个符号
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<Decoded>,
+}
+
+impl Sequence {
+    pub fn is_complete(&self) -> bool {
+        self.missing.is_empty()
+    }
+
+    /// 结果是否属于该序列
+    pub fn contains(&self, decoded: &Decoded) -> bool {
+        decoded
+            .structured_append
+            .is_some_and(|sa| sa.parity == self.parity && sa.total == self.total)
+    }
+}
+
+/// 按奇偶校验值和总数分组结构化追加符号，完整的序列按序号拼接为一条消息
+pub fn reassemble(results: &[Decoded], charset: Option<&'static Encoding>) -> Vec<Sequence> {
+    // 同一符号可能出现在多张图片中，保留第一次出现的结果
+    let mut groups: BTreeMap<(u8, u8), BTreeMap<u8, &Decoded>> = BTreeMap::new();
+    for decoded in results {
+        if let Some(sa) = decoded.structured_append {
+            groups
+                .entry((sa.parity, sa.total))
+                .or_default()
+                .entry(sa.index)
+                .or_insert(decoded);
+        }
+    }
+
+    groups
+        .into_iter()
+        .map(|((parity, total), parts)| {
+            let found: Vec<u8> = parts.keys().copied().collect();
+            let missing: Vec<u8> = (1..=total).filter(|i| !parts.contains_key(i)).collect();
+            let mut sequence = Sequence {
+                parity,
+                total,
+                found,
+                missing,
+                parity_valid: None,
+                message: None,
+            };
+            if sequence.is_complete() {
+                let mut parts = parts.into_values();
+                let first = parts.next().expect("complete sequence has at least one part");
+                let mut stream = first.stream.clone();
+                for part in parts {
+                    stream.append(&part.stream);
+                }
+                stream.structured_append = None;
+                sequence.parity_valid = Some(stream.bytes().iter().fold(0, |acc, b| acc ^ b) == parity);
+                sequence.message = Some(Decoded::new(
+                    stream,
+                    charset,
+                    first.symbology,
+                    first.version,
+                    first.ecc_level.clone(),
+                    first.strategy,
+                ));
+            }
+            sequence
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitstream::{Stream, StructuredAppend};
+    use crate::symbology::Symbology;
+
+    fn part(runs: Vec<(Option<u32>, &[u8])>, index: u8, total: u8, parity: u8) -> Decoded {
+        let stream = Stream {
+            runs: runs.into_iter().map(|(eci, bytes)| (eci, bytes.to_vec())).collect(),
+            structured_append: Some(StructuredAppend { index, total, parity }),
+            ..Default::default()
+        };
+        Decoded::new(stream, None, Symbology::Qr, Some(1), Some("M".to_string()), "original")
+    }
+
+    #[test]
+    fn test_reassemble() {
+        // 多字节字符跨符号拆分
+        let message = "结构化追加 https://example.com".as_bytes();
+        let parity = message.iter().fold(0, |acc, b| acc ^ b);
+        let (a, rest) = message.split_at(4);
+        let (b, c) = rest.split_at(9);
+        let results = vec![
+            part(vec![(None, c)], 3, 3, parity),
+            part(vec![(None, a)], 1, 3, parity),
+            part(vec![(None, b)], 2, 3, parity),
+            part(vec![(None, b)], 2, 3, parity),
+        ];
+
+        let sequences = reassemble(&results, None);
+        assert_eq!(sequences.len(), 1);
+        assert_eq!(sequences[0].parity_valid, Some(true));
+        let decoded = sequences[0].message.as_ref().unwrap();
+        assert_eq!(decoded.content, "结构化追加 https://example.com");
+        assert!(decoded.structured_append.is_none());
+        assert!(sequences[0].contains(&results[0]));
+    }
+
+    #[test]
+    fn test_missing_parts() {
+        let results = vec![
+            part(vec![(None, b"abc")], 2, 4, 0x11),
+            part(vec![(None, b"xyz")], 1, 2, 0x22),
+        ];
+        let sequences = reassemble(&results, None);
+        assert_eq!(sequences.len(), 2);
+        assert_eq!(sequences[0].found, vec![2]);
+        assert_eq!(sequences[0].missing, vec![1, 3, 4]);
+        assert!(sequences[0].message.is_none());
+        assert_eq!(sequences[1].missing, vec![2]);
+    }
+
+    #[test]
+    fn test_eci_carries_over() {
+        let message = [0xd6, 0xd0, 0xce, 0xc4];
+        let parity = message.iter().fold(0, |acc, b| acc ^ b);
+        let results = vec![
+            part(vec![(Some(29), &message[..1])], 1, 2, parity),
+            part(vec![(None, &message[1..])], 2, 2, parity),
+        ];
+        let sequences = reassemble(&results, None);
+        let decoded = sequences[0].message.as_ref().unwrap();
+        assert_eq!(decoded.content, "中文");
+        assert_eq!(decoded.eci, Some(29));
+    }
+}
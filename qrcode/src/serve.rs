@@ -0,0 +1,318 @@
+use std::io::{self, ErrorKind, Read, Write};
+use std::mem;
+use std::path::PathBuf;
+
+use clap::Args;
+use encoding_rs::Encoding;
+use image::GrayImage;
+use serde_json::{Value, json};
+
+use crate::batch::Failure;
+use crate::decode::{self, BufferPool, Decoded, ScanError, ScanOptions};
+use crate::frames;
+use crate::otpauth;
+use crate::safety::{self, Rules};
+use crate::symbology::Symbology;
+
+// 编码图片帧的类型标记，后跟 u32 长度及图片文件内容
+const TAG_IMAGE: u8 = b'I';
+// 灰度帧的类型标记，后跟 u32 宽度、u32 高度及逐行排列的亮度值
+const TAG_LUMA: u8 = b'L';
+// 单帧数据的上限，超过时视为数据流损坏
+const MAX_FRAME: usize = 256 << 20;
+
+#[derive(Args)]
+pub struct ServeArgs {
+    /// Decode byte content with this charset (e.g. gbk, shift_jis), ignoring ECI and detection
+    #[arg(long, value_parser = crate::parse_charset)]
+    charset: Option<&'static Encoding>,
+
+    /// Only look for these symbologies (comma separated); other symbologies slow down every frame
+    #[arg(long, value_enum, value_delimiter = ',', default_value = "qr")]
+    symbologies: Vec<Symbology>,
+
+    /// Try every preprocessing strategy instead of only the plain frame and an Otsu threshold (much slower)
+    #[arg(long)]
+    thorough: bool,
+
+    /// Scan large frames on a downscaled image pyramid first
+    #[arg(long)]
+    pyramid: bool,
+
+    /// Show OTP secrets instead of redacting them
+    #[arg(long)]
+    show_secrets: bool,
+
+    /// Load URL safety rules from this JSON file instead of the built-in ones
+    #[arg(long, value_name = "FILE")]
+    pub url_rules: Option<PathBuf>,
+}
+
+// 数据流中的一帧，内容保存在会话的缓冲区中
+enum Frame {
+    Image,
+    Luma(u32, u32),
+}
+
+// 连续帧之间保留的状态
+struct Session<'a> {
+    options: ScanOptions,
+    rules: &'a Rules,
+    show_secrets: bool,
+    /// 帧数据的缓冲区，各帧复用
+    buffer: Vec<u8>,
+    count: u64,
+    /// 上一帧的结果，不含帧序号
+    last: Option<Value>,
+}
+
+/// 从标准输入逐帧读取图像，每帧在标准输出回应一行 JSON，直到输入结束
+pub fn run(args: ServeArgs, rules: &Rules) -> io::Result<()> {
+    Session::new(args, rules).serve(io::stdin().lock(), io::stdout().lock())
+}
+
+impl<'a> Session<'a> {
+    // 默认只用快速策略识别二维码，视频帧大多没有条码，每帧的耗时决定预览是否流畅
+    fn new(args: ServeArgs, rules: &'a Rules) -> Self {
+        Session {
+            options: ScanOptions {
+                charset: args.charset,
+                symbologies: args.symbologies,
+                pyramid: args.pyramid,
+                quick: !args.thorough,
+                buffers: Some(BufferPool::default()),
+                ..Default::default()
+            },
+            rules,
+            show_secrets: args.show_secrets,
+            buffer: Vec::new(),
+            count: 0,
+            last: None,
+        }
+    }
+
+    fn serve(&mut self, mut input: impl Read, mut output: impl Write) -> io::Result<()> {
+        while let Some(frame) = read_frame(&mut input, &mut self.buffer)? {
+            let line = self.answer(frame);
+            writeln!(output, "{}", line)?;
+            // 宿主按行等待回应，不能停留在缓冲区中
+            output.flush()?;
+        }
+        Ok(())
+    }
+
+    // 与上一帧结果相同时只回应帧序号
+    fn answer(&mut self, frame: Frame) -> Value {
+        let index = self.count;
+        self.count += 1;
+        let body = match self.scan(frame) {
+            Ok(results) => json!({ "results": results }),
+            Err(e) => json!({ "results": [], "error": e.to_string() }),
+        };
+        if self.last.as_ref() == Some(&body) {
+            return json!({ "frame": index, "unchanged": true });
+        }
+        let mut line = json!({ "frame": index });
+        line.as_object_mut().unwrap().extend(body.as_object().unwrap().clone());
+        self.last = Some(body);
+        line
+    }
+
+    // 未找到条码不视为错误，视频帧中大多数时候没有条码
+    fn scan(&mut self, frame: Frame) -> Result<Vec<Decoded>, Failure> {
+        let mut results = Vec::new();
+        let mut failure = None;
+        match frame {
+            Frame::Image => {
+                let images = frames::load(&self.buffer, &self.options.render).map_err(Failure::Open)?;
+                for img in images {
+                    match decode::scan(&img.to_luma8(), &self.options) {
+                        Ok(found) => results.extend(found),
+                        Err(ScanError::NotFound) => {}
+                        Err(e) => failure = Some(Failure::Scan(e)),
+                    }
+                }
+            }
+            Frame::Luma(width, height) => {
+                let img =
+                    GrayImage::from_raw(width, height, mem::take(&mut self.buffer)).expect("frame has the luma size");
+                match decode::scan(&img, &self.options) {
+                    Ok(found) => results = found,
+                    Err(ScanError::NotFound) => {}
+                    Err(e) => failure = Some(Failure::Scan(e)),
+                }
+                self.buffer = img.into_raw();
+            }
+        }
+        if let (Some(e), true) = (failure, results.is_empty()) {
+            return Err(e);
+        }
+        for result in &mut results {
+            otpauth::protect(result, self.show_secrets, false);
+            safety::inspect(result, self.rules);
+        }
+        Ok(results)
+    }
+}
+
+// 读取一帧到 buffer，输入在帧边界结束时返回 None；帧头无效或数据不完整时无法继续同步，返回错误
+fn read_frame(input: &mut impl Read, buffer: &mut Vec<u8>) -> io::Result<Option<Frame>> {
+    let mut tag = [0; 1];
+    loop {
+        match input.read(&mut tag) {
+            Ok(0) => return Ok(None),
+            Ok(_) => break,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    let (frame, len) = match tag[0] {
+        TAG_IMAGE => (Frame::Image, read_u32(input)? as usize),
+        TAG_LUMA => {
+            let (width, height) = (read_u32(input)?, read_u32(input)?);
+            let len = (width as usize).saturating_mul(height as usize);
+            (Frame::Luma(width, height), len)
+        }
+        tag => {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("unknown frame type 0x{:02x}", tag),
+            ));
+        }
+    };
+    if len == 0 || len > MAX_FRAME {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!("invalid frame size {}", len),
+        ));
+    }
+    buffer.resize(len, 0);
+    input.read_exact(buffer)?;
+    Ok(Some(frame))
+}
+
+fn read_u32(input: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::time::{Duration, Instant};
+
+    use clap::Parser;
+    use image::{DynamicImage, Rgba};
+
+    use super::*;
+    use crate::encode::build_code;
+    use crate::render::{self, Matrix, Style};
+
+    fn luma_frame(img: &GrayImage) -> Vec<u8> {
+        let mut frame = vec![TAG_LUMA];
+        frame.extend(img.width().to_le_bytes());
+        frame.extend(img.height().to_le_bytes());
+        frame.extend(img.as_raw());
+        frame
+    }
+
+    #[derive(clap::Parser)]
+    struct Cli {
+        #[command(flatten)]
+        args: ServeArgs,
+    }
+
+    // 使用命令行的默认选项
+    fn session(rules: &Rules) -> Session<'_> {
+        Session::new(Cli::parse_from(["serve"]).args, rules)
+    }
+
+    fn serve(input: Vec<u8>) -> io::Result<Vec<Value>> {
+        let rules = Rules::builtin();
+        let mut session = session(&rules);
+        let mut output = Vec::new();
+        session.serve(Cursor::new(input), &mut output)?;
+        Ok(output
+            .split(|&b| b == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_slice(line).unwrap())
+            .collect())
+    }
+
+    #[test]
+    fn test_serve() {
+        let code = build_code(b"frame", qrcode::EcLevel::M, None).unwrap();
+        let style = Style {
+            quiet_zone: 4,
+            module_size: 6,
+            foreground: Rgba([0, 0, 0, 255]),
+            background: Rgba([255, 255, 255, 255]),
+        };
+        let symbol = DynamicImage::ImageRgba8(render::render_image(&Matrix::from_code(&code), &style, None)).to_luma8();
+        let blank = GrayImage::from_pixel(64, 48, image::Luma([255]));
+
+        let mut input = luma_frame(&symbol);
+        input.extend(luma_frame(&symbol));
+        input.extend(luma_frame(&blank));
+        input.extend([TAG_IMAGE, 3, 0, 0, 0, 1, 2, 3]);
+        let lines = serve(input).unwrap();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0]["frame"], 0);
+        assert_eq!(lines[0]["results"][0]["content"], "frame");
+        // 相同的连续结果只回应帧序号
+        assert_eq!(lines[1], json!({ "frame": 1, "unchanged": true }));
+        assert_eq!(lines[2], json!({ "frame": 2, "results": [] }));
+        assert!(lines[3]["error"].as_str().unwrap().starts_with("can not open image"));
+    }
+
+    #[test]
+    fn test_invalid_stream() {
+        assert!(serve(vec![b'X']).is_err());
+        assert!(serve(vec![TAG_LUMA, 4, 0, 0, 0, 4, 0, 0, 0, 255]).is_err());
+        assert!(serve(vec![TAG_LUMA, 0, 0, 0, 0, 4, 0, 0, 0]).is_err());
+        assert!(serve(Vec::new()).unwrap().is_empty());
+    }
+
+    // 720p 噪声帧，与摄像头对着没有条码的画面时相同，所有策略都会走完
+    fn noise_frame() -> GrayImage {
+        let mut seed = 1u32;
+        GrayImage::from_fn(1280, 720, |_, _| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            image::Luma([(seed >> 24) as u8])
+        })
+    }
+
+    #[test]
+    fn test_noise_frame() {
+        let rules = Rules::builtin();
+        let mut session = session(&rules);
+        session.buffer = noise_frame().into_raw();
+        assert!(
+            session
+                .scan(Frame::Luma(1280, 720))
+                .is_ok_and(|results| results.is_empty())
+        );
+
+        // 默认只尝试两种快速策略，也不调用 rxing
+        let mut trace = decode::Trace::default();
+        assert!(decode::scan_traced(&noise_frame(), &session.options, &mut trace).is_err());
+        assert_eq!(trace.tried, [("original", false), ("otsu", false)]);
+    }
+
+    // 耗时与机器有关，需要时以 `cargo test --release -- --ignored` 运行；发布构建每帧约 150ms
+    #[test]
+    #[ignore]
+    fn bench_noise_frame() {
+        let rules = Rules::builtin();
+        let mut session = session(&rules);
+        session.buffer = noise_frame().into_raw();
+        let started = Instant::now();
+        for _ in 0..3 {
+            assert!(session.scan(Frame::Luma(1280, 720)).is_ok());
+        }
+        let elapsed = started.elapsed() / 3;
+        println!("1280x720 noise frame: {:?}", elapsed);
+        assert!(elapsed < Duration::from_millis(500));
+    }
+}
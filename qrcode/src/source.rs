@@ -0,0 +1,118 @@
+use std::fmt;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+use data_encoding::{BASE64_NOPAD, BASE64URL_NOPAD};
+use percent_encoding::percent_decode_str;
+
+/// 图片来源：文件、标准输入或 `data:` URI
+pub enum Source<'a> {
+    File(&'a Path),
+    Stdin,
+    DataUri(&'a str),
+}
+
+impl<'a> Source<'a> {
+    pub fn parse(arg: &'a Path) -> Self {
+        match arg.to_str() {
+            Some("-") => Source::Stdin,
+            Some(s) if s.starts_with("data:") => Source::DataUri(s),
+            _ => Source::File(arg),
+        }
+    }
+
+    /// 读取图片字节，`base64` 为真时文件和标准输入的内容按 base64 文本解码
+    pub fn read(&self, base64: bool) -> io::Result<Vec<u8>> {
+        let data = match self {
+            Source::DataUri(uri) => return decode_data_uri(uri),
+            Source::File(path) => fs::read(path)?,
+            Source::Stdin => {
+                let mut data = Vec::new();
+                io::stdin().read_to_end(&mut data)?;
+                data
+            }
+        };
+        if !base64 {
+            return Ok(data);
+        }
+
+        let text = std::str::from_utf8(&data).map_err(|_| invalid("base64 input is not text"))?;
+        let text = text.trim();
+        // 兼容直接粘贴 canvas.toDataURL() 的结果
+        if text.starts_with("data:") {
+            decode_data_uri(text)
+        } else {
+            decode_base64(text)
+        }
+    }
+}
+
+impl fmt::Display for Source<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::File(path) => write!(f, "{}", path.display()),
+            Source::Stdin => f.write_str("<stdin>"),
+            // 只显示媒体类型，避免输出整段数据
+            Source::DataUri(uri) => {
+                let meta = uri.split_once(',').map_or(*uri, |(meta, _)| meta);
+                f.write_str(meta.split(';').next().unwrap_or(meta))
+            }
+        }
+    }
+}
+
+// data:[<mediatype>][;base64],<data>
+fn decode_data_uri(uri: &str) -> io::Result<Vec<u8>> {
+    let (meta, data) = uri
+        .strip_prefix("data:")
+        .and_then(|rest| rest.split_once(','))
+        .ok_or_else(|| invalid("malformed data URI"))?;
+    let data: Vec<u8> = percent_decode_str(data).collect();
+    if meta.split(';').any(|param| param.eq_ignore_ascii_case("base64")) {
+        let text = std::str::from_utf8(&data).map_err(|_| invalid("malformed data URI"))?;
+        decode_base64(text)
+    } else {
+        Ok(data)
+    }
+}
+
+// 忽略空白和填充，同时接受标准字母表和 URL 安全字母表
+fn decode_base64(text: &str) -> io::Result<Vec<u8>> {
+    let text: String = text.chars().filter(|c| !c.is_ascii_whitespace()).collect();
+    let text = text.trim_end_matches('=').as_bytes();
+    BASE64_NOPAD
+        .decode(text)
+        .or_else(|_| BASE64URL_NOPAD.decode(text))
+        .map_err(|e| invalid(&format!("invalid base64: {}", e)))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_data_uri() {
+        assert_eq!(decode_data_uri("data:image/png;base64,aGVs\nbG8=").unwrap(), b"hello");
+        assert_eq!(decode_data_uri("data:image/png;base64,aGVsbG8").unwrap(), b"hello");
+        assert_eq!(decode_data_uri("data:,a%20b").unwrap(), b"a b");
+        assert!(decode_data_uri("data:image/png;base64").is_err());
+        assert!(decode_data_uri("data:image/png;base64,a$b").is_err());
+
+        let uri = "data:image/png;base64,aGVsbG8=";
+        assert_eq!(Source::parse(Path::new(uri)).to_string(), "data:image/png");
+        assert!(matches!(Source::parse(Path::new("-")), Source::Stdin));
+        assert!(matches!(Source::parse(Path::new("a.png")), Source::File(_)));
+    }
+
+    #[test]
+    fn test_decode_base64() {
+        assert_eq!(decode_base64(" aGVs\r\nbG8=\n").unwrap(), b"hello");
+        assert_eq!(decode_base64("-_8").unwrap(), [0xfb, 0xff]);
+        assert_eq!(decode_base64("+/8=").unwrap(), [0xfb, 0xff]);
+    }
+}
@@ -0,0 +1,220 @@
+use std::collections::HashSet;
+use std::ops::Deref;
+
+use clap::ValueEnum;
+use image::{ImageBuffer, Luma};
+use rxing::{BarcodeFormat, DecodeHints, RXingResultMetadataType, RXingResultMetadataValue};
+use serde::Serialize;
+
+use crate::bitstream::Stream;
+use crate::decode::{Decoded, ScanOptions};
+use crate::preprocess::Strategy;
+
+/// 支持识别的码制，二维码由 rqrr 解码，其余由 rxing 解码
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, ValueEnum, Serialize)]
+pub enum Symbology {
+    #[value(name = "qr")]
+    #[serde(rename = "qr")]
+    Qr,
+    #[value(name = "micro-qr")]
+    #[serde(rename = "micro-qr")]
+    MicroQr,
+    #[value(name = "rmqr")]
+    #[serde(rename = "rmqr")]
+    Rmqr,
+    #[value(name = "ean-13")]
+    #[serde(rename = "ean-13")]
+    Ean13,
+    #[value(name = "ean-8")]
+    #[serde(rename = "ean-8")]
+    Ean8,
+    #[value(name = "upc-a")]
+    #[serde(rename = "upc-a")]
+    UpcA,
+    #[value(name = "upc-e")]
+    #[serde(rename = "upc-e")]
+    UpcE,
+    #[value(name = "code-128")]
+    #[serde(rename = "code-128")]
+    Code128,
+    #[value(name = "code-39")]
+    #[serde(rename = "code-39")]
+    Code39,
+    #[value(name = "code-93")]
+    #[serde(rename = "code-93")]
+    Code93,
+    #[value(name = "codabar")]
+    #[serde(rename = "codabar")]
+    Codabar,
+    #[value(name = "itf")]
+    #[serde(rename = "itf")]
+    Itf,
+    #[value(name = "datamatrix")]
+    #[serde(rename = "datamatrix")]
+    DataMatrix,
+    #[value(name = "aztec")]
+    #[serde(rename = "aztec")]
+    Aztec,
+    #[value(name = "pdf417")]
+    #[serde(rename = "pdf417")]
+    Pdf417,
+}
+
+// 码制与 rxing 格式的对应关系，二维码不经过 rxing
+const FORMATS: &[(Symbology, BarcodeFormat)] = &[
+    (Symbology::MicroQr, BarcodeFormat::MICRO_QR_CODE),
+    (Symbology::Rmqr, BarcodeFormat::RECTANGULAR_MICRO_QR_CODE),
+    (Symbology::Ean13, BarcodeFormat::EAN_13),
+    (Symbology::Ean8, BarcodeFormat::EAN_8),
+    (Symbology::UpcA, BarcodeFormat::UPC_A),
+    (Symbology::UpcE, BarcodeFormat::UPC_E),
+    (Symbology::Code128, BarcodeFormat::CODE_128),
+    (Symbology::Code39, BarcodeFormat::CODE_39),
+    (Symbology::Code93, BarcodeFormat::CODE_93),
+    (Symbology::Codabar, BarcodeFormat::CODABAR),
+    (Symbology::Itf, BarcodeFormat::ITF),
+    (Symbology::DataMatrix, BarcodeFormat::DATA_MATRIX),
+    (Symbology::Aztec, BarcodeFormat::AZTEC),
+    (Symbology::Pdf417, BarcodeFormat::PDF_417),
+];
+
+/// 是否启用指定码制，未指定过滤条件时全部启用
+pub fn enabled(options: &ScanOptions, symbology: Symbology) -> bool {
+    options.symbologies.is_empty() || options.symbologies.contains(&symbology)
+}
+
+/// 是否启用了需要 rxing 识别的码制
+pub fn scans_barcodes(options: &ScanOptions) -> bool {
+    FORMATS.iter().any(|(symbology, _)| enabled(options, *symbology))
+}
+
+/// 使用 rxing 识别一维码及 Micro QR、rMQR、DataMatrix、Aztec、PDF417
+pub fn scan_barcodes<C>(img: &ImageBuffer<Luma<u8>, C>, strategy: &Strategy, options: &ScanOptions) -> Vec<Decoded>
+where
+    C: Deref<Target = [u8]>,
+{
+    let formats: HashSet<BarcodeFormat> = FORMATS
+        .iter()
+        .filter(|(symbology, _)| enabled(options, *symbology))
+        .map(|(_, format)| *format)
+        .collect();
+    if formats.is_empty() {
+        return Vec::new();
+    }
+
+    let mut hints = DecodeHints {
+        PossibleFormats: Some(formats),
+        ..Default::default()
+    };
+    // rxing 需要取得像素的所有权，只能复制一份；调用方只在快速策略上识别，常驻模式默认只识别二维码，不会走到这里
+    let mut found = rxing::helpers::detect_multiple_in_luma_with_hints(
+        img.as_raw().to_vec(),
+        img.width(),
+        img.height(),
+        &mut hints,
+    )
+    .unwrap_or_default();
+    // rxing 依靠定位图案采样时找不到 R7、R9 两种高度的 rMQR，图像中只有单个符号时按整幅图像的边界重试，
+    // 只在原图上重试一次
+    let rmqr = BarcodeFormat::RECTANGULAR_MICRO_QR_CODE;
+    if strategy.name == "original"
+        && enabled(options, Symbology::Rmqr)
+        && !found.iter().any(|r| *r.getBarcodeFormat() == rmqr)
+    {
+        let mut hints = DecodeHints {
+            PossibleFormats: Some(HashSet::from([rmqr])),
+            PureBarcode: Some(true),
+            ..Default::default()
+        };
+        found.extend(
+            rxing::helpers::detect_in_luma_with_hints(
+                img.as_raw().to_vec(),
+                img.width(),
+                img.height(),
+                None,
+                &mut hints,
+            )
+            .ok()
+            .filter(|r| *r.getBarcodeFormat() == rmqr),
+        );
+    }
+
+    found
+        .iter()
+        .filter_map(|result| {
+            let symbology = FORMATS
+                .iter()
+                .find(|(_, format)| format == result.getBarcodeFormat())
+                .map(|(symbology, _)| *symbology)?;
+            // rxing 已按 ECI 完成字符集转换，这里只保留文本
+            let stream = Stream {
+                runs: vec![(None, result.getText().as_bytes().to_vec())],
+                ..Default::default()
+            };
+            let ecc_level = match result
+                .getRXingResultMetadata()
+                .get(&RXingResultMetadataType::ERROR_CORRECTION_LEVEL)
+            {
+                Some(RXingResultMetadataValue::ErrorCorrectionLevel(level)) => Some(level.clone()),
+                _ => None,
+            };
+            Some(Decoded::new(stream, None, symbology, None, ecc_level, strategy.name))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use image::GrayImage;
+    use rxing::{MultiFormatWriter, Writer};
+
+    use super::*;
+    use crate::preprocess::STRATEGIES;
+
+    fn render(text: &str, format: BarcodeFormat, width: i32, height: i32) -> GrayImage {
+        let matrix = MultiFormatWriter.encode(text, &format, width, height).unwrap();
+        let pad = 20;
+        GrayImage::from_fn(matrix.getWidth() + pad * 2, matrix.getHeight() + pad * 2, |x, y| {
+            let inside = (pad..matrix.getWidth() + pad).contains(&x) && (pad..matrix.getHeight() + pad).contains(&y);
+            if inside && matrix.get(x - pad, y - pad) {
+                Luma([0])
+            } else {
+                Luma([255])
+            }
+        })
+    }
+
+    #[test]
+    fn test_scan_barcodes() {
+        let options = ScanOptions::default();
+        for (text, format, symbology) in [
+            ("5901234123457", BarcodeFormat::EAN_13, Symbology::Ean13),
+            ("SIDECAR-128", BarcodeFormat::CODE_128, Symbology::Code128),
+            ("hello datamatrix", BarcodeFormat::DATA_MATRIX, Symbology::DataMatrix),
+        ] {
+            let (width, height) = if format == BarcodeFormat::DATA_MATRIX {
+                (200, 200)
+            } else {
+                (300, 120)
+            };
+            let img = render(text, format, width, height);
+            let results = scan_barcodes(&img, &STRATEGIES[0], &options);
+            assert_eq!(results.len(), 1);
+            assert_eq!(results[0].symbology, symbology);
+            assert_eq!(results[0].content, text);
+            assert!(results[0].version.is_none());
+        }
+    }
+
+    #[test]
+    fn test_symbology_filter() {
+        let img = render("SIDECAR-128", BarcodeFormat::CODE_128, 300, 120);
+        let options = ScanOptions {
+            symbologies: vec![Symbology::Qr, Symbology::Ean13],
+            ..Default::default()
+        };
+        assert!(scan_barcodes(&img, &STRATEGIES[0], &options).is_empty());
+        assert!(!enabled(&options, Symbology::Code128));
+        assert!(enabled(&ScanOptions::default(), Symbology::Code128));
+    }
+}
@@ -0,0 +1,262 @@
+use std::io::Read;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+use flate2::read::GzDecoder;
+use hayro::hayro_interpret::InterpreterSettings;
+use hayro::hayro_syntax::Pdf;
+use hayro::vello_cpu::color::palette::css::WHITE;
+use hayro::vello_cpu::peniko::ImageAlphaType;
+use hayro::{PixmapSettings, RenderCache, RenderSettings};
+use image::error::{DecodingError, ImageFormatHint};
+use image::{DynamicImage, ImageError, ImageResult, RgbaImage};
+use resvg::tiny_skia::{Color, Pixmap, Transform};
+use resvg::usvg::{Options, Tree};
+
+// 未指定 DPI 时的渲染分辨率
+const DEFAULT_DPI: f32 = 200.0;
+// 渲染结果最长边的下限，避免小尺寸 SVG 的模块不足一个像素
+const MIN_SIZE: f32 = 1024.0;
+// 渲染结果最长边的上限
+const MAX_SIZE: f32 = 8192.0;
+// PDF 所选页面渲染结果的总像素数上限，约 512MB 的 RGBA，200 DPI 时约 34 页 A4
+const MAX_PDF_PIXELS: u64 = 1 << 27;
+// 判断是否为 SVG 时只检查开头这么多字节
+const SNIFF_SIZE: usize = 4096;
+
+/// SVG 和 PDF 的渲染选项
+#[derive(Debug, Default)]
+pub struct Render {
+    /// 需要渲染的 PDF 页面，为空时渲染全部页面
+    pub pages: Option<Pages>,
+    pub dpi: Option<f32>,
+}
+
+/// PDF 页码范围，格式为 `1,3-5`，页码从 1 开始
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pages(Vec<RangeInclusive<usize>>);
+
+impl FromStr for Pages {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let page = |v: &str| match v.trim().parse::<usize>() {
+            Ok(0) | Err(_) => Err(format!("invalid page '{}' in '{}'", v.trim(), s)),
+            Ok(page) => Ok(page),
+        };
+        s.split(',')
+            .map(|part| {
+                let (start, end) = match part.split_once('-') {
+                    Some((start, end)) => (page(start)?, page(end)?),
+                    None => (page(part)?, page(part)?),
+                };
+                if start > end {
+                    return Err(format!("invalid page range '{}'", part.trim()));
+                }
+                Ok(start..=end)
+            })
+            .collect::<Result<_, _>>()
+            .map(Pages)
+    }
+}
+
+impl Pages {
+    pub fn contains(&self, page: usize) -> bool {
+        self.0.iter().any(|range| range.contains(&page))
+    }
+}
+
+/// 是否为 PDF 文件
+pub fn is_pdf(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"%PDF-")
+}
+
+/// 是否为 SVG 文件，解压后为 SVG 的 gzip 文件（SVGZ）也视为 SVG
+pub fn is_svg(bytes: &[u8]) -> bool {
+    if bytes.starts_with(&[0x1f, 0x8b]) {
+        // 只解压开头一段，其他 gzip 文件仍交给图片解码器识别
+        let mut head = Vec::new();
+        let _ = GzDecoder::new(bytes).take(SNIFF_SIZE as u64).read_to_end(&mut head);
+        return svg_text(&head);
+    }
+    svg_text(bytes)
+}
+
+fn svg_text(bytes: &[u8]) -> bool {
+    let head = String::from_utf8_lossy(&bytes[..bytes.len().min(SNIFF_SIZE)]);
+    let head = head.trim_start_matches('\u{feff}').trim_start();
+    head.starts_with('<') && head.contains("<svg")
+}
+
+/// 在白色背景上渲染 SVG，尺寸按 96 DPI 换算
+pub fn render_svg(bytes: &[u8], render: &Render) -> ImageResult<DynamicImage> {
+    let tree = Tree::from_data(bytes, &Options::default()).map_err(|e| error("SVG", e))?;
+    let size = tree.size();
+    let scale = render.scale(size.width(), size.height(), 96.0);
+    let width = (size.width() * scale).ceil() as u32;
+    let height = (size.height() * scale).ceil() as u32;
+    let mut pixmap = Pixmap::new(width, height).ok_or_else(|| error("SVG", "svg has no size"))?;
+    pixmap.fill(Color::WHITE);
+    resvg::render(&tree, Transform::from_scale(scale, scale), &mut pixmap.as_mut());
+    RgbaImage::from_raw(width, height, pixmap.take())
+        .map(DynamicImage::ImageRgba8)
+        .ok_or_else(|| error("SVG", "svg pixmap size mismatch"))
+}
+
+/// 在白色背景上逐页渲染 PDF，尺寸按 72 DPI 换算；所选页面的总像素数超过上限时在渲染前报错
+pub fn render_pdf(bytes: &[u8], render: &Render) -> ImageResult<Vec<DynamicImage>> {
+    let pdf = Pdf::new(bytes.to_vec()).map_err(|e| error("PDF", format!("{:?}", e)))?;
+    let selected: Vec<_> = pdf
+        .pages()
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| render.pages.as_ref().is_none_or(|pages| pages.contains(index + 1)))
+        .map(|(_, page)| {
+            let (width, height) = page.render_dimensions();
+            (page, render.scale(width, height, 72.0))
+        })
+        .collect();
+    let pixels: u64 = selected
+        .iter()
+        .map(|(page, scale)| {
+            let (width, height) = page.render_dimensions();
+            (width * scale).ceil() as u64 * (height * scale).ceil() as u64
+        })
+        .sum();
+    if pixels > MAX_PDF_PIXELS {
+        return Err(error(
+            "PDF",
+            format!(
+                "the {} selected pages would render to {} megapixels, more than the limit of {}; \
+                 choose fewer pages with --pages or a lower --dpi",
+                selected.len(),
+                pixels >> 20,
+                MAX_PDF_PIXELS >> 20
+            ),
+        ));
+    }
+
+    let cache = RenderCache::new();
+    let interpreter = InterpreterSettings::default();
+    let mut images = Vec::new();
+    for (page, scale) in selected {
+        let settings = PixmapSettings {
+            x_scale: scale,
+            y_scale: scale,
+            bg_color: WHITE,
+        };
+        let pixmap = hayro::render(page, &cache, &interpreter, &RenderSettings::default(), &settings);
+        let (width, height) = (pixmap.width() as u32, pixmap.height() as u32);
+        let image = RgbaImage::from_raw(width, height, pixmap.take_rgba8(ImageAlphaType::AlphaPremultiplied))
+            .ok_or_else(|| error("PDF", "pdf pixmap size mismatch"))?;
+        images.push(DynamicImage::ImageRgba8(image));
+    }
+    if images.is_empty() {
+        let count = pdf.pages().len();
+        return Err(error(
+            "PDF",
+            format!("none of the selected pages exist, the pdf has {} pages", count),
+        ));
+    }
+    Ok(images)
+}
+
+impl Render {
+    // 按 DPI 换算的缩放比例，结果的最长边限制在 MIN_SIZE 到 MAX_SIZE 之间
+    fn scale(&self, width: f32, height: f32, base_dpi: f32) -> f32 {
+        let longest = width.max(height).max(1.0);
+        let scale = self.dpi.unwrap_or(DEFAULT_DPI) / base_dpi;
+        scale.clamp(MIN_SIZE / longest, MAX_SIZE / longest)
+    }
+}
+
+fn error(format: &str, e: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> ImageError {
+    ImageError::Decoding(DecodingError::new(ImageFormatHint::Name(format.to_string()), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_pages() {
+        let pages: Pages = "1, 3-5".parse().unwrap();
+        assert!(pages.contains(1) && pages.contains(4) && !pages.contains(2) && !pages.contains(6));
+        assert!("0".parse::<Pages>().is_err());
+        assert!("5-3".parse::<Pages>().is_err());
+        assert!("a".parse::<Pages>().is_err());
+    }
+
+    #[test]
+    fn test_render_svg() {
+        let svg = br#"<?xml version="1.0"?>
+<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 4 2"><rect x="2" width="2" height="2"/></svg>"#;
+        assert!(is_svg(svg) && !is_pdf(svg));
+        let gzip = |bytes: &[u8]| {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(bytes).unwrap();
+            encoder.finish().unwrap()
+        };
+        assert!(is_svg(&gzip(svg)));
+        assert!(!is_svg(&gzip(b"not an image")));
+        assert!(!is_svg(&[0x1f, 0x8b, 0, 0]));
+        let img = render_svg(svg, &Render::default()).unwrap().to_luma8();
+        // 小尺寸 SVG 放大到最长边 1024 像素，透明区域为白色
+        assert_eq!(img.dimensions(), (1024, 512));
+        assert_eq!(img.get_pixel(100, 256).0, [255]);
+        assert_eq!(img.get_pixel(900, 256).0, [0]);
+    }
+
+    // 三页空白的 Letter 尺寸 PDF
+    fn blank_pdf() -> Vec<u8> {
+        let mut objects = vec![
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R 4 0 R 5 0 R] /Count 3 >>".to_string(),
+        ];
+        for _ in 0..3 {
+            objects.push("<< /Type /Page /Parent 2 0 R /MediaBox [0 0 612 792] /Resources << >> >>".to_string());
+        }
+        let mut pdf = String::from("%PDF-1.4\n");
+        let mut offsets = Vec::new();
+        for (i, object) in objects.iter().enumerate() {
+            offsets.push(pdf.len());
+            pdf.push_str(&format!("{} 0 obj\n{}\nendobj\n", i + 1, object));
+        }
+        let xref = pdf.len();
+        pdf.push_str(&format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1));
+        for offset in offsets {
+            pdf.push_str(&format!("{:010} 00000 n \n", offset));
+        }
+        pdf.push_str(&format!(
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+            objects.len() + 1,
+            xref
+        ));
+        pdf.into_bytes()
+    }
+
+    #[test]
+    fn test_pdf_pixel_limit() {
+        let pdf = blank_pdf();
+        let pages = render_pdf(&pdf, &Render::default()).unwrap();
+        assert_eq!(pages.len(), 3);
+
+        // 每页的最长边都被限制在 8192 像素，三页合计超过上限，渲染前即报错
+        let render = Render {
+            dpi: Some(2000.0),
+            ..Default::default()
+        };
+        let e = render_pdf(&pdf, &render).unwrap_err().to_string();
+        assert!(e.contains("--pages"), "{}", e);
+        let render = Render {
+            pages: Some("2".parse().unwrap()),
+            dpi: Some(300.0),
+        };
+        assert_eq!(render_pdf(&pdf, &render).unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,532 @@
+use std::fs::{self, File, Metadata};
+use std::io::{self, BufWriter, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use axum::body::{Body, Bytes};
+use chrono::{DateTime, Datelike, Local, Timelike};
+use crc32fast::Hasher;
+use flate2::Compression;
+use flate2::write::DeflateEncoder;
+use futures_util::stream;
+use tokio::sync::mpsc;
+
+use crate::tree::Tree;
+
+// 已压缩的媒体及归档格式，再用 deflate 压缩几乎没有收益
+const COMPRESSED: &[&str] = &[
+    "jpg", "jpeg", "png", "gif", "webp", "heic", "heif", "avif", "mp4", "m4v", "mov", "mkv", "webm", "avi", "3gp",
+    "mp3", "m4a", "aac", "ogg", "opus", "flac", "zip", "gz", "tgz", "bz2", "xz", "zst", "7z", "rar", "apk", "ipa",
+    "jar", "docx", "xlsx", "pptx", "epub",
+];
+
+// 32 位字段的上限，达到时改用 zip64 扩展字段
+const ZIP64_LIMIT: u64 = 0xFFFF_FFFF;
+// deflate 处理不可压缩的数据时略有膨胀，接近上限的文件预先按 zip64 写入
+const DEFLATE_ZIP64_SIZE: u64 = 0xF000_0000;
+// 写入响应流的块大小
+const CHUNK: usize = 64 << 10;
+
+// 通用标志：大小和 CRC 写在数据之后的数据描述符中，文件名为 UTF-8
+const FLAGS: u16 = 0x0808;
+const VERSION: u16 = 20;
+const VERSION_ZIP64: u16 = 45;
+// 高字节 3 表示 Unix，外部属性的高 16 位为文件权限
+const VERSION_MADE_BY: u16 = 3 << 8 | VERSION_ZIP64;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Method {
+    Store = 0,
+    Deflate = 8,
+}
+
+// 归档中的一项，目录名以 / 结尾且没有数据
+struct Entry {
+    name: String,
+    source: Option<PathBuf>,
+    size: u64,
+    modified: SystemTime,
+    method: Method,
+}
+
+// 写入一项后才知道的信息，用于中央目录
+struct Record {
+    offset: u64,
+    crc: u32,
+    compressed: u64,
+}
+
+/// 边读取文件边生成的 zip 归档，不使用临时文件
+pub struct Archive {
+    entries: Vec<Entry>,
+}
+
+impl Entry {
+    // 本地文件头中无法在写入前得知压缩后的大小，只能按原始大小判断
+    fn zip64(&self) -> bool {
+        match self.method {
+            Method::Store => self.size >= ZIP64_LIMIT,
+            Method::Deflate => self.size >= DEFLATE_ZIP64_SIZE,
+        }
+    }
+}
+
+impl Archive {
+    pub fn new() -> Self {
+        Archive { entries: Vec::new() }
+    }
+
+    /// 以 name 为归档内路径加入文件或目录，目录递归加入其中的内容
+    pub fn add(&mut self, tree: &Tree, path: &Path, name: &str) -> io::Result<()> {
+        let metadata = fs::metadata(path)?;
+        if !metadata.is_dir() {
+            let extension = path
+                .extension()
+                .and_then(|e| e.to_str())
+                .unwrap_or("")
+                .to_ascii_lowercase();
+            let method = if metadata.len() == 0 || COMPRESSED.contains(&extension.as_str()) {
+                Method::Store
+            } else {
+                Method::Deflate
+            };
+            self.entries.push(Entry {
+                name: name.to_string(),
+                source: Some(path.to_path_buf()),
+                size: metadata.len(),
+                modified: modified(&metadata),
+                method,
+            });
+            return Ok(());
+        }
+        self.entries.push(Entry {
+            name: format!("{}/", name),
+            source: None,
+            size: 0,
+            modified: modified(&metadata),
+            method: Method::Store,
+        });
+        let mut children = Vec::new();
+        for item in fs::read_dir(path)? {
+            let item = item?;
+            let Ok(child) = item.file_name().into_string() else {
+                continue;
+            };
+            // 目录链接可能形成环，只收录指向分享范围内文件的链接
+            if item.file_type()?.is_symlink() {
+                match item.path().canonicalize() {
+                    Ok(target) if target.is_file() && tree.contains(&target) => {}
+                    _ => continue,
+                }
+            }
+            children.push((child, item.path()));
+        }
+        children.sort();
+        for (child, child_path) in children {
+            self.add(tree, &child_path, &format!("{}/{}", name, child))?;
+        }
+        Ok(())
+    }
+
+    /// 全部以存储方式写入时归档长度可以预先算出
+    pub fn content_length(&self) -> Option<u64> {
+        if self.entries.iter().any(|entry| entry.method == Method::Deflate) {
+            return None;
+        }
+        let mut offset = 0;
+        let mut central = 0;
+        for entry in &self.entries {
+            let record = Record {
+                offset,
+                crc: 0,
+                compressed: entry.size,
+            };
+            let zip64 = entry.zip64();
+            offset += (local_header(entry, zip64).len() + descriptor(&record, entry.size, zip64).len()) as u64;
+            offset += entry.size;
+            central += central_header(entry, &record).len() as u64;
+        }
+        Some(offset + central + end_records(self.entries.len(), offset, central).len() as u64)
+    }
+
+    /// 在阻塞线程中生成归档，作为响应体流式输出；读取文件出错时中断响应
+    pub fn into_body(self) -> Body {
+        let (tx, rx) = mpsc::channel(8);
+        tokio::task::spawn_blocking(move || {
+            let writer = BufWriter::with_capacity(CHUNK, ChannelWriter(tx.clone()));
+            if let Err(e) = self.write_to(writer) {
+                let _ = tx.blocking_send(Err(e));
+            }
+        });
+        Body::from_stream(stream::unfold(rx, |mut rx| async move {
+            rx.recv().await.map(|chunk| (chunk, rx))
+        }))
+    }
+
+    fn write_to(&self, writer: impl Write) -> io::Result<()> {
+        let mut out = Counter {
+            inner: writer,
+            count: 0,
+        };
+        let mut records = Vec::with_capacity(self.entries.len());
+        for entry in &self.entries {
+            let offset = out.count;
+            let zip64 = entry.zip64();
+            out.write_all(&local_header(entry, zip64))?;
+            let (crc, compressed) = match &entry.source {
+                Some(path) => write_data(entry, path, &mut out)?,
+                None => (0, 0),
+            };
+            let record = Record {
+                offset,
+                crc,
+                compressed,
+            };
+            out.write_all(&descriptor(&record, entry.size, zip64))?;
+            records.push(record);
+        }
+        let start = out.count;
+        for (entry, record) in self.entries.iter().zip(&records) {
+            out.write_all(&central_header(entry, record))?;
+        }
+        let size = out.count - start;
+        out.write_all(&end_records(self.entries.len(), start, size))?;
+        out.flush()
+    }
+}
+
+// 写入文件数据，返回 CRC 和压缩后的大小；文件在打包过程中变短时报错，变长时只取开头部分
+fn write_data(entry: &Entry, path: &Path, out: &mut Counter<impl Write>) -> io::Result<(u32, u64)> {
+    let mut reader = Checksum {
+        inner: File::open(path)?.take(entry.size),
+        hasher: Hasher::new(),
+        count: 0,
+    };
+    let start = out.count;
+    match entry.method {
+        Method::Store => {
+            io::copy(&mut reader, out)?;
+        }
+        Method::Deflate => {
+            let mut encoder = DeflateEncoder::new(&mut *out, Compression::default());
+            io::copy(&mut reader, &mut encoder)?;
+            encoder.finish()?;
+        }
+    }
+    if reader.count != entry.size {
+        return Err(io::Error::new(
+            ErrorKind::UnexpectedEof,
+            format!("{} changed while archiving", path.display()),
+        ));
+    }
+    Ok((reader.hasher.finalize(), out.count - start))
+}
+
+fn local_header(entry: &Entry, zip64: bool) -> Vec<u8> {
+    let (time, date) = dos_time(entry.modified);
+    let mut header = Vec::with_capacity(30 + entry.name.len() + 20);
+    put32(&mut header, 0x0403_4b50);
+    put16(&mut header, if zip64 { VERSION_ZIP64 } else { VERSION });
+    put16(&mut header, FLAGS);
+    put16(&mut header, entry.method as u16);
+    put16(&mut header, time);
+    put16(&mut header, date);
+    // CRC 和大小写在数据描述符中
+    put32(&mut header, 0);
+    let size = if zip64 { ZIP64_LIMIT as u32 } else { 0 };
+    put32(&mut header, size);
+    put32(&mut header, size);
+    put16(&mut header, entry.name.len() as u16);
+    put16(&mut header, if zip64 { 20 } else { 0 });
+    header.extend(entry.name.as_bytes());
+    if zip64 {
+        put16(&mut header, 0x0001);
+        put16(&mut header, 16);
+        put64(&mut header, 0);
+        put64(&mut header, 0);
+    }
+    header
+}
+
+fn descriptor(record: &Record, size: u64, zip64: bool) -> Vec<u8> {
+    let mut descriptor = Vec::with_capacity(24);
+    put32(&mut descriptor, 0x0807_4b50);
+    put32(&mut descriptor, record.crc);
+    if zip64 {
+        put64(&mut descriptor, record.compressed);
+        put64(&mut descriptor, size);
+    } else {
+        put32(&mut descriptor, record.compressed as u32);
+        put32(&mut descriptor, size as u32);
+    }
+    descriptor
+}
+
+fn central_header(entry: &Entry, record: &Record) -> Vec<u8> {
+    // 超出 32 位的字段写入 zip64 扩展字段，顺序固定为原始大小、压缩后大小、偏移
+    let mut extra = Vec::new();
+    let mut field = |value: u64| {
+        if value >= ZIP64_LIMIT {
+            put64(&mut extra, value);
+            ZIP64_LIMIT as u32
+        } else {
+            value as u32
+        }
+    };
+    let size = field(entry.size);
+    let compressed = field(record.compressed);
+    let offset = field(record.offset);
+    let (time, date) = dos_time(entry.modified);
+    let attributes: u32 = match entry.source {
+        Some(_) => 0o100644 << 16,
+        // 低位 0x10 为 MS-DOS 的目录属性
+        None => 0o40755 << 16 | 0x10,
+    };
+
+    let mut header = Vec::with_capacity(46 + entry.name.len() + 28);
+    put32(&mut header, 0x0201_4b50);
+    put16(&mut header, VERSION_MADE_BY);
+    put16(&mut header, if extra.is_empty() { VERSION } else { VERSION_ZIP64 });
+    put16(&mut header, FLAGS);
+    put16(&mut header, entry.method as u16);
+    put16(&mut header, time);
+    put16(&mut header, date);
+    put32(&mut header, record.crc);
+    put32(&mut header, compressed);
+    put32(&mut header, size);
+    put16(&mut header, entry.name.len() as u16);
+    put16(&mut header, if extra.is_empty() { 0 } else { extra.len() as u16 + 4 });
+    // 注释长度、起始磁盘号、内部属性
+    put16(&mut header, 0);
+    put16(&mut header, 0);
+    put16(&mut header, 0);
+    put32(&mut header, attributes);
+    put32(&mut header, offset);
+    header.extend(entry.name.as_bytes());
+    if !extra.is_empty() {
+        put16(&mut header, 0x0001);
+        put16(&mut header, extra.len() as u16);
+        header.extend(extra);
+    }
+    header
+}
+
+// 中央目录结束记录，条目数或偏移超出范围时先写 zip64 结束记录及其定位符
+fn end_records(count: usize, start: u64, size: u64) -> Vec<u8> {
+    let count = count as u64;
+    let mut records = Vec::with_capacity(98);
+    if count >= 0xFFFF || start >= ZIP64_LIMIT || size >= ZIP64_LIMIT {
+        put32(&mut records, 0x0606_4b50);
+        put64(&mut records, 44);
+        put16(&mut records, VERSION_MADE_BY);
+        put16(&mut records, VERSION_ZIP64);
+        put32(&mut records, 0);
+        put32(&mut records, 0);
+        put64(&mut records, count);
+        put64(&mut records, count);
+        put64(&mut records, size);
+        put64(&mut records, start);
+
+        put32(&mut records, 0x0706_4b50);
+        put32(&mut records, 0);
+        put64(&mut records, start + size);
+        put32(&mut records, 1);
+    }
+    put32(&mut records, 0x0605_4b50);
+    put16(&mut records, 0);
+    put16(&mut records, 0);
+    put16(&mut records, count.min(0xFFFF) as u16);
+    put16(&mut records, count.min(0xFFFF) as u16);
+    put32(&mut records, size.min(ZIP64_LIMIT) as u32);
+    put32(&mut records, start.min(ZIP64_LIMIT) as u32);
+    put16(&mut records, 0);
+    records
+}
+
+fn modified(metadata: &Metadata) -> SystemTime {
+    metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH)
+}
+
+// MS-DOS 格式的本地时间，只能表示 1980 年之后，精度 2 秒
+fn dos_time(time: SystemTime) -> (u16, u16) {
+    let time = DateTime::<Local>::from(time);
+    if time.year() < 1980 {
+        return (0, 1 << 5 | 1);
+    }
+    let date = ((time.year() - 1980).min(127) as u16) << 9 | (time.month() as u16) << 5 | time.day() as u16;
+    let clock = (time.hour() as u16) << 11 | (time.minute() as u16) << 5 | (time.second() / 2) as u16;
+    (clock, date)
+}
+
+fn put16(buffer: &mut Vec<u8>, value: u16) {
+    buffer.extend(value.to_le_bytes());
+}
+
+fn put32(buffer: &mut Vec<u8>, value: u32) {
+    buffer.extend(value.to_le_bytes());
+}
+
+fn put64(buffer: &mut Vec<u8>, value: u64) {
+    buffer.extend(value.to_le_bytes());
+}
+
+// 读取时计算 CRC 和已读取的字节数
+struct Checksum<R> {
+    inner: R,
+    hasher: Hasher,
+    count: u64,
+}
+
+impl<R: Read> Read for Checksum<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.count += n as u64;
+        Ok(n)
+    }
+}
+
+// 记录已写入的字节数，即下一项的偏移
+struct Counter<W> {
+    inner: W,
+    count: u64,
+}
+
+impl<W: Write> Write for Counter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// 将数据块发送给响应流，客户端断开后接收端关闭，写入失败并结束打包
+struct ChannelWriter(mpsc::Sender<io::Result<Bytes>>);
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0
+            .blocking_send(Ok(Bytes::copy_from_slice(buf)))
+            .map_err(|_| io::Error::new(ErrorKind::BrokenPipe, "client disconnected"))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use flate2::read::DeflateDecoder;
+
+    use super::*;
+
+    fn u16_at(data: &[u8], at: usize) -> u16 {
+        u16::from_le_bytes(data[at..at + 2].try_into().unwrap())
+    }
+
+    fn u32_at(data: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(data[at..at + 4].try_into().unwrap())
+    }
+
+    // 按中央目录读出各项的名称和内容
+    fn read(data: &[u8]) -> Vec<(String, Vec<u8>)> {
+        let end = data.len() - 22;
+        assert_eq!(u32_at(data, end), 0x0605_4b50);
+        let count = u16_at(data, end + 10) as usize;
+        let mut at = u32_at(data, end + 16) as usize;
+        let mut files = Vec::new();
+        for _ in 0..count {
+            assert_eq!(u32_at(data, at), 0x0201_4b50);
+            let method = u16_at(data, at + 10);
+            let crc = u32_at(data, at + 16);
+            let compressed = u32_at(data, at + 20) as usize;
+            let name_len = u16_at(data, at + 28) as usize;
+            let offset = u32_at(data, at + 42) as usize;
+            let name = String::from_utf8(data[at + 46..at + 46 + name_len].to_vec()).unwrap();
+            let start = offset + 30 + u16_at(data, offset + 26) as usize + u16_at(data, offset + 28) as usize;
+            let raw = &data[start..start + compressed];
+            let content = match method {
+                0 => raw.to_vec(),
+                _ => {
+                    let mut content = Vec::new();
+                    DeflateDecoder::new(raw).read_to_end(&mut content).unwrap();
+                    content
+                }
+            };
+            assert_eq!(crc32fast::hash(&content), crc);
+            files.push((name, content));
+            at += 46 + name_len + u16_at(data, at + 30) as usize;
+        }
+        files
+    }
+
+    #[test]
+    fn test_archive() {
+        let base = std::env::temp_dir().join(format!("share-archive-{}", std::process::id()));
+        let dir = base.join("album");
+        fs::create_dir_all(dir.join("raw")).unwrap();
+        fs::write(dir.join("a.jpg"), [0xff, 0xd8, 0xff, 0xe0]).unwrap();
+        fs::write(dir.join("raw/notes.txt"), "hello ".repeat(100)).unwrap();
+        fs::write(dir.join("empty.txt"), b"").unwrap();
+        let tree = Tree::new(std::slice::from_ref(&dir)).unwrap();
+        let dir = dir.canonicalize().unwrap();
+
+        let mut archive = Archive::new();
+        archive.add(&tree, &dir, "album").unwrap();
+        assert_eq!(archive.content_length(), None);
+        let mut data = Vec::new();
+        archive.write_to(&mut data).unwrap();
+        let files = read(&data);
+        let names: Vec<_> = files.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "album/",
+                "album/a.jpg",
+                "album/empty.txt",
+                "album/raw/",
+                "album/raw/notes.txt"
+            ]
+        );
+        assert_eq!(files[4].1, "hello ".repeat(100).as_bytes());
+
+        // 只含已压缩的文件时全部存储，长度可以预先算出
+        let mut archive = Archive::new();
+        archive.add(&tree, &dir.join("a.jpg"), "a.jpg").unwrap();
+        archive.add(&tree, &dir.join("empty.txt"), "empty.txt").unwrap();
+        let mut data = Vec::new();
+        archive.write_to(&mut data).unwrap();
+        assert_eq!(archive.content_length(), Some(data.len() as u64));
+        assert_eq!(read(&data)[0].1, [0xff, 0xd8, 0xff, 0xe0]);
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn test_zip64_records() {
+        let entry = Entry {
+            name: "big.mp4".to_string(),
+            source: Some(PathBuf::from("big.mp4")),
+            size: 5 << 30,
+            modified: SystemTime::UNIX_EPOCH,
+            method: Method::Store,
+        };
+        assert!(entry.zip64());
+        assert_eq!(local_header(&entry, true).len(), 30 + 7 + 20);
+        let record = Record {
+            offset: 6 << 30,
+            crc: 0,
+            compressed: entry.size,
+        };
+        // 原始大小、压缩后大小和偏移都写入扩展字段
+        assert_eq!(central_header(&entry, &record).len(), 46 + 7 + 4 + 24);
+        assert_eq!(end_records(1, 12 << 30, 80).len(), 56 + 20 + 22);
+        assert_eq!(end_records(1, 100, 80).len(), 22);
+        assert_eq!(dos_time(SystemTime::UNIX_EPOCH), (0, 33));
+    }
+}
@@ -0,0 +1,143 @@
+use std::fmt::Write;
+
+use chrono::{DateTime, Local};
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, utf8_percent_encode};
+
+use crate::tree::Entry;
+
+// 路径段中保留原样的字符之外全部编码，`/` 也要编码以免名称被拆成多段
+const SEGMENT: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'.').remove(b'_').remove(b'~');
+
+const STYLE: &str = "body{font-family:system-ui,sans-serif;margin:0 auto;max-width:960px;padding:12px}\
+table{border-collapse:collapse;width:100%}\
+td{padding:10px 6px;border-bottom:1px solid #eee;word-break:break-all}\
+td.size,td.time{white-space:nowrap;color:#666;text-align:right}\
+a{text-decoration:none;color:#0366d6}\
+button{padding:6px 12px}input[type=checkbox]{width:20px;height:20px}";
+
+/// 对 URL 中的相对路径逐段编码
+pub fn encode_path(path: &str) -> String {
+    path.split('/')
+        .map(|segment| utf8_percent_encode(segment, SEGMENT).to_string())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// 生成目录页面，path 为相对分享根的路径，链接均为相对地址
+pub fn render(path: &str, entries: &[Entry]) -> String {
+    let title = format!("/{}", path.trim_matches('/'));
+    let mut html = String::new();
+    let _ = write!(
+        html,
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\">\
+<meta name=\"viewport\" content=\"width=device-width,initial-scale=1\">\
+<title>{title}</title><style>{STYLE}</style></head><body><h3>{title}</h3>\
+<form method=\"post\" action=\"./\"><p><a href=\"./?zip\">Download all (.zip)</a> \
+<button type=\"submit\">Download selected</button></p><table>",
+        title = escape(&title),
+    );
+    if !path.trim_matches('/').is_empty() {
+        html.push_str("<tr><td></td><td><a href=\"../\">../</a></td><td></td><td></td></tr>");
+    }
+    for entry in entries {
+        let (href, name, size) = if entry.is_dir {
+            (
+                format!("{}/", encode_path(&entry.name)),
+                format!("{}/", entry.name),
+                "-".to_string(),
+            )
+        } else {
+            (encode_path(&entry.name), entry.name.clone(), format_size(entry.size))
+        };
+        let time = entry
+            .modified
+            .map(|t| DateTime::<Local>::from(t).format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_default();
+        let _ = write!(
+            html,
+            "<tr><td><input type=\"checkbox\" name=\"name\" value=\"{}\"></td>\
+<td><a href=\"./{}\">{}</a></td><td class=\"size\">{}</td><td class=\"time\">{}</td></tr>",
+            escape(&entry.name),
+            escape(&href),
+            escape(&name),
+            size,
+            time
+        );
+    }
+    html.push_str("</table></form></body></html>");
+    html
+}
+
+/// 解析目录页面提交的表单，返回勾选的条目名称
+pub fn selection(body: &[u8]) -> Vec<String> {
+    let mut names: Vec<String> = form_urlencoded::parse(body)
+        .filter(|(key, _)| key == "name")
+        .map(|(_, value)| value.into_owned())
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn format_size(size: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut value = size as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", size)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let entries = vec![
+            Entry {
+                name: "a b".to_string(),
+                is_dir: true,
+                size: 0,
+                modified: None,
+            },
+            Entry {
+                name: "<x>&#?.txt".to_string(),
+                is_dir: false,
+                size: 1536,
+                modified: None,
+            },
+        ];
+        let html = render("docs/", &entries);
+        assert!(html.contains("<title>/docs</title>"));
+        assert!(html.contains("href=\"../\""));
+        assert!(html.contains("href=\"./a%20b/\">a b/</a>"));
+        assert!(html.contains("href=\"./%3Cx%3E%26%23%3F.txt\">&lt;x&gt;&amp;#?.txt</a>"));
+        assert!(html.contains("1.5 KB"));
+        assert!(!render("", &entries).contains("../"));
+        assert!(html.contains("name=\"name\" value=\"&lt;x&gt;&amp;#?.txt\""));
+        assert_eq!(encode_path("a b/c%d"), "a%20b/c%25d");
+        assert_eq!(selection(b"name=b+c&name=a%2F1&other=x&name=a%2F1"), ["a/1", "b c"]);
+    }
+}
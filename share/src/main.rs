@@ -1,29 +1,145 @@
+mod archive;
+mod index;
+mod multipart;
+mod policy;
+mod range;
+mod receive;
+mod tls;
+mod tree;
+mod tus;
+
+use archive::Archive;
 use axum::{
-    body::Body,
-    http::{header, HeaderMap, StatusCode},
-    response::{IntoResponse, Response},
-    routing::get,
     Router,
+    body::{Body, Bytes},
+    extract::{ConnectInfo, Path, RawQuery, State, connect_info::Connected},
+    http::{HeaderMap, HeaderValue, Method, StatusCode, header},
+    middleware,
+    response::{Html, IntoResponse, Redirect, Response},
+    routing::get,
+    serve::IncomingStream,
 };
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser, Subcommand};
+use futures_util::{StreamExt, future, stream};
+use policy::{Download, Policy, PolicyArgs};
 use qrcode::{EcLevel, QrCode};
-use std::env;
-use std::fs::File;
-use std::io::{Cursor, SeekFrom};
-use std::net::{TcpListener as StdTcpListener, UdpSocket};
-use std::path::PathBuf;
+use range::{Ranges, Validators};
+use receive::ReceiveArgs;
+use serde_json::{Value, json};
+use std::io::{self, Cursor, SeekFrom};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener as StdTcpListener, UdpSocket};
+use std::ops::Range;
+use std::path::{Path as FsPath, PathBuf};
 use std::sync::Arc;
-use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tls::TlsListener;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, Take};
 use tokio::net::TcpListener;
 use tokio_util::io::ReaderStream;
+use tree::Tree;
 use uuid::Uuid;
 
+#[derive(Parser)]
+#[command(
+    version,
+    about = "Share files and directories over HTTP with a QR code",
+    override_usage = "share [OPTIONS] <PATHS>...\n       share [--tls] receive [OPTIONS] <DIR>",
+    subcommand_negates_reqs = true
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Files or directories to share
+    #[arg(required = true)]
+    paths: Vec<PathBuf>,
+
+    #[command(flatten)]
+    policy: PolicyArgs,
+
+    /// Serve over HTTPS with a self-signed certificate generated at startup
+    #[arg(long, global = true)]
+    tls: bool,
+}
+
+impl Cli {
+    // 全局的 --tls 可以写在 receive 前后，分享模式的限制选项写在 receive 之前时报错而不是被忽略
+    fn parse_from_args<I, T>(args: I) -> Result<Self, clap::Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<std::ffi::OsString> + Clone,
+    {
+        let cli = Cli::try_parse_from(args)?;
+        if cli.command.is_some() && cli.policy.restricted() {
+            return Err(Cli::command().error(
+                ErrorKind::ArgumentConflict,
+                "--password, --expires, --ttl and --max-downloads only apply when sharing files",
+            ));
+        }
+        Ok(cli)
+    }
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Receive files uploaded from a phone into a directory
+    Receive(ReceiveArgs),
+}
+
 struct AppState {
-    file_path: PathBuf,
+    tree: Tree,
+    file_id: String,
     download_url: String,
 }
 
-async fn qrcode(axum::extract::State(state): axum::extract::State<Arc<AppState>>) -> Result<Response, StatusCode> {
-    let code = QrCode::with_error_correction_level(&state.download_url, EcLevel::H).unwrap();
+/// 连接两端的地址
+#[derive(Clone, Copy)]
+pub struct Peer {
+    pub remote: SocketAddr,
+    pub local: SocketAddr,
+}
+
+impl Peer {
+    /// 请求来自本机：回环地址，或者经由本机某个网卡地址连接时两端地址相同
+    pub fn is_local(&self) -> bool {
+        let remote = self.remote.ip().to_canonical();
+        remote.is_loopback() || remote == self.local.ip().to_canonical()
+    }
+}
+
+impl Connected<IncomingStream<'_, TcpListener>> for Peer {
+    fn connect_info(stream: IncomingStream<'_, TcpListener>) -> Self {
+        let remote = *stream.remote_addr();
+        Peer {
+            remote,
+            local: stream.io().local_addr().unwrap_or(remote),
+        }
+    }
+}
+
+impl Connected<IncomingStream<'_, TlsListener>> for Peer {
+    fn connect_info(stream: IncomingStream<'_, TlsListener>) -> Self {
+        let remote = *stream.remote_addr();
+        Peer {
+            remote,
+            local: stream.io().get_ref().0.local_addr().unwrap_or(remote),
+        }
+    }
+}
+
+async fn qrcode(
+    ConnectInfo(peer): ConnectInfo<Peer>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Response, StatusCode> {
+    qrcode_png(peer, &state.download_url)
+}
+
+/// 生成指向 url 的二维码图片；服务监听所有网卡，二维码中带有分享地址及证书指纹，只回应本机（含经由局域网地址）的请求
+pub fn qrcode_png(peer: Peer, url: &str) -> Result<Response, StatusCode> {
+    if !peer.is_local() {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    let code = QrCode::with_error_correction_level(url, EcLevel::H).unwrap();
     let image = code.render::<image::Luma<u8>>().build();
 
     let mut png_data = Vec::new();
@@ -42,122 +158,322 @@ async fn qrcode(axum::extract::State(state): axum::extract::State<Arc<AppState>>
         .into_response())
 }
 
-async fn download(
-    axum::extract::State(state): axum::extract::State<Arc<AppState>>,
+// 分享地址：只分享一个文件时直接下载，否则跳转到根目录页面
+async fn share_root(
+    State(state): State<Arc<AppState>>,
+    method: Method,
     headers: HeaderMap,
 ) -> Result<Response, StatusCode> {
-    let file_path = &state.file_path;
+    match state.tree.single_file() {
+        Some(file_path) => download(file_path, &method, &headers).await,
+        None => Ok(Redirect::permanent(&format!("/{}/", state.file_id)).into_response()),
+    }
+}
+
+async fn browse_root(State(state): State<Arc<AppState>>, RawQuery(query): RawQuery) -> Result<Response, StatusCode> {
+    if query.as_deref() == Some("zip") {
+        return archive(state, String::new(), None).await;
+    }
+    Ok(Html(index::render("", &state.tree.root_entries())).into_response())
+}
+
+// 目录返回列表页面，带 ?zip 时打包整个目录，文件交给 download 处理
+async fn browse(
+    State(state): State<Arc<AppState>>,
+    Path(path): Path<String>,
+    RawQuery(query): RawQuery,
+    method: Method,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let target = state.tree.resolve(&path).ok_or(StatusCode::NOT_FOUND)?;
+    if !target.is_dir() {
+        return download(&target, &method, &headers).await;
+    }
+    if query.as_deref() == Some("zip") {
+        return archive(state, path, None).await;
+    }
+    // 页面中的链接都是相对地址，目录地址必须以 / 结尾
+    if !path.ends_with('/') {
+        let location = format!("/{}/{}/", state.file_id, index::encode_path(&path));
+        return Ok(Redirect::permanent(&location).into_response());
+    }
+    let entries = tree::list(&target).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Html(index::render(&path, &entries)).into_response())
+}
+
+async fn select_root(State(state): State<Arc<AppState>>, body: Bytes) -> Result<Response, StatusCode> {
+    archive(state, String::new(), Some(index::selection(&body))).await
+}
+
+// 目录页面提交的表单，打包其中勾选的条目
+async fn select(
+    State(state): State<Arc<AppState>>,
+    Path(path): Path<String>,
+    body: Bytes,
+) -> Result<Response, StatusCode> {
+    archive(state, path, Some(index::selection(&body))).await
+}
+
+// 打包目录或其中选中的条目，dir 为空表示分享根
+async fn archive(state: Arc<AppState>, dir: String, selection: Option<Vec<String>>) -> Result<Response, StatusCode> {
+    let dir = dir.trim_matches('/').to_string();
+    let name = dir.rsplit('/').next().unwrap_or_default().to_string();
+    let file_name = match name.as_str() {
+        "" => "share.zip".to_string(),
+        name => format!("{}.zip", name),
+    };
+    // 遍历目录属于阻塞操作
+    let archive = tokio::task::spawn_blocking(move || {
+        let tree = &state.tree;
+        // (相对分享根的路径, 归档内的名称)
+        let items: Vec<(String, String)> = match selection {
+            Some(names) => names
+                .into_iter()
+                .map(|name| (format!("{}/{}", dir, name), name))
+                .collect(),
+            None if dir.is_empty() => tree
+                .root_entries()
+                .into_iter()
+                .map(|entry| (entry.name.clone(), entry.name))
+                .collect(),
+            None => vec![(dir.clone(), name)],
+        };
+        if items.is_empty() {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        let mut archive = Archive::new();
+        for (path, name) in items {
+            let target = tree.resolve(&path).ok_or(StatusCode::NOT_FOUND)?;
+            archive
+                .add(tree, &target, name.trim_matches('/'))
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        }
+        Ok(archive)
+    })
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)??;
+
+    let content_length = archive.content_length();
+    let mut response = (
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, "application/zip".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", file_name),
+            ),
+        ],
+        archive.into_body(),
+    )
+        .into_response();
+    if let Some(len) = content_length {
+        response.headers_mut().insert(header::CONTENT_LENGTH, len.into());
+    }
+    response.extensions_mut().insert(Download);
+    Ok(response)
+}
+
+// 下载文件，按 RFC 9110 处理条件请求和范围请求，HEAD 请求只返回头部
+async fn download(file_path: &FsPath, method: &Method, headers: &HeaderMap) -> Result<Response, StatusCode> {
     let file_name = file_path
         .file_name()
         .and_then(|name| name.to_str())
         .unwrap_or("download");
-    let file_read = File::open(file_path).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    let file_size = file_read
-        .metadata()
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-        .len();
+    let metadata = tokio::fs::metadata(file_path)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let file_size = metadata.len();
+    let modified = metadata.modified().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let validators = Validators::new(file_size, modified);
     let content_type = mime_guess::from_path(file_path).first_or_octet_stream().to_string();
+    let head = method == Method::HEAD;
 
-    let (start, end) = if let Some(range_header) = headers.get(header::RANGE) {
-        let range_str = range_header.to_str().map_err(|_| StatusCode::BAD_REQUEST)?;
-        if !range_str.starts_with("bytes=") {
-            (0, file_size - 1)
-        } else {
-            let range_spec = &range_str[6..];
-            if let Some(dash_pos) = range_spec.find('-') {
-                let start_str = &range_spec[..dash_pos];
-                let end_str = &range_spec[dash_pos + 1..];
-
-                let start = if start_str.is_empty() {
-                    if let Ok(suffix_len) = end_str.parse::<u64>() {
-                        file_size.saturating_sub(suffix_len)
-                    } else {
-                        0
-                    }
-                } else {
-                    start_str.parse::<u64>().unwrap_or(0).min(file_size - 1)
-                };
-
-                let end = if end_str.is_empty() {
-                    file_size - 1
+    // 范围请求只对 GET 有意义，If-Range 不匹配时返回完整内容
+    let ranges = match headers.get(header::RANGE) {
+        Some(value) if !head && validators.if_range(headers) => {
+            range::parse(value.to_str().unwrap_or_default(), file_size)
+        }
+        _ => Ranges::Full,
+    };
+    // 发送了文件最后一个字节的响应才算完成一次下载
+    let complete = !head
+        && match &ranges {
+            Ranges::Full => true,
+            Ranges::Partial(ranges) => ranges.last().is_some_and(|range| range.end == file_size),
+            Ranges::Unsatisfiable => false,
+        };
+    let mut response = if validators.not_modified(headers) {
+        StatusCode::NOT_MODIFIED.into_response()
+    } else {
+        match ranges {
+            Ranges::Full => {
+                let body = if head {
+                    Body::empty()
                 } else {
-                    end_str.parse::<u64>().unwrap_or(file_size - 1).min(file_size - 1)
+                    read(file_path, 0..file_size).await?
                 };
-
-                (start, end.max(start))
-            } else {
-                (0, file_size - 1)
+                let mut response = (StatusCode::OK, [(header::CONTENT_TYPE, content_type)], body).into_response();
+                response.headers_mut().insert(header::CONTENT_LENGTH, file_size.into());
+                response
+            }
+            Ranges::Partial(ranges) if ranges.len() == 1 => {
+                let range = ranges[0].clone();
+                let content_range = format!("bytes {}-{}/{}", range.start, range.end - 1, file_size);
+                let content_length = (range.end - range.start).to_string();
+                (
+                    StatusCode::PARTIAL_CONTENT,
+                    [
+                        (header::CONTENT_TYPE, content_type),
+                        (header::CONTENT_RANGE, content_range),
+                        (header::CONTENT_LENGTH, content_length),
+                    ],
+                    read(file_path, range).await?,
+                )
+                    .into_response()
             }
+            Ranges::Partial(ranges) => byteranges(file_path, &content_type, file_size, ranges),
+            Ranges::Unsatisfiable => (
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                [(header::CONTENT_RANGE, format!("bytes */{}", file_size))],
+            )
+                .into_response(),
         }
-    } else {
-        (0, file_size - 1)
     };
 
-    let len = end - start + 1;
-    let mut file = tokio::fs::File::open(file_path)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    file.seek(SeekFrom::Start(start))
+    let status = response.status();
+    let response_headers = response.headers_mut();
+    response_headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    for (name, value) in [
+        (header::ETAG, &validators.etag),
+        (header::LAST_MODIFIED, &validators.last_modified),
+    ] {
+        response_headers.insert(name, value.parse().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?);
+    }
+    if status.is_success() {
+        let disposition = format!("attachment; filename=\"{}\"", file_name);
+        response_headers.insert(
+            header::CONTENT_DISPOSITION,
+            disposition.parse().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+        );
+        if complete {
+            response.extensions_mut().insert(Download);
+        }
+    }
+    Ok(response)
+}
+
+// 读取文件的一段作为响应体
+async fn read(file_path: &FsPath, range: Range<u64>) -> Result<Body, StatusCode> {
+    let stream = read_stream(file_path.to_path_buf(), range)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    let stream = ReaderStream::new(file.take(len));
-
-    if start != 0 || end != file_size - 1 {
-        Ok((
-            StatusCode::PARTIAL_CONTENT,
-            [
-                (header::ACCEPT_RANGES, "bytes"),
-                (header::CONTENT_RANGE, &format!("bytes {}-{}/{}", start, end, file_size)),
-                (header::CONTENT_LENGTH, &len.to_string()),
-                (header::CONTENT_TYPE, &content_type),
-                (
-                    header::CONTENT_DISPOSITION,
-                    &format!("attachment; filename=\"{}\"", file_name),
-                ),
-            ],
-            Body::from_stream(stream),
-        )
-            .into_response())
-    } else {
-        Ok((
-            StatusCode::OK,
-            [
-                (header::ACCEPT_RANGES, "bytes"),
-                (header::CONTENT_LENGTH, &len.to_string()),
-                (header::CONTENT_TYPE, &content_type),
-                (
-                    header::CONTENT_DISPOSITION,
-                    &format!("attachment; filename=\"{}\"", file_name),
-                ),
-            ],
-            Body::from_stream(stream),
-        )
-            .into_response())
-    }
+    Ok(Body::from_stream(stream))
+}
+
+async fn read_stream(file_path: PathBuf, range: Range<u64>) -> io::Result<ReaderStream<Take<tokio::fs::File>>> {
+    let mut file = tokio::fs::File::open(file_path).await?;
+    file.seek(SeekFrom::Start(range.start)).await?;
+    Ok(ReaderStream::new(file.take(range.end - range.start)))
+}
+
+// 多个区间以 multipart/byteranges 返回，各部分依次读取文件
+fn byteranges(file_path: &FsPath, content_type: &str, file_size: u64, ranges: Vec<Range<u64>>) -> Response {
+    let boundary = Uuid::new_v4().simple().to_string();
+    let mut content_length = 0;
+    let parts: Vec<(String, Range<u64>)> = ranges
+        .into_iter()
+        .map(|range| {
+            let head = format!(
+                "\r\n--{}\r\nContent-Type: {}\r\nContent-Range: bytes {}-{}/{}\r\n\r\n",
+                boundary,
+                content_type,
+                range.start,
+                range.end - 1,
+                file_size
+            );
+            content_length += head.len() as u64 + range.end - range.start;
+            (head, range)
+        })
+        .collect();
+    let tail = format!("\r\n--{}--\r\n", boundary);
+    content_length += tail.len() as u64;
+
+    let file_path = file_path.to_path_buf();
+    let stream = stream::iter(parts)
+        .then(move |(head, range)| {
+            let head = stream::once(future::ready(Ok(Bytes::from(head))));
+            let file_path = file_path.clone();
+            async move {
+                match read_stream(file_path, range).await {
+                    Ok(body) => head.chain(body).left_stream(),
+                    Err(e) => stream::once(future::ready(Err(e))).right_stream(),
+                }
+            }
+        })
+        .flatten()
+        .chain(stream::once(future::ready(Ok(Bytes::from(tail)))));
+    (
+        StatusCode::PARTIAL_CONTENT,
+        [
+            (
+                header::CONTENT_TYPE,
+                format!("multipart/byteranges; boundary={}", boundary),
+            ),
+            (header::CONTENT_LENGTH, content_length.to_string()),
+        ],
+        Body::from_stream(stream),
+    )
+        .into_response()
+}
+
+/// 在标准输出写一行 JSON，供宿主程序读取
+pub fn announce(event: Value) {
+    println!("{}", event);
+}
+
+// 分享模式的路由，fragment 附加在二维码地址末尾
+fn share_router(
+    paths: &[PathBuf],
+    policy: PolicyArgs,
+    file_id: &str,
+    base_url: &str,
+    fragment: &str,
+) -> Result<Router, String> {
+    let tree = Tree::new(paths)?;
+    let secure = base_url.starts_with("https://");
+    let policy = Arc::new(Policy::new(policy, file_id, secure)?);
+    policy.watch();
+    // 只有一个文件时二维码指向下载地址，否则指向目录页面
+    let download_url = match tree.single_file() {
+        Some(_) => format!("{}/{}{}", base_url, file_id, fragment),
+        None => format!("{}/{}/{}", base_url, file_id, fragment),
+    };
+    // 二维码只回应本机的请求，不受分享限制
+    let router = Router::new()
+        .route(&format!("/{}", file_id), get(share_root))
+        .route(&format!("/{}/", file_id), get(browse_root).post(select_root))
+        .route(&format!("/{}/{{*path}}", file_id), get(browse).post(select))
+        .layer(middleware::from_fn_with_state(policy, policy::guard))
+        .route("/qrcode", get(qrcode))
+        .with_state(Arc::new(AppState {
+            tree,
+            file_id: file_id.to_string(),
+            download_url,
+        }));
+    Ok(router)
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        eprintln!("Usage: {} <file_path>", args[0]);
-        std::process::exit(1);
-    }
-
-    let file_path = PathBuf::from(&args[1]);
-    if !file_path.exists() || !file_path.is_file() {
-        eprintln!("Error: '{}' is not a valid file", args[1]);
-        std::process::exit(1);
-    }
+    let cli = Cli::parse_from_args(std::env::args_os()).unwrap_or_else(|e| e.exit());
 
     // 获取 IP 地址
     let mut local_ip = "127.0.0.1".to_string();
-    if let Ok(socket) = UdpSocket::bind("0.0.0.0:0") {
-        if socket.connect("8.8.8.8:80").is_ok() {
-            if let Ok(addr) = socket.local_addr() {
-                local_ip = addr.ip().to_string();
-            }
-        }
+    if let Ok(socket) = UdpSocket::bind("0.0.0.0:0")
+        && socket.connect("8.8.8.8:80").is_ok()
+        && let Ok(addr) = socket.local_addr()
+    {
+        local_ip = addr.ip().to_string();
     }
 
     // 获取随机端口号
@@ -165,30 +481,59 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let local_port = local_tcp.local_addr()?.port();
     drop(local_tcp);
 
+    // TLS 模式下证书指纹放在二维码地址的片段中，片段不会发给服务器
+    let certificate = if cli.tls {
+        let ip = local_ip.parse().unwrap_or(Ipv4Addr::LOCALHOST.into());
+        Some(tls::generate(ip).unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }))
+    } else {
+        None
+    };
+    let fragment = match &certificate {
+        Some(certificate) => format!("#sha256={}", certificate.fingerprint),
+        None => String::new(),
+    };
+
     let file_id = Uuid::new_v4().to_string();
-    let base_url = format!("http://{}:{}", local_ip, local_port);
-    println!(
-        r#"{{"pid":{},"file_id":"{}","base_url":"{}"}}"#,
-        std::process::id(),
-        file_id,
-        base_url
-    );
-
-    let app = Router::new()
-        .route("/qrcode", get(qrcode))
-        .route(&format!("/{}", file_id), get(download))
-        .with_state(Arc::new(AppState {
-            file_path,
-            download_url: format!("{}/{}", base_url, file_id),
-        }));
+    let scheme = if cli.tls { "https" } else { "http" };
+    let base_url = format!("{}://{}:{}", scheme, local_ip, local_port);
+    let (app, receiver) = match cli.command {
+        Some(Command::Receive(args)) => {
+            receive::router(args, &file_id, &base_url, &fragment).map(|(app, receiver)| (app, Some(receiver)))
+        }
+        None => share_router(&cli.paths, cli.policy, &file_id, &base_url, &fragment).map(|app| (app, None)),
+    }
+    .unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    });
+    let mut event = json!({
+        "pid": std::process::id(),
+        "file_id": file_id,
+        "base_url": base_url,
+    });
+    if let Some(certificate) = &certificate {
+        event["fingerprint"] = json!(certificate.fingerprint);
+    }
+    announce(event);
 
     let listener = TcpListener::bind(format!("0.0.0.0:{}", local_port)).await?;
-    tokio::spawn(async {
+    tokio::spawn(async move {
         tokio::signal::ctrl_c().await.unwrap();
+        // 进程退出后无法再续传，未完成的上传数据一并删除
+        if let Some(receiver) = receiver {
+            receiver.uploads.remove();
+        }
         std::process::exit(0);
     });
 
-    axum::serve(listener, app).await?;
+    let app = app.into_make_service_with_connect_info::<Peer>();
+    match certificate {
+        Some(certificate) => axum::serve(TlsListener::new(listener, certificate.config)?, app).await?,
+        None => axum::serve(listener, app).await?,
+    }
     Ok(())
 }
 
@@ -198,6 +543,207 @@ mod tests {
     use std::fs;
     use std::io::Cursor;
     use std::net::UdpSocket;
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    use super::{Cli, Command, Peer, StatusCode, qrcode_png, share_router};
+    use crate::receive::{self, Receiver};
+
+    // 按命令行参数在本地端口启动分享服务，返回地址
+    async fn serve(args: &[&str]) -> String {
+        let cli = Cli::parse_from_args(std::iter::once("share").chain(args.iter().copied())).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let app = share_router(&cli.paths, cli.policy, "id", &format!("http://{}", addr), "").unwrap();
+        let app = app.into_make_service_with_connect_info::<Peer>();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        addr
+    }
+
+    // 按命令行参数在本地端口启动接收服务，返回地址及共享状态
+    pub(crate) async fn serve_receive(args: &[&str]) -> (String, Arc<Receiver>) {
+        let args = ["share", "receive"].into_iter().chain(args.iter().copied());
+        let Some(Command::Receive(args)) = Cli::parse_from_args(args).unwrap().command else {
+            unreachable!("parsed a receive command");
+        };
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let (app, receiver) = receive::router(args, "id", &format!("http://{}", addr), "").unwrap();
+        let app = app.into_make_service_with_connect_info::<Peer>();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (addr, receiver)
+    }
+
+    pub(crate) type Reply = (u16, Vec<(String, String)>, Vec<u8>);
+
+    // 发送一个请求，返回状态码、小写的头部和响应体
+    async fn request(addr: &str, method: &str, path: &str, headers: &[(&str, &str)]) -> Reply {
+        send(addr, method, path, headers, "").await
+    }
+
+    // 提交登录表单
+    async fn login(addr: &str, password: &str) -> Reply {
+        let body = format!("password={}", password);
+        let headers = [("Content-Type", "application/x-www-form-urlencoded")];
+        send(addr, "POST", "/id?login", &headers, &body).await
+    }
+
+    // 接收模式及断点续传的测试也用这里的函数发送请求
+    pub(crate) async fn send(addr: &str, method: &str, path: &str, headers: &[(&str, &str)], body: &str) -> Reply {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let mut request = format!(
+            "{} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n",
+            method, path, addr
+        );
+        for (name, value) in headers {
+            request += &format!("{}: {}\r\n", name, value);
+        }
+        request += &format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await.unwrap();
+        let end = response.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+        let head = String::from_utf8(response[..end].to_vec()).unwrap();
+        let mut lines = head.split("\r\n");
+        let status = lines.next().unwrap()[9..12].parse().unwrap();
+        let headers = lines
+            .filter_map(|line| line.split_once(": "))
+            .map(|(name, value)| (name.to_ascii_lowercase(), value.to_string()))
+            .collect();
+        (status, headers, response[end + 4..].to_vec())
+    }
+
+    pub(crate) fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+        headers
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    #[tokio::test]
+    async fn test_download_ranges() {
+        let dir = std::env::temp_dir().join(format!("share-range-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let data: Vec<u8> = (0..100u8).collect();
+        fs::write(dir.join("a.bin"), &data).unwrap();
+        fs::write(dir.join("empty"), b"").unwrap();
+        let (file, empty) = (dir.join("a.bin"), dir.join("empty"));
+        let addr = serve(&[file.to_str().unwrap(), empty.to_str().unwrap()]).await;
+
+        let (status, headers, body) = request(&addr, "GET", "/id/a.bin", &[]).await;
+        assert_eq!((status, body), (200, data.clone()));
+        assert_eq!(header(&headers, "accept-ranges"), Some("bytes"));
+        let etag = header(&headers, "etag").unwrap().to_string();
+        let last_modified = header(&headers, "last-modified").unwrap().to_string();
+
+        let (status, headers, body) = request(&addr, "HEAD", "/id/a.bin", &[("Range", "bytes=0-9")]).await;
+        assert_eq!((status, body.len()), (200, 0));
+        assert_eq!(header(&headers, "content-length"), Some("100"));
+
+        let (status, headers, body) = request(&addr, "GET", "/id/a.bin", &[("Range", "bytes=-10")]).await;
+        assert_eq!((status, body), (206, data[90..].to_vec()));
+        assert_eq!(header(&headers, "content-range"), Some("bytes 90-99/100"));
+
+        let (status, headers, body) = request(&addr, "GET", "/id/a.bin", &[("Range", "bytes=0-1, 10-11")]).await;
+        assert_eq!(status, 206);
+        let content_type = header(&headers, "content-type").unwrap();
+        let boundary = content_type.strip_prefix("multipart/byteranges; boundary=").unwrap();
+        let mut expected = Vec::new();
+        for (range, bytes) in [("0-1", &data[0..2]), ("10-11", &data[10..12])] {
+            let head = format!(
+                "\r\n--{}\r\nContent-Type: application/octet-stream\r\nContent-Range: bytes {}/100\r\n\r\n",
+                boundary, range
+            );
+            expected.extend_from_slice(head.as_bytes());
+            expected.extend_from_slice(bytes);
+        }
+        expected.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+        assert_eq!(body, expected);
+        assert_eq!(
+            header(&headers, "content-length"),
+            Some(expected.len().to_string().as_str())
+        );
+
+        let (status, headers, _) = request(&addr, "GET", "/id/a.bin", &[("Range", "bytes=100-")]).await;
+        assert_eq!(status, 416);
+        assert_eq!(header(&headers, "content-range"), Some("bytes */100"));
+
+        let (status, _, body) = request(
+            &addr,
+            "GET",
+            "/id/a.bin",
+            &[("Range", "bytes=50-"), ("If-Range", "\"old\"")],
+        )
+        .await;
+        assert_eq!((status, body.len()), (200, 100));
+        let (status, _, body) = request(
+            &addr,
+            "GET",
+            "/id/a.bin",
+            &[("Range", "bytes=50-"), ("If-Range", &etag)],
+        )
+        .await;
+        assert_eq!((status, body.len()), (206, 50));
+
+        let (status, _, body) = request(&addr, "GET", "/id/a.bin", &[("If-None-Match", &etag)]).await;
+        assert_eq!((status, body.len()), (304, 0));
+        let (status, _, _) = request(&addr, "GET", "/id/a.bin", &[("If-Modified-Since", &last_modified)]).await;
+        assert_eq!(status, 304);
+
+        let (status, headers, body) = request(&addr, "GET", "/id/empty", &[]).await;
+        assert_eq!((status, body.len()), (200, 0));
+        assert_eq!(header(&headers, "content-length"), Some("0"));
+        let (status, headers, _) = request(&addr, "GET", "/id/empty", &[("Range", "bytes=0-")]).await;
+        assert_eq!(status, 416);
+        assert_eq!(header(&headers, "content-range"), Some("bytes */0"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_share_policy() {
+        let dir = std::env::temp_dir().join(format!("share-policy-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("a.txt");
+        fs::write(&file, "hello").unwrap();
+        let file = file.to_str().unwrap();
+        let addr = serve(&["--password", "1234", "--max-downloads", "1", file]).await;
+
+        let (status, _, body) = request(&addr, "GET", "/id", &[]).await;
+        assert_eq!(status, 401);
+        assert!(String::from_utf8(body).unwrap().contains("action=\"?login\""));
+        // 本机不需要密码即可获取二维码
+        let (status, headers, _) = request(&addr, "GET", "/qrcode", &[]).await;
+        assert_eq!((status, header(&headers, "content-type")), (200, Some("image/png")));
+        let (status, headers, _) = login(&addr, "0000").await;
+        assert_eq!(status, 401);
+        assert_eq!(header(&headers, "set-cookie"), None);
+        let (status, headers, _) = login(&addr, "1234").await;
+        assert_eq!(status, 303);
+        assert_eq!(header(&headers, "location"), Some("/id"));
+        let cookie = header(&headers, "set-cookie")
+            .unwrap()
+            .split(';')
+            .next()
+            .unwrap()
+            .to_string();
+
+        // 没有发送最后一个字节的范围请求不计数
+        let (status, _, body) = request(&addr, "GET", "/id", &[("Cookie", &cookie), ("Range", "bytes=0-1")]).await;
+        assert_eq!((status, body), (206, b"he".to_vec()));
+        let (status, _, body) = request(&addr, "GET", "/id", &[("Cookie", &cookie)]).await;
+        assert_eq!((status, body), (200, b"hello".to_vec()));
+        let (status, _, _) = request(&addr, "GET", "/id", &[("Cookie", &cookie)]).await;
+        assert_eq!(status, 410);
+
+        let addr = serve(&["--ttl", "1s", file]).await;
+        assert_eq!(request(&addr, "HEAD", "/id", &[]).await.0, 200);
+        tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+        assert_eq!(request(&addr, "HEAD", "/id", &[]).await.0, 410);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
 
     #[test]
     fn test_local_ip() {
@@ -207,6 +753,17 @@ mod tests {
         println!("local_addr: {}", local_addr);
     }
 
+    #[test]
+    fn test_cli_order() {
+        let cli = Cli::parse_from_args(["share", "--tls", "receive", "dir"]).unwrap();
+        assert!(cli.tls && matches!(cli.command, Some(Command::Receive(_))));
+        assert!(Cli::parse_from_args(["share", "receive", "dir", "--tls"]).unwrap().tls);
+        let cli = Cli::parse_from_args(["share", "--tls", "a.txt", "receive"]).unwrap();
+        assert!(cli.command.is_none() && cli.paths.len() == 2);
+        assert!(Cli::parse_from_args(["share", "--password", "1234", "receive", "dir"]).is_err());
+        assert!(Cli::parse_from_args(["share", "receive", "dir", "--password", "1234"]).is_err());
+    }
+
     #[test]
     fn test_qrcode() {
         let code = QrCode::with_error_correction_level(
@@ -218,12 +775,23 @@ mod tests {
         // 生成图像查看实际尺寸
         let qr_image = code.render::<image::Luma<u8>>().build();
 
-        // 保存测试图片
+        // 在内存中编码，不在源码目录留下文件
         let mut png_data = Vec::new();
         image::DynamicImage::ImageLuma8(qr_image)
             .write_to(&mut Cursor::new(&mut png_data), image::ImageFormat::Png)
             .unwrap();
+        assert!(png_data.starts_with(b"\x89PNG"));
 
-        fs::write("test_qrcode.png", &png_data).unwrap();
+        // 其他设备不能获取带有分享地址的二维码，宿主程序经由局域网地址访问本机时可以
+        let url = "http://192.168.1.55:55940/id";
+        let peer = |remote: &str, local: &str| Peer {
+            remote: remote.parse().unwrap(),
+            local: local.parse().unwrap(),
+        };
+        assert!(qrcode_png(peer("127.0.0.1:50000", "127.0.0.1:55940"), url).is_ok());
+        assert!(qrcode_png(peer("[::ffff:127.0.0.1]:50000", "[::1]:55940"), url).is_ok());
+        assert!(qrcode_png(peer("192.168.1.55:50000", "192.168.1.55:55940"), url).is_ok());
+        let remote = qrcode_png(peer("192.168.1.20:50000", "192.168.1.55:55940"), url);
+        assert_eq!(remote.err(), Some(StatusCode::FORBIDDEN));
     }
 }
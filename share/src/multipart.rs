@@ -0,0 +1,241 @@
+use std::fmt;
+
+use axum::body::Bytes;
+use bytes::BytesMut;
+use futures_util::{Stream, StreamExt};
+use memchr::memmem;
+
+// 各部分头部的长度上限
+const MAX_HEADERS: usize = 16 << 10;
+
+/// 解析 multipart/form-data 请求体时的错误
+#[derive(Debug)]
+pub enum Error {
+    /// 读取请求体失败，通常是客户端断开
+    Body(axum::Error),
+    /// 请求体不符合 multipart 格式
+    Malformed(&'static str),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Body(e) => write!(f, "can not read request body: {}", e),
+            Error::Malformed(reason) => write!(f, "malformed multipart body: {}", reason),
+        }
+    }
+}
+
+/// 一个部分的头部信息
+pub struct Part {
+    pub name: Option<String>,
+    pub filename: Option<String>,
+}
+
+/// 流式解析 multipart/form-data，数据逐块交给调用方，不在内存中保留整个部分
+pub struct Multipart<S> {
+    stream: S,
+    buffer: BytesMut,
+    /// 分隔符 `\r\n--boundary`
+    delimiter: Vec<u8>,
+    state: State,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    /// 尚未读到第一个分隔符
+    Preamble,
+    /// 正在读取一个部分的数据
+    Part,
+    /// 刚读过分隔符
+    Delimiter,
+    Finished,
+}
+
+/// 从 Content-Type 中取出 boundary
+pub fn boundary(content_type: &str) -> Option<String> {
+    let (mime, params) = content_type.split_once(';')?;
+    if !mime.trim().eq_ignore_ascii_case("multipart/form-data") {
+        return None;
+    }
+    params.split(';').find_map(|param| {
+        let (key, value) = param.split_once('=')?;
+        let value = value.trim().trim_matches('"');
+        (key.trim().eq_ignore_ascii_case("boundary") && !value.is_empty()).then(|| value.to_string())
+    })
+}
+
+impl<S: Stream<Item = Result<Bytes, axum::Error>> + Unpin> Multipart<S> {
+    pub fn new(stream: S, boundary: &str) -> Self {
+        // 第一个分隔符前没有换行，补上后所有分隔符的形式相同
+        let mut buffer = BytesMut::new();
+        buffer.extend_from_slice(b"\r\n");
+        Multipart {
+            stream,
+            buffer,
+            delimiter: format!("\r\n--{}", boundary).into_bytes(),
+            state: State::Preamble,
+        }
+    }
+
+    /// 跳过当前部分的剩余数据，读取下一部分的头部；没有更多部分时返回 None
+    pub async fn next_part(&mut self) -> Result<Option<Part>, Error> {
+        match self.state {
+            State::Preamble => loop {
+                // 跳过第一个分隔符之前的前导内容
+                if let Some(pos) = memmem::find(&self.buffer, &self.delimiter) {
+                    let _ = self.buffer.split_to(pos + self.delimiter.len());
+                    break;
+                }
+                let keep = self.delimiter.len() - 1;
+                if self.buffer.len() > keep {
+                    let _ = self.buffer.split_to(self.buffer.len() - keep);
+                }
+                self.fill().await?;
+            },
+            State::Part => while self.chunk().await?.is_some() {},
+            State::Delimiter => {}
+            State::Finished => return Ok(None),
+        }
+        // 分隔符之后是 `--` 表示结束，否则为 `\r\n` 及头部
+        while self.buffer.len() < 2 {
+            self.fill().await?;
+        }
+        if self.buffer.starts_with(b"--") {
+            self.state = State::Finished;
+            return Ok(None);
+        }
+        let end = loop {
+            if let Some(pos) = memmem::find(&self.buffer, b"\r\n\r\n") {
+                break pos;
+            }
+            if self.buffer.len() > MAX_HEADERS {
+                return Err(Error::Malformed("part headers too large"));
+            }
+            self.fill().await?;
+        };
+        let headers = self.buffer.split_to(end + 4);
+        let headers = std::str::from_utf8(&headers).map_err(|_| Error::Malformed("part headers are not UTF-8"))?;
+        let mut part = Part {
+            name: None,
+            filename: None,
+        };
+        for line in headers.split("\r\n") {
+            if let Some((key, value)) = line.split_once(':')
+                && key.trim().eq_ignore_ascii_case("content-disposition")
+            {
+                part.name = disposition_param(value, "name");
+                part.filename = disposition_param(value, "filename");
+            }
+        }
+        self.state = State::Part;
+        Ok(Some(part))
+    }
+
+    /// 读取当前部分的下一块数据，部分结束时返回 None
+    pub async fn chunk(&mut self) -> Result<Option<Bytes>, Error> {
+        if self.state != State::Part {
+            return Ok(None);
+        }
+        loop {
+            if let Some(pos) = memmem::find(&self.buffer, &self.delimiter) {
+                if pos > 0 {
+                    return Ok(Some(self.buffer.split_to(pos).freeze()));
+                }
+                let _ = self.buffer.split_to(self.delimiter.len());
+                self.state = State::Delimiter;
+                return Ok(None);
+            }
+            // 末尾可能是分隔符的前半部分，需要留到下次判断
+            let safe = self.buffer.len().saturating_sub(self.delimiter.len() - 1);
+            if safe > 0 {
+                return Ok(Some(self.buffer.split_to(safe).freeze()));
+            }
+            self.fill().await?;
+        }
+    }
+
+    async fn fill(&mut self) -> Result<(), Error> {
+        match self.stream.next().await {
+            Some(Ok(bytes)) => {
+                self.buffer.extend_from_slice(&bytes);
+                Ok(())
+            }
+            Some(Err(e)) => Err(Error::Body(e)),
+            None => Err(Error::Malformed("unexpected end of body")),
+        }
+    }
+}
+
+// 取出 Content-Disposition 中的参数值，浏览器只对引号和换行做转义
+fn disposition_param(value: &str, key: &str) -> Option<String> {
+    let mut rest = value;
+    while let Some(pos) = rest.find(';') {
+        rest = rest[pos + 1..].trim_start();
+        let Some((name, tail)) = rest.split_once('=') else {
+            break;
+        };
+        if let Some(quoted) = tail.strip_prefix('"') {
+            let end = quoted.find('"').unwrap_or(quoted.len());
+            if name.trim().eq_ignore_ascii_case(key) {
+                return Some(
+                    quoted[..end]
+                        .replace("%22", "\"")
+                        .replace("%0D", "\r")
+                        .replace("%0A", "\n"),
+                );
+            }
+            rest = &quoted[end..];
+        } else if name.trim().eq_ignore_ascii_case(key) {
+            return Some(tail.split(';').next().unwrap_or("").trim().to_string());
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::stream;
+
+    use super::*;
+
+    // 按给定大小切分请求体，覆盖分隔符跨越数据块的情况
+    async fn parse(body: &[u8], chunk: usize) -> Result<Vec<(Option<String>, Option<String>, Vec<u8>)>, Error> {
+        let chunks: Vec<Result<Bytes, axum::Error>> =
+            body.chunks(chunk).map(|c| Ok(Bytes::copy_from_slice(c))).collect();
+        let mut multipart = Multipart::new(stream::iter(chunks), "XyZ");
+        let mut parts = Vec::new();
+        while let Some(part) = multipart.next_part().await? {
+            let mut data = Vec::new();
+            while let Some(chunk) = multipart.chunk().await? {
+                data.extend_from_slice(&chunk);
+            }
+            parts.push((part.name, part.filename, data));
+        }
+        Ok(parts)
+    }
+
+    #[tokio::test]
+    async fn test_multipart() {
+        let body = b"preamble\r\n--XyZ\r\nContent-Disposition: form-data; name=\"note\"\r\n\r\nhi\r\n\
+--XyZ\r\nContent-Disposition: form-data; name=\"file\"; filename=\"a;b %22c%22.txt\"\r\n\
+Content-Type: text/plain\r\n\r\nline\r\n--Xy\r\n--X\r\n--XyZ--\r\n";
+        for chunk in [1, 3, 7, body.len()] {
+            let parts = parse(body, chunk).await.unwrap();
+            assert_eq!(parts.len(), 2);
+            assert_eq!(parts[0], (Some("note".to_string()), None, b"hi".to_vec()));
+            assert_eq!(parts[1].1.as_deref(), Some("a;b \"c\".txt"));
+            assert_eq!(parts[1].2, b"line\r\n--Xy\r\n--X");
+        }
+        assert!(
+            parse(b"--XyZ\r\nContent-Disposition: form-data\r\n\r\ntrunc", 4)
+                .await
+                .is_err()
+        );
+        assert_eq!(
+            boundary("multipart/form-data; boundary=\"----abc\"").as_deref(),
+            Some("----abc")
+        );
+        assert_eq!(boundary("text/plain; boundary=x"), None);
+    }
+}
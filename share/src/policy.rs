@@ -0,0 +1,349 @@
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{HeaderMap, Method, StatusCode, header},
+    middleware::Next,
+    response::{Html, IntoResponse, Redirect, Response},
+};
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use clap::Args;
+use futures_util::{StreamExt, stream};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::announce;
+
+// 输入密码的页面
+const PAGE: &str = include_str!("login.html");
+// 登录后保存在浏览器中的 cookie 名称
+const COOKIE: &str = "share";
+// 密码错误后的等待时间，所有登录请求排队进行，限制猜测 PIN 的速度
+const FAILURE_DELAY: Duration = Duration::from_secs(1);
+
+#[derive(Args)]
+pub struct PolicyArgs {
+    /// Require this password or PIN before anything can be viewed or downloaded
+    #[arg(long, value_name = "PASSWORD")]
+    password: Option<String>,
+
+    /// Stop serving at this time, e.g. "2026-10-20 18:00" or RFC 3339
+    #[arg(long, value_name = "TIME", conflicts_with = "ttl")]
+    expires: Option<Deadline>,
+
+    /// Stop serving after this long, e.g. 90s, 30m, 2h or 7d
+    #[arg(long, value_name = "DURATION")]
+    ttl: Option<Lifetime>,
+
+    /// Stop serving after this many completed downloads
+    #[arg(long, value_name = "COUNT", value_parser = clap::value_parser!(u64).range(1..))]
+    max_downloads: Option<u64>,
+}
+
+impl PolicyArgs {
+    /// 是否指定了任何访问限制
+    pub fn restricted(&self) -> bool {
+        self.password.is_some() || self.expires.is_some() || self.ttl.is_some() || self.max_downloads.is_some()
+    }
+}
+
+/// 本地时间或 RFC 3339 表示的时刻
+#[derive(Clone, Copy)]
+pub struct Deadline(pub DateTime<Local>);
+
+impl FromStr for Deadline {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+            return Ok(Deadline(time.with_timezone(&Local)));
+        }
+        [
+            "%Y-%m-%d %H:%M:%S",
+            "%Y-%m-%d %H:%M",
+            "%Y-%m-%dT%H:%M:%S",
+            "%Y-%m-%dT%H:%M",
+        ]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(s, format).ok())
+        .and_then(|time| Local.from_local_datetime(&time).earliest())
+        .map(Deadline)
+        .ok_or_else(|| format!("invalid time '{}', expected e.g. \"2026-10-20 18:00\"", s))
+    }
+}
+
+/// 带 s、m、h、d 后缀的时长，不带后缀时按秒计算
+#[derive(Clone, Copy)]
+pub struct Lifetime(pub Duration);
+
+impl FromStr for Lifetime {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let lower = s.to_ascii_lowercase();
+        let (number, unit) = match lower.chars().last() {
+            Some('s') => (&lower[..lower.len() - 1], 1),
+            Some('m') => (&lower[..lower.len() - 1], 60),
+            Some('h') => (&lower[..lower.len() - 1], 3600),
+            Some('d') => (&lower[..lower.len() - 1], 86400),
+            _ => (lower.as_str(), 1),
+        };
+        number
+            .trim()
+            .parse::<u64>()
+            .ok()
+            .and_then(|n| n.checked_mul(unit))
+            .filter(|&seconds| seconds > 0)
+            .map(|seconds| Lifetime(Duration::from_secs(seconds)))
+            .ok_or_else(|| format!("invalid duration '{}', expected e.g. 90s, 30m, 2h or 7d", s))
+    }
+}
+
+/// 分享链接的访问限制
+pub struct Policy {
+    password: Option<String>,
+    /// 登录后 cookie 的值，每次启动随机生成
+    token: String,
+    file_id: String,
+    /// 通过 HTTPS 提供服务，cookie 只在加密连接上发送
+    secure: bool,
+    deadline: Option<DateTime<Local>>,
+    max_downloads: Option<u64>,
+    downloads: AtomicU64,
+    /// 已经输出过 ended 事件
+    ended: AtomicBool,
+    login: tokio::sync::Mutex<()>,
+}
+
+/// 下载响应带上这个扩展，响应体完整发送后计为一次下载
+#[derive(Clone)]
+pub struct Download;
+
+impl Policy {
+    pub fn new(args: PolicyArgs, file_id: &str, secure: bool) -> Result<Self, String> {
+        let deadline = match (args.expires, args.ttl) {
+            (Some(Deadline(time)), _) => Some(time),
+            (None, Some(Lifetime(ttl))) => Some(
+                chrono::Duration::from_std(ttl)
+                    .ok()
+                    .and_then(|ttl| Local::now().checked_add_signed(ttl))
+                    .ok_or_else(|| "the --ttl is too long".to_string())?,
+            ),
+            (None, None) => None,
+        };
+        if deadline.is_some_and(|time| time <= Local::now()) {
+            return Err("the expiry time is in the past".to_string());
+        }
+        if args.password.as_deref() == Some("") {
+            return Err("the password must not be empty".to_string());
+        }
+        Ok(Policy {
+            password: args.password,
+            token: Uuid::new_v4().simple().to_string(),
+            file_id: file_id.to_string(),
+            secure,
+            deadline,
+            max_downloads: args.max_downloads,
+            downloads: AtomicU64::new(0),
+            ended: AtomicBool::new(false),
+            login: tokio::sync::Mutex::new(()),
+        })
+    }
+
+    /// 到期时输出 ended 事件，不必等到下一个请求
+    pub fn watch(self: &Arc<Self>) {
+        let Some(deadline) = self.deadline else {
+            return;
+        };
+        let policy = self.clone();
+        tokio::spawn(async move {
+            let wait = (deadline - Local::now()).to_std().unwrap_or_default();
+            tokio::time::sleep(wait).await;
+            policy.end("expired");
+        });
+    }
+
+    // 分享链接已经失效时返回原因
+    fn ended(&self) -> Option<&'static str> {
+        if self.deadline.is_some_and(|time| time <= Local::now()) {
+            return Some("expired");
+        }
+        match self.max_downloads {
+            Some(max) if self.downloads.load(Ordering::SeqCst) >= max => Some("downloads"),
+            _ => None,
+        }
+    }
+
+    // 第一次失效时在标准输出写一行事件
+    fn end(&self, reason: &str) {
+        if !self.ended.swap(true, Ordering::SeqCst) {
+            announce(json!({
+                "event": "ended",
+                "reason": reason,
+                "downloads": self.downloads.load(Ordering::SeqCst),
+            }));
+        }
+    }
+
+    fn completed(&self) {
+        let downloads = self.downloads.fetch_add(1, Ordering::SeqCst) + 1;
+        announce(json!({"event": "downloaded", "downloads": downloads}));
+        if self.max_downloads.is_some_and(|max| downloads >= max) {
+            self.end("downloads");
+        }
+    }
+
+    fn authorized(&self, headers: &HeaderMap) -> bool {
+        if self.password.is_none() {
+            return true;
+        }
+        headers
+            .get_all(header::COOKIE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(';'))
+            .filter_map(|pair| pair.trim().split_once('='))
+            .any(|(name, value)| name == COOKIE && same(value.as_bytes(), self.token.as_bytes()))
+    }
+
+    // 校验表单中的密码，成功时设置 cookie 并跳回原来的地址
+    async fn login(&self, path: &str, body: &[u8]) -> Response {
+        let password = form_urlencoded::parse(body)
+            .find(|(key, _)| key == "password")
+            .map(|(_, value)| value.into_owned())
+            .unwrap_or_default();
+        let _guard = self.login.lock().await;
+        let expected = self.password.as_deref().unwrap_or_default();
+        if !same(password.as_bytes(), expected.as_bytes()) {
+            tokio::time::sleep(FAILURE_DELAY).await;
+            return page(StatusCode::UNAUTHORIZED, "Wrong password, please try again.");
+        }
+        let mut cookie = format!(
+            "{}={}; Path=/{}; HttpOnly; SameSite=Strict",
+            COOKIE, self.token, self.file_id
+        );
+        if self.secure {
+            cookie += "; Secure";
+        }
+        ([(header::SET_COOKIE, cookie)], Redirect::to(path)).into_response()
+    }
+}
+
+// 比较时间与内容无关，避免通过响应时间逐字节猜测
+fn same(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+fn page(status: StatusCode, error: &str) -> Response {
+    (status, Html(PAGE.replace("{{error}}", error))).into_response()
+}
+
+/// 检查分享链接是否失效及是否已经登录，并统计完成的下载
+pub async fn guard(State(policy): State<Arc<Policy>>, request: Request, next: Next) -> Response {
+    if let Some(reason) = policy.ended() {
+        policy.end(reason);
+        return (StatusCode::GONE, "This share link is no longer available").into_response();
+    }
+    if policy.password.is_some() && request.method() == Method::POST && request.uri().query() == Some("login") {
+        let path = request.uri().path().to_string();
+        let Ok(body) = axum::body::to_bytes(request.into_body(), 64 << 10).await else {
+            return StatusCode::PAYLOAD_TOO_LARGE.into_response();
+        };
+        return policy.login(&path, &body).await;
+    }
+    if !policy.authorized(request.headers()) {
+        return page(StatusCode::UNAUTHORIZED, "");
+    }
+
+    let response = next.run(request).await;
+    if response.extensions().get::<Download>().is_none() {
+        return response;
+    }
+    // 响应体全部交出才算完成，客户端中途断开时不计数；带 Content-Length 时
+    // hyper 发送够长度后不再读取响应体，所以按字节数判断
+    let (parts, body) = response.into_parts();
+    let length = parts
+        .headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok()?.parse::<u64>().ok());
+    if length == Some(0) {
+        // 空文件的响应体不会被读取
+        policy.completed();
+        return Response::from_parts(parts, body);
+    }
+    let stream = stream::unfold(
+        (body.into_data_stream(), Some(policy), 0),
+        move |(mut body, mut policy, mut sent)| async move {
+            let item = body.next().await;
+            match &item {
+                Some(Ok(bytes)) => sent += bytes.len() as u64,
+                Some(Err(_)) => policy = None,
+                None => {}
+            }
+            if (item.is_none() || length == Some(sent))
+                && let Some(policy) = policy.take()
+            {
+                policy.completed();
+            }
+            item.map(|item| (item, (body, policy, sent)))
+        },
+    );
+    Response::from_parts(parts, Body::from_stream(stream))
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        args: PolicyArgs,
+    }
+
+    #[tokio::test]
+    async fn test_login_cookie() {
+        for secure in [false, true] {
+            let args = Cli::parse_from(["share", "--password", "1234"]).args;
+            let policy = Policy::new(args, "id", secure).unwrap();
+            let response = policy.login("/id", b"password=1234").await;
+            assert_eq!(response.status(), StatusCode::SEE_OTHER);
+            let cookie = response.headers()[header::SET_COOKIE].to_str().unwrap();
+            assert!(cookie.starts_with(&format!("share={}; Path=/id; HttpOnly", policy.token)));
+            assert_eq!(cookie.ends_with("; Secure"), secure);
+        }
+    }
+
+    #[test]
+    fn test_parse_limits() {
+        assert_eq!(Lifetime::from_str("90").unwrap().0, Duration::from_secs(90));
+        assert_eq!(Lifetime::from_str("30m").unwrap().0, Duration::from_secs(1800));
+        assert_eq!(Lifetime::from_str("2H").unwrap().0, Duration::from_secs(7200));
+        assert_eq!(Lifetime::from_str("7d").unwrap().0, Duration::from_secs(7 * 86400));
+        assert!(Lifetime::from_str("0s").is_err());
+        assert!(Lifetime::from_str("soon").is_err());
+
+        let time = Deadline::from_str("2026-10-20T18:00:00+08:00").unwrap().0;
+        assert_eq!(time.timestamp(), 1_792_490_400);
+        let local = Deadline::from_str("2026-10-20 18:00").unwrap().0;
+        assert_eq!(local.format("%Y-%m-%d %H:%M:%S").to_string(), "2026-10-20 18:00:00");
+        assert!(Deadline::from_str("tomorrow").is_err());
+        // 超出日期范围时报错而不是溢出
+        let args = Cli::parse_from(["share", "--ttl", "100000000000d"]).args;
+        assert_eq!(
+            Policy::new(args, "id", false).err().as_deref(),
+            Some("the --ttl is too long")
+        );
+        assert!(same(b"1234", b"1234"));
+        assert!(!same(b"1234", b"1235"));
+        assert!(!same(b"1234", b"12345"));
+    }
+}
@@ -0,0 +1,200 @@
+use std::ops::Range;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use axum::http::{HeaderMap, header};
+
+// 超过这么多个区间时忽略 Range 头，避免大量小区间拖慢服务
+const MAX_RANGES: usize = 64;
+
+/// Range 头的解析结果
+#[derive(Debug, PartialEq)]
+pub enum Ranges {
+    /// 没有 Range 头、单位不是 bytes 或格式错误，返回完整内容
+    Full,
+    /// 可以满足的区间，已排序并合并了重叠或相邻的部分
+    Partial(Vec<Range<u64>>),
+    /// 没有一个区间可以满足，回应 416
+    Unsatisfiable,
+}
+
+/// 按 RFC 9110 解析 Range 头，size 为文件长度
+pub fn parse(value: &str, size: u64) -> Ranges {
+    let Some((unit, set)) = value.split_once('=') else {
+        return Ranges::Full;
+    };
+    if !unit.trim().eq_ignore_ascii_case("bytes") {
+        return Ranges::Full;
+    }
+    let mut ranges = Vec::new();
+    let mut count = 0;
+    for spec in set.split(',').map(str::trim).filter(|spec| !spec.is_empty()) {
+        count += 1;
+        if count > MAX_RANGES {
+            return Ranges::Full;
+        }
+        let Some((first, last)) = spec.split_once('-') else {
+            return Ranges::Full;
+        };
+        let range = if first.is_empty() {
+            // 后缀区间 -N 表示最后 N 个字节
+            let Some(suffix) = number(last) else {
+                return Ranges::Full;
+            };
+            size.saturating_sub(suffix)..size
+        } else {
+            let Some(first) = number(first) else {
+                return Ranges::Full;
+            };
+            let last = match last {
+                "" => u64::MAX,
+                last => match number(last) {
+                    Some(last) if last >= first => last,
+                    _ => return Ranges::Full,
+                },
+            };
+            first..last.saturating_add(1).min(size)
+        };
+        if !range.is_empty() {
+            ranges.push(range);
+        }
+    }
+    if count == 0 {
+        return Ranges::Full;
+    }
+    if ranges.is_empty() {
+        return Ranges::Unsatisfiable;
+    }
+    ranges.sort_by_key(|range| range.start);
+    let mut merged: Vec<Range<u64>> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    Ranges::Partial(merged)
+}
+
+// 只接受十进制数字，str::parse 还会接受前导的 +
+fn number(text: &str) -> Option<u64> {
+    if text.is_empty() || !text.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    text.parse().ok()
+}
+
+/// 文件的校验信息，用于 ETag、Last-Modified 及条件请求
+pub struct Validators {
+    pub etag: String,
+    pub last_modified: String,
+    // 秒级精度，与 HTTP 日期比较
+    modified: u64,
+}
+
+impl Validators {
+    pub fn new(size: u64, modified: SystemTime) -> Self {
+        let since_epoch = modified.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let modified = UNIX_EPOCH + std::time::Duration::from_secs(since_epoch.as_secs());
+        Validators {
+            etag: format!("\"{:x}-{:x}\"", since_epoch.as_nanos(), size),
+            last_modified: httpdate::fmt_http_date(modified),
+            modified: since_epoch.as_secs(),
+        }
+    }
+
+    /// 按 If-None-Match 或 If-Modified-Since 判断客户端缓存是否仍然有效
+    pub fn not_modified(&self, headers: &HeaderMap) -> bool {
+        if let Some(value) = headers.get(header::IF_NONE_MATCH) {
+            // 弱比较，忽略 W/ 前缀
+            let value = value.to_str().unwrap_or_default();
+            return value
+                .split(',')
+                .map(str::trim)
+                .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == self.etag);
+        }
+        headers
+            .get(header::IF_MODIFIED_SINCE)
+            .and_then(|value| seconds(value.to_str().ok()?))
+            .is_some_and(|since| self.modified <= since)
+    }
+
+    /// 没有 If-Range 或其中的校验信息与当前文件一致时才按 Range 返回部分内容
+    pub fn if_range(&self, headers: &HeaderMap) -> bool {
+        let Some(value) = headers.get(header::IF_RANGE) else {
+            return true;
+        };
+        let value = value.to_str().unwrap_or_default().trim();
+        if value.starts_with('"') || value.starts_with("W/") {
+            // 强比较，弱 ETag 永远不匹配
+            return value == self.etag;
+        }
+        seconds(value) == Some(self.modified)
+    }
+}
+
+// 解析 HTTP 日期，返回 UNIX 时间戳秒数
+fn seconds(value: &str) -> Option<u64> {
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(date.duration_since(UNIX_EPOCH).ok()?.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+
+    // 可以满足的区间，以 (起点, 终点) 表示
+    fn partial(value: &str, size: u64) -> Vec<(u64, u64)> {
+        match parse(value, size) {
+            Ranges::Partial(ranges) => ranges.into_iter().map(|range| (range.start, range.end)).collect(),
+            ranges => panic!("{:?}", ranges),
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(partial("bytes=0-499", 1000), [(0, 500)]);
+        assert_eq!(partial("bytes=500-", 1000), [(500, 1000)]);
+        assert_eq!(partial("bytes=-200", 1000), [(800, 1000)]);
+        assert_eq!(partial("bytes=-2000", 1000), [(0, 1000)]);
+        assert_eq!(partial("bytes=900-2000", 1000), [(900, 1000)]);
+        assert_eq!(
+            partial("Bytes= 500-599 , 0-99,, 50-150, 600-700", 1000),
+            [(0, 151), (500, 701)]
+        );
+        assert_eq!(parse("bytes=1000-", 1000), Ranges::Unsatisfiable);
+        assert_eq!(parse("bytes=-0", 1000), Ranges::Unsatisfiable);
+        assert_eq!(parse("bytes=0-", 0), Ranges::Unsatisfiable);
+        assert_eq!(parse("bytes=-5", 0), Ranges::Unsatisfiable);
+        assert_eq!(parse("bytes=5-1", 1000), Ranges::Full);
+        assert_eq!(parse("bytes=+1-2", 1000), Ranges::Full);
+        assert_eq!(parse("bytes=", 1000), Ranges::Full);
+        assert_eq!(parse("items=0-1", 1000), Ranges::Full);
+        let many = vec!["0-0"; MAX_RANGES + 1].join(",");
+        assert_eq!(parse(&format!("bytes={}", many), 1000), Ranges::Full);
+    }
+
+    #[test]
+    fn test_validators() {
+        let modified = UNIX_EPOCH + std::time::Duration::from_millis(1_700_000_000_250);
+        let validators = Validators::new(10, modified);
+        assert_eq!(validators.last_modified, "Tue, 14 Nov 2023 22:13:20 GMT");
+        let headers = |name, value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(name, HeaderValue::from_str(value).unwrap());
+            headers
+        };
+        let weak = format!("\"x\", W/{}", validators.etag);
+        assert!(validators.not_modified(&headers(header::IF_NONE_MATCH, &weak)));
+        assert!(validators.not_modified(&headers(header::IF_NONE_MATCH, "*")));
+        assert!(!validators.not_modified(&headers(header::IF_NONE_MATCH, "\"x\"")));
+        assert!(validators.not_modified(&headers(header::IF_MODIFIED_SINCE, &validators.last_modified)));
+        assert!(!validators.not_modified(&headers(header::IF_MODIFIED_SINCE, "Tue, 14 Nov 2023 22:13:19 GMT")));
+        assert!(validators.if_range(&HeaderMap::new()));
+        assert!(validators.if_range(&headers(header::IF_RANGE, &validators.etag)));
+        assert!(!validators.if_range(&headers(header::IF_RANGE, &format!("W/{}", validators.etag))));
+        assert!(validators.if_range(&headers(header::IF_RANGE, &validators.last_modified)));
+        assert!(!validators.if_range(&headers(header::IF_RANGE, "Tue, 14 Nov 2023 22:13:21 GMT")));
+    }
+}
@@ -0,0 +1,521 @@
+use std::fs::OpenOptions;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use axum::{
+    Json, Router,
+    body::Body,
+    extract::{ConnectInfo, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{Html, IntoResponse, Redirect, Response},
+    routing::{get, post},
+};
+use clap::Args;
+use futures_util::Stream;
+use serde_json::{Value, json};
+use sysinfo::Disks;
+use tokio::io::AsyncWriteExt;
+
+use crate::Peer;
+use crate::multipart::{self, Multipart};
+use crate::tus::{self, Uploads};
+
+// 上传页面，包含拖放、多文件选择及进度条
+const PAGE: &str = include_str!("receive.html");
+// 文件名的字节数上限，多数文件系统允许 255 字节，留出重名时追加序号的空间
+const MAX_NAME: usize = 200;
+// Windows 的保留设备名，无论扩展名是什么都不能用作文件名
+const RESERVED: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9", "LPT1", "LPT2",
+    "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+// 写入过程中每隔这么多字节重新检查一次剩余空间
+const SPACE_CHECK_INTERVAL: u64 = 64 << 20;
+// 清理过期的断点续传上传的间隔
+const EXPIRE_INTERVAL: Duration = Duration::from_secs(600);
+
+#[derive(Args)]
+pub struct ReceiveArgs {
+    /// Directory to save uploaded files into, created if missing
+    dir: PathBuf,
+
+    /// Largest accepted file, e.g. 500M or 4G
+    #[arg(long, value_name = "SIZE")]
+    max_size: Option<ByteSize>,
+
+    /// Free disk space that uploads must leave behind
+    #[arg(long, value_name = "SIZE", default_value = "256M")]
+    keep_free: ByteSize,
+}
+
+/// 带 K、M、G、T 后缀的字节数，按 1024 进位
+#[derive(Clone, Copy)]
+pub struct ByteSize(pub u64);
+
+impl FromStr for ByteSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let upper = s.to_ascii_uppercase();
+        let digits = upper.trim_end_matches('B').trim_end_matches('I');
+        let (number, shift) = match digits.chars().last() {
+            Some('K') => (&digits[..digits.len() - 1], 10),
+            Some('M') => (&digits[..digits.len() - 1], 20),
+            Some('G') => (&digits[..digits.len() - 1], 30),
+            Some('T') => (&digits[..digits.len() - 1], 40),
+            _ => (digits, 0),
+        };
+        number
+            .trim()
+            .parse::<u64>()
+            .ok()
+            .and_then(|n| n.checked_mul(1 << shift))
+            .map(ByteSize)
+            .ok_or_else(|| format!("invalid size '{}', expected e.g. 512K, 100M or 4G", s))
+    }
+}
+
+/// 接收模式的共享状态
+pub struct Receiver {
+    pub dir: PathBuf,
+    pub max_size: Option<u64>,
+    keep_free: u64,
+    /// 已承诺给进行中的上传、尚未写入磁盘的字节数
+    pub reserved: AtomicU64,
+    pub file_id: String,
+    upload_url: String,
+    pub uploads: Uploads,
+}
+
+/// 上传失败时回应的状态码及原因
+pub type Failure = (StatusCode, String);
+
+/// 接收模式的路由及共享状态，二维码指向上传页面，fragment 附加在二维码地址末尾
+pub fn router(
+    args: ReceiveArgs,
+    file_id: &str,
+    base_url: &str,
+    fragment: &str,
+) -> Result<(Router, Arc<Receiver>), String> {
+    std::fs::create_dir_all(&args.dir).map_err(|e| format!("can not create '{}': {}", args.dir.display(), e))?;
+    let dir = args
+        .dir
+        .canonicalize()
+        .map_err(|e| format!("'{}' is not a valid directory: {}", args.dir.display(), e))?;
+    if !dir.is_dir() {
+        return Err(format!("'{}' is not a directory", dir.display()));
+    }
+    let uploads = Uploads::new(&dir, file_id).map_err(|e| format!("can not write to '{}': {}", dir.display(), e))?;
+    let state = Arc::new(Receiver {
+        dir,
+        max_size: args.max_size.map(|size| size.0),
+        keep_free: args.keep_free.0,
+        reserved: AtomicU64::new(0),
+        file_id: file_id.to_string(),
+        upload_url: format!("{}/{}/{}", base_url, file_id, fragment),
+        uploads,
+    });
+    let expiring = state.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(EXPIRE_INTERVAL);
+        loop {
+            interval.tick().await;
+            release(&expiring, expiring.uploads.expire());
+        }
+    });
+    let app = Router::new()
+        .route("/qrcode", get(qrcode))
+        .route(&format!("/{}", file_id), get(redirect))
+        .route(&format!("/{}/", file_id), get(page).post(upload))
+        .route(&format!("/{}/files/", file_id), post(tus::create).options(tus::options))
+        .route(
+            &format!("/{}/files/{{id}}", file_id),
+            axum::routing::head(tus::head)
+                .patch(tus::patch)
+                .delete(tus::terminate)
+                .options(tus::options),
+        )
+        .with_state(state.clone());
+    Ok((app, state))
+}
+
+async fn qrcode(
+    ConnectInfo(peer): ConnectInfo<Peer>,
+    State(state): State<Arc<Receiver>>,
+) -> Result<Response, StatusCode> {
+    crate::qrcode_png(peer, &state.upload_url)
+}
+
+async fn redirect(State(state): State<Arc<Receiver>>) -> Redirect {
+    Redirect::permanent(&format!("/{}/", state.file_id))
+}
+
+async fn page(State(state): State<Arc<Receiver>>) -> Html<String> {
+    let limit = match state.max_size {
+        Some(size) => size.to_string(),
+        None => "0".to_string(),
+    };
+    Html(PAGE.replace("{{max_size}}", &limit))
+}
+
+// 逐个保存请求中的文件部分，其他表单字段忽略；出错时已保存的文件保留，正在写入的文件删除
+async fn upload(State(state): State<Arc<Receiver>>, headers: HeaderMap, body: Body) -> Response {
+    let Some(boundary) = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(multipart::boundary)
+    else {
+        return (StatusCode::BAD_REQUEST, "expected multipart/form-data").into_response();
+    };
+    // 请求长度已知时先为整个请求预留空间，并发的上传不会都以为剩余空间足够
+    let mut reservation = Reservation {
+        state: &state,
+        remaining: 0,
+    };
+    if let Some(length) = headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok())
+    {
+        if let Err(failure) = reserve(&state, length) {
+            return failure.into_response();
+        }
+        reservation.remaining = length;
+    }
+
+    let mut multipart = Multipart::new(body.into_data_stream(), &boundary);
+    let mut saved = Vec::new();
+    loop {
+        let part = match multipart.next_part().await {
+            Ok(Some(part)) => part,
+            Ok(None) => break,
+            Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+        };
+        let Some(filename) = part.filename else {
+            continue;
+        };
+        match save(&mut reservation, &mut multipart, &filename).await {
+            Ok(file) => saved.push(file),
+            Err(failure) => return failure.into_response(),
+        }
+    }
+    Json(saved).into_response()
+}
+
+async fn save<S>(
+    reservation: &mut Reservation<'_>,
+    multipart: &mut Multipart<S>,
+    filename: &str,
+) -> Result<Value, Failure>
+where
+    S: Stream<Item = Result<axum::body::Bytes, axum::Error>> + Unpin,
+{
+    let state = reservation.state;
+    let (path, file) = create_unique(&state.dir, &sanitize(filename))?;
+    let mut file = tokio::fs::File::from_std(file);
+    let result = async {
+        let mut size = 0;
+        let mut next_check = SPACE_CHECK_INTERVAL;
+        while let Some(chunk) = multipart
+            .chunk()
+            .await
+            .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?
+        {
+            size += chunk.len() as u64;
+            if let Some(max) = state.max_size
+                && size > max
+            {
+                return Err((
+                    StatusCode::PAYLOAD_TOO_LARGE,
+                    format!("{} exceeds the {} byte limit", filename, max),
+                ));
+            }
+            if size >= next_check {
+                check_space(state)?;
+                next_check += SPACE_CHECK_INTERVAL;
+            }
+            file.write_all(&chunk).await.map_err(internal)?;
+            reservation.consume(chunk.len() as u64);
+        }
+        file.flush().await.map_err(internal)?;
+        Ok(size)
+    }
+    .await;
+    match result {
+        Ok(size) => Ok(announce_saved(&path, size)),
+        Err(failure) => {
+            drop(file);
+            let _ = std::fs::remove_file(&path);
+            Err(failure)
+        }
+    }
+}
+
+/// 在标准输出报告保存完成的文件，返回的对象同时作为上传请求的回应
+pub fn announce_saved(path: &Path, size: u64) -> Value {
+    let file = json!({
+        "name": path.file_name().and_then(|name| name.to_str()).unwrap_or_default(),
+        "path": path.to_string_lossy(),
+        "size": size,
+    });
+    let mut event = json!({ "event": "received" });
+    event.as_object_mut().unwrap().extend(file.as_object().unwrap().clone());
+    crate::announce(event);
+    file
+}
+
+// 普通上传请求持有的预留空间，随写入逐步归还，请求结束时归还剩余部分
+struct Reservation<'a> {
+    state: &'a Receiver,
+    remaining: u64,
+}
+
+impl Reservation<'_> {
+    fn consume(&mut self, written: u64) {
+        let written = written.min(self.remaining);
+        self.remaining -= written;
+        release(self.state, written);
+    }
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        release(self.state, self.remaining);
+    }
+}
+
+/// 为即将写入的字节预留空间，剩余空间扣除所有预留的字节后不能低于保留值；空间不足时不预留
+pub fn reserve(state: &Receiver, incoming: u64) -> Result<(), Failure> {
+    let Ok(reserved) = state
+        .reserved
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |reserved| {
+            reserved.checked_add(incoming)
+        })
+    else {
+        return Err((StatusCode::INSUFFICIENT_STORAGE, "not enough free space".to_string()));
+    };
+    let result = enough_space(state, reserved + incoming);
+    if result.is_err() {
+        release(state, incoming);
+    }
+    result
+}
+
+/// 归还已写入磁盘或不再需要的预留空间
+pub fn release(state: &Receiver, bytes: u64) {
+    let _ = state
+        .reserved
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |reserved| {
+            Some(reserved.saturating_sub(bytes))
+        });
+}
+
+/// 写入过程中定期检查，其他程序也可能占用磁盘
+pub fn check_space(state: &Receiver) -> Result<(), Failure> {
+    enough_space(state, state.reserved.load(Ordering::SeqCst))
+}
+
+// 剩余空间扣除预留的字节数后不能低于保留值；无法获取磁盘信息时不做限制
+fn enough_space(state: &Receiver, reserved: u64) -> Result<(), Failure> {
+    match available_space(&state.dir) {
+        Some(available) if available < reserved.saturating_add(state.keep_free) => Err((
+            StatusCode::INSUFFICIENT_STORAGE,
+            format!(
+                "not enough free space, {} bytes available and {} bytes promised to uploads in progress",
+                available, reserved
+            ),
+        )),
+        _ => Ok(()),
+    }
+}
+
+// 取挂载点最长匹配的磁盘
+fn available_space(dir: &Path) -> Option<u64> {
+    let disks = Disks::new_with_refreshed_list();
+    disks
+        .list()
+        .iter()
+        .filter(|disk| dir.starts_with(disk.mount_point()))
+        .max_by_key(|disk| disk.mount_point().as_os_str().len())
+        .map(|disk| disk.available_space())
+}
+
+/// 以不覆盖已有文件的方式创建文件，重名时追加 (1)、(2) 等序号
+pub fn create_unique(dir: &Path, name: &str) -> Result<(PathBuf, std::fs::File), Failure> {
+    let path = Path::new(name);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or(name);
+    let extension = path.extension().and_then(|e| e.to_str());
+    for n in 0..10000 {
+        let candidate = match (n, extension) {
+            (0, _) => name.to_string(),
+            (n, Some(extension)) => format!("{} ({}).{}", stem, n, extension),
+            (n, None) => format!("{} ({})", stem, n),
+        };
+        let path = dir.join(candidate);
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => return Ok((path, file)),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(internal(e)),
+        }
+    }
+    Err((StatusCode::CONFLICT, format!("too many files named {}", name)))
+}
+
+/// 将客户端提供的文件名转为可安全保存的名称：去掉路径、控制字符及 Windows 不允许的字符，
+/// 去掉首尾的点和空白以免产生隐藏文件或 `..`，避开保留设备名并限制长度
+pub fn sanitize(filename: &str) -> String {
+    // 部分浏览器会带上客户端的完整路径
+    let name = filename.rsplit(['/', '\\']).next().unwrap_or_default();
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_control() || "<>:\"|?*".contains(c) {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect();
+    let mut name = name.trim_matches(|c: char| c == '.' || c.is_whitespace()).to_string();
+    let stem = name.split('.').next().unwrap_or_default().trim_end();
+    if RESERVED.iter().any(|reserved| stem.eq_ignore_ascii_case(reserved)) {
+        name.insert(0, '_');
+    }
+    if name.len() > MAX_NAME {
+        let extension = match name.rfind('.') {
+            Some(dot) if name.len() - dot <= 16 => name[dot..].to_string(),
+            _ => String::new(),
+        };
+        let mut end = MAX_NAME - extension.len();
+        while !name.is_char_boundary(end) {
+            end -= 1;
+        }
+        name = format!("{}{}", &name[..end], extension);
+    }
+    if name.is_empty() {
+        name = "upload".to_string();
+    }
+    name
+}
+
+pub fn internal(e: std::io::Error) -> Failure {
+    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{send, serve_receive};
+
+    // 按 multipart/form-data 上传若干文件
+    async fn post_files(addr: &str, files: &[(&str, &str)]) -> (u16, Vec<u8>) {
+        let mut body = String::new();
+        for (name, content) in files {
+            body += &format!(
+                "--XyZ\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{}\"\r\n\r\n{}\r\n",
+                name, content
+            );
+        }
+        body += "--XyZ--\r\n";
+        let headers = [("Content-Type", "multipart/form-data; boundary=XyZ")];
+        let (status, _, body) = send(addr, "POST", "/id/", &headers, &body).await;
+        (status, body)
+    }
+
+    // 目录中的文件名，不含断点续传的临时目录
+    fn files(dir: &Path) -> Vec<String> {
+        let mut names: Vec<_> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .filter(|name| !name.starts_with('.'))
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[tokio::test]
+    async fn test_upload() {
+        let dir = std::env::temp_dir().join(format!("share-upload-{}", std::process::id()));
+        let (addr, receiver) = serve_receive(&[dir.to_str().unwrap(), "--max-size", "1K", "--keep-free", "0"]).await;
+        assert_eq!(send(&addr, "GET", "/qrcode", &[], "").await.0, 200);
+
+        // 回应的对象与标准输出中 received 事件的内容相同
+        let files_in = [("a.txt", "one"), ("a.txt", "two"), ("../<evil>.txt", "three")];
+        let (status, body) = post_files(&addr, &files_in).await;
+        assert_eq!(status, 200);
+        let saved: Value = serde_json::from_slice(&body).unwrap();
+        let names: Vec<_> = saved
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|file| file["name"].clone())
+            .collect();
+        assert_eq!(names, ["a.txt", "a (1).txt", "_evil_.txt"]);
+        assert_eq!(saved[2]["size"], 5);
+        assert_eq!(
+            saved[2]["path"],
+            dir.canonicalize().unwrap().join("_evil_.txt").to_str().unwrap()
+        );
+        assert_eq!(std::fs::read_to_string(dir.join("a (1).txt")).unwrap(), "two");
+
+        // 超过 --max-size 时回应 413，写了一半的文件被删除
+        let (status, _) = post_files(&addr, &[("big.bin", &"x".repeat(4096))]).await;
+        assert_eq!(status, 413);
+        assert_eq!(files(&dir), ["_evil_.txt", "a (1).txt", "a.txt"]);
+        assert_eq!(receiver.reserved.load(Ordering::SeqCst), 0);
+
+        // 已预留的空间计入后续的检查，两个各占 5/8 剩余空间的上传只能接受一个
+        if let Some(available) = available_space(&receiver.dir) {
+            let share = available / 8 * 5;
+            assert!(reserve(&receiver, share).is_ok());
+            let failure = reserve(&receiver, share).unwrap_err();
+            assert_eq!(failure.0, StatusCode::INSUFFICIENT_STORAGE);
+            assert_eq!(receiver.reserved.load(Ordering::SeqCst), share);
+            release(&receiver, share);
+            assert!(reserve(&receiver, share).is_ok());
+            release(&receiver, share);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_sanitize() {
+        assert_eq!(sanitize("photo.jpg"), "photo.jpg");
+        assert_eq!(sanitize("C:\\Users\\me\\a.txt"), "a.txt");
+        assert_eq!(sanitize("../../etc/passwd"), "passwd");
+        assert_eq!(sanitize(".."), "upload");
+        assert_eq!(sanitize(" .bashrc "), "bashrc");
+        assert_eq!(sanitize("a<b>:c?\u{7}.txt"), "a_b__c__.txt");
+        assert_eq!(sanitize("con.txt"), "_con.txt");
+        assert_eq!(sanitize("照片 2024.heic"), "照片 2024.heic");
+        let long = sanitize(&format!("{}.mp4", "视".repeat(100)));
+        assert!(long.len() <= MAX_NAME && long.ends_with("视.mp4"));
+    }
+
+    #[test]
+    fn test_create_unique() {
+        let dir = std::env::temp_dir().join(format!("share-receive-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let names: Vec<_> = (0..3)
+            .map(|_| create_unique(&dir, "a.tar.gz").unwrap().0)
+            .chain((0..2).map(|_| create_unique(&dir, "README").unwrap().0))
+            .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(
+            names,
+            ["a.tar.gz", "a.tar (1).gz", "a.tar (2).gz", "README", "README (1)"]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(ByteSize::from_str("4G").unwrap().0, 4 << 30);
+        assert_eq!(ByteSize::from_str("512kb").unwrap().0, 512 << 10);
+        assert_eq!(ByteSize::from_str("100MiB").unwrap().0, 100 << 20);
+        assert_eq!(ByteSize::from_str("42").unwrap().0, 42);
+        assert!(ByteSize::from_str("lots").is_err());
+    }
+}
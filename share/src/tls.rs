@@ -0,0 +1,343 @@
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::serve::Listener;
+use chrono::{DateTime, Utc};
+use data_encoding::HEXLOWER;
+use ring::digest::{SHA256, digest};
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::signature::{ECDSA_P256_SHA256_ASN1_SIGNING, EcdsaKeyPair, KeyPair};
+use rustls::ServerConfig;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::server::TlsStream;
+
+// 证书的有效期，开始时间提前一些以容忍设备间的时钟误差
+const VALID_BEFORE: Duration = Duration::from_secs(3600);
+const VALID_FOR: Duration = Duration::from_secs(7 * 86400);
+// 握手超过这个时间仍未完成的连接直接断开
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// DER 编码用到的对象标识符，已包含标签和长度
+const OID_ECDSA_SHA256: &[u8] = &[0x06, 0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02];
+const OID_EC_PUBLIC_KEY: &[u8] = &[0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+const OID_P256: &[u8] = &[0x06, 0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+const OID_COMMON_NAME: &[u8] = &[0x06, 0x03, 0x55, 0x04, 0x03];
+const OID_SUBJECT_ALT_NAME: &[u8] = &[0x06, 0x03, 0x55, 0x1d, 0x11];
+const OID_KEY_USAGE: &[u8] = &[0x06, 0x03, 0x55, 0x1d, 0x0f];
+const OID_EXT_KEY_USAGE: &[u8] = &[0x06, 0x03, 0x55, 0x1d, 0x25];
+const OID_SERVER_AUTH: &[u8] = &[0x06, 0x08, 0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x03, 0x01];
+
+/// 启动时生成的自签名证书及对应的 rustls 配置
+pub struct Certificate {
+    pub config: Arc<ServerConfig>,
+    /// 证书 DER 编码的 SHA-256，小写十六进制，供客户端固定证书
+    pub fingerprint: String,
+}
+
+/// 为本机地址生成 ECDSA P-256 自签名证书，私钥只保存在内存中
+pub fn generate(ip: IpAddr) -> Result<Certificate, String> {
+    let rng = SystemRandom::new();
+    let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng)
+        .map_err(|_| "can not generate a private key".to_string())?;
+    let key = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref(), &rng)
+        .map_err(|_| "can not load the private key".to_string())?;
+    let der = self_signed(&key, &rng, ip, Utc::now())?;
+
+    let fingerprint = HEXLOWER.encode(digest(&SHA256, &der).as_ref());
+    let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(pkcs8.as_ref().to_vec()));
+    let mut config = ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .and_then(|builder| {
+            builder
+                .with_no_client_auth()
+                .with_single_cert(vec![CertificateDer::from(der)], key)
+        })
+        .map_err(|e| format!("invalid certificate: {}", e))?;
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    Ok(Certificate {
+        config: Arc::new(config),
+        fingerprint,
+    })
+}
+
+// 自签名证书的 DER 编码，有效期从 now 前 VALID_BEFORE 开始
+fn self_signed(key: &EcdsaKeyPair, rng: &SystemRandom, ip: IpAddr, now: DateTime<Utc>) -> Result<Vec<u8>, String> {
+    let mut serial = [0u8; 16];
+    rng.fill(&mut serial)
+        .map_err(|_| "can not generate a serial number".to_string())?;
+    let tbs = tbs_certificate(serial, key, ip, now - VALID_BEFORE, now + VALID_FOR);
+    let signature = key
+        .sign(rng, &tbs)
+        .map_err(|_| "can not sign the certificate".to_string())?;
+    Ok(sequence(&[
+        tbs,
+        sequence(&[OID_ECDSA_SHA256.to_vec()]),
+        bit_string(signature.as_ref()),
+    ]))
+}
+
+// 待签名的证书主体，颁发者和主体相同，附带 IP 地址和 localhost 的 subjectAltName，
+// 密钥只用于数字签名及 TLS 服务端认证
+fn tbs_certificate(
+    mut serial: [u8; 16],
+    key: &EcdsaKeyPair,
+    ip: IpAddr,
+    not_before: DateTime<Utc>,
+    not_after: DateTime<Utc>,
+) -> Vec<u8> {
+    // 序列号必须为正数且不能为零
+    serial[0] = serial[0] & 0x7f | 0x01;
+    let name = sequence(&[der(0x31, &sequence(&[OID_COMMON_NAME.to_vec(), der(0x0c, b"share")]))]);
+    let time = |time: DateTime<Utc>| der(0x17, time.format("%y%m%d%H%M%SZ").to_string().as_bytes());
+    let ip = match ip {
+        IpAddr::V4(ip) => ip.octets().to_vec(),
+        IpAddr::V6(ip) => ip.octets().to_vec(),
+    };
+    let alt_names = sequence(&[der(0x87, &ip), der(0x82, b"localhost")]);
+    // keyUsage 只有 digitalSignature 一位，按惯例标为关键扩展
+    let key_usage = bit_string_unused(7, &[0x80]);
+    let extensions = sequence(&[
+        sequence(&[OID_SUBJECT_ALT_NAME.to_vec(), der(0x04, &alt_names)]),
+        sequence(&[OID_KEY_USAGE.to_vec(), der(0x01, &[0xff]), der(0x04, &key_usage)]),
+        sequence(&[
+            OID_EXT_KEY_USAGE.to_vec(),
+            der(0x04, &sequence(&[OID_SERVER_AUTH.to_vec()])),
+        ]),
+    ]);
+    sequence(&[
+        der(0xa0, &der(0x02, &[2])),
+        der(0x02, &serial),
+        sequence(&[OID_ECDSA_SHA256.to_vec()]),
+        name.clone(),
+        sequence(&[time(not_before), time(not_after)]),
+        name,
+        sequence(&[
+            sequence(&[OID_EC_PUBLIC_KEY.to_vec(), OID_P256.to_vec()]),
+            bit_string(key.public_key().as_ref()),
+        ]),
+        der(0xa3, &extensions),
+    ])
+}
+
+// 按 DER 规则编码一个 TLV
+fn der(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    let len = content.len();
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let bytes = len.to_be_bytes();
+        let skip = bytes.iter().take_while(|&&b| b == 0).count();
+        out.push(0x80 | (bytes.len() - skip) as u8);
+        out.extend_from_slice(&bytes[skip..]);
+    }
+    out.extend_from_slice(content);
+    out
+}
+
+fn sequence(items: &[Vec<u8>]) -> Vec<u8> {
+    der(0x30, &items.concat())
+}
+
+// 没有未用位的 BIT STRING
+fn bit_string(bytes: &[u8]) -> Vec<u8> {
+    bit_string_unused(0, bytes)
+}
+
+// 最后一个字节末尾有 unused 个未用位的 BIT STRING
+fn bit_string_unused(unused: u8, bytes: &[u8]) -> Vec<u8> {
+    der(0x03, &[&[unused], bytes].concat())
+}
+
+/// 在后台完成 TLS 握手的监听器，慢速或失败的握手不会阻塞其他连接
+pub struct TlsListener {
+    incoming: mpsc::Receiver<(TlsStream<TcpStream>, SocketAddr)>,
+    local_addr: SocketAddr,
+}
+
+impl TlsListener {
+    pub fn new(listener: TcpListener, config: Arc<ServerConfig>) -> io::Result<Self> {
+        let local_addr = listener.local_addr()?;
+        let acceptor = TlsAcceptor::from(config);
+        let (sender, incoming) = mpsc::channel(64);
+        tokio::spawn(async move {
+            loop {
+                let (stream, addr) = match listener.accept().await {
+                    Ok(connection) => connection,
+                    Err(_) => {
+                        // 文件描述符耗尽等错误，稍后重试
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                        continue;
+                    }
+                };
+                let acceptor = acceptor.clone();
+                let sender = sender.clone();
+                tokio::spawn(async move {
+                    if let Ok(Ok(stream)) = tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                        let _ = sender.send((stream, addr)).await;
+                    }
+                });
+            }
+        });
+        Ok(TlsListener { incoming, local_addr })
+    }
+}
+
+impl Listener for TlsListener {
+    type Io = TlsStream<TcpStream>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match self.incoming.recv().await {
+            Some(connection) => connection,
+            // 接受连接的任务不会结束，这里只是为了类型完整
+            None => std::future::pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        Ok(self.local_addr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+    use rustls::pki_types::{ServerName, UnixTime};
+    use rustls::{ClientConfig, DigitallySignedStruct, SignatureScheme};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_rustls::TlsConnector;
+
+    use super::*;
+
+    // 只接受指纹一致的证书，与二维码中携带指纹的客户端做法相同
+    #[derive(Debug)]
+    struct Pinned(String);
+
+    impl ServerCertVerifier for Pinned {
+        fn verify_server_cert(
+            &self,
+            end_entity: &CertificateDer<'_>,
+            _: &[CertificateDer<'_>],
+            _: &ServerName<'_>,
+            _: &[u8],
+            _: UnixTime,
+        ) -> Result<ServerCertVerified, rustls::Error> {
+            match HEXLOWER.encode(digest(&SHA256, end_entity).as_ref()) == self.0 {
+                true => Ok(ServerCertVerified::assertion()),
+                false => Err(rustls::Error::General("fingerprint mismatch".to_string())),
+            }
+        }
+
+        fn verify_tls12_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, rustls::Error> {
+            let algorithms = rustls::crypto::ring::default_provider().signature_verification_algorithms;
+            rustls::crypto::verify_tls12_signature(message, cert, dss, &algorithms)
+        }
+
+        fn verify_tls13_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, rustls::Error> {
+            let algorithms = rustls::crypto::ring::default_provider().signature_verification_algorithms;
+            rustls::crypto::verify_tls13_signature(message, cert, dss, &algorithms)
+        }
+
+        fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+            vec![SignatureScheme::ECDSA_NISTP256_SHA256]
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pinned_handshake() {
+        let certificate = generate("127.0.0.1".parse().unwrap()).unwrap();
+        assert_eq!(certificate.fingerprint.len(), 64);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = axum::Router::new().route("/", axum::routing::get(|| async { "pinned" }));
+        let listener = TlsListener::new(listener, certificate.config).unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let connect = |fingerprint: String| async move {
+            let config = ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .unwrap()
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(Pinned(fingerprint)))
+                .with_no_client_auth();
+            let stream = TcpStream::connect(addr).await.unwrap();
+            let name = ServerName::try_from("127.0.0.1").unwrap();
+            TlsConnector::from(Arc::new(config)).connect(name, stream).await
+        };
+        assert!(connect("0".repeat(64)).await.is_err());
+        let mut stream = connect(certificate.fingerprint).await.unwrap();
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.ends_with("pinned"));
+    }
+
+    #[test]
+    fn test_certificate() {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng).unwrap();
+        let key = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref(), &rng).unwrap();
+        let now = Utc::now();
+        let der = CertificateDer::from(self_signed(&key, &rng, "192.168.1.55".parse().unwrap(), now).unwrap());
+        let cert = webpki::EndEntityCert::try_from(&der).unwrap();
+        let anchor = webpki::anchor_from_trusted_cert(&der).unwrap();
+
+        // 以自身为信任锚验证签名、有效期及扩展用途
+        let verify = |time: DateTime<Utc>, usage: webpki::KeyUsage| {
+            let time = UnixTime::since_unix_epoch(Duration::from_secs(time.timestamp() as u64));
+            cert.verify_for_usage(
+                &[webpki::ring::ECDSA_P256_SHA256],
+                std::slice::from_ref(&anchor),
+                &[],
+                time,
+                usage,
+                None,
+                None,
+            )
+            .map(|_| ())
+        };
+        let server = webpki::KeyUsage::server_auth;
+        let hour = chrono::Duration::hours(1);
+        assert!(verify(now, server()).is_ok());
+        assert!(verify(now - VALID_BEFORE + hour, server()).is_ok());
+        assert!(matches!(
+            verify(now - VALID_BEFORE - hour, server()),
+            Err(webpki::Error::CertNotValidYet { .. })
+        ));
+        assert!(matches!(
+            verify(now + VALID_FOR + hour, server()),
+            Err(webpki::Error::CertExpired { .. })
+        ));
+        assert!(matches!(
+            verify(now, webpki::KeyUsage::client_auth()),
+            Err(webpki::Error::RequiredEkuNotFoundContext(_))
+        ));
+
+        // subjectAltName 包含本机地址和 localhost
+        let name = |name: &str| cert.verify_is_valid_for_subject_name(&ServerName::try_from(name).unwrap());
+        assert!(name("192.168.1.55").is_ok());
+        assert!(name("localhost").is_ok());
+        assert!(name("192.168.1.56").is_err());
+        assert!(name("share").is_err());
+    }
+}
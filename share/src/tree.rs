@@ -0,0 +1,160 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+
+/// 分享的顶层条目，URL 中以名称区分
+struct Root {
+    name: String,
+    path: PathBuf,
+}
+
+/// 一次分享的全部文件和目录
+pub struct Tree {
+    roots: Vec<Root>,
+}
+
+/// 目录列表中的一项
+pub struct Entry {
+    pub name: String,
+    pub is_dir: bool,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+}
+
+impl Tree {
+    /// 校验并规范化命令行传入的路径，顶层名称不能重复
+    pub fn new(paths: &[PathBuf]) -> Result<Self, String> {
+        let mut roots = Vec::new();
+        let mut names = HashSet::new();
+        for path in paths {
+            let path = path
+                .canonicalize()
+                .map_err(|e| format!("'{}' is not a valid path: {}", path.display(), e))?;
+            if !path.is_file() && !path.is_dir() {
+                return Err(format!("'{}' is not a file or directory", path.display()));
+            }
+            let name = match path.file_name() {
+                Some(name) => name
+                    .to_str()
+                    .ok_or_else(|| format!("'{}' is not valid UTF-8", path.display()))?,
+                // 文件系统根目录没有名称
+                None => "root",
+            }
+            .to_string();
+            if !names.insert(name.clone()) {
+                return Err(format!("duplicate name '{}'", name));
+            }
+            roots.push(Root { name, path });
+        }
+        Ok(Tree { roots })
+    }
+
+    /// 只分享了一个文件时返回该文件，此时分享地址直接下载
+    pub fn single_file(&self) -> Option<&Path> {
+        match self.roots.as_slice() {
+            [root] if root.path.is_file() => Some(&root.path),
+            _ => None,
+        }
+    }
+
+    /// 分享根页面列出的顶层条目
+    pub fn root_entries(&self) -> Vec<Entry> {
+        let mut entries: Vec<Entry> = self
+            .roots
+            .iter()
+            .filter_map(|root| entry(root.name.clone(), &root.path))
+            .collect();
+        sort(&mut entries);
+        entries
+    }
+
+    /// 判断规范化后的磁盘路径是否位于分享范围内
+    pub fn contains(&self, path: &Path) -> bool {
+        self.roots.iter().any(|root| path.starts_with(&root.path))
+    }
+
+    /// 将 URL 中的相对路径映射到磁盘路径，拒绝 `..` 等特殊路径段及经符号链接逃出分享目录的路径
+    pub fn resolve(&self, path: &str) -> Option<PathBuf> {
+        let mut segments = path.split('/').filter(|s| !s.is_empty());
+        let first = segments.next()?;
+        let root = self.roots.iter().find(|root| root.name == first)?;
+        let mut target = root.path.clone();
+        for segment in segments {
+            let mut components = Path::new(segment).components();
+            match (components.next(), components.next()) {
+                (Some(Component::Normal(_)), None) => target.push(segment),
+                _ => return None,
+            }
+        }
+        let target = target.canonicalize().ok()?;
+        target.starts_with(&root.path).then_some(target)
+    }
+}
+
+/// 列出目录内容，目录在前，名称不是 UTF-8 的条目无法出现在 URL 中，直接跳过
+pub fn list(dir: &Path) -> io::Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    for item in fs::read_dir(dir)? {
+        let item = item?;
+        if let Ok(name) = item.file_name().into_string()
+            && let Some(entry) = entry(name, &item.path())
+        {
+            entries.push(entry);
+        }
+    }
+    sort(&mut entries);
+    Ok(entries)
+}
+
+// 符号链接取目标的信息，失效的链接不列出
+fn entry(name: String, path: &Path) -> Option<Entry> {
+    let metadata = fs::metadata(path).ok()?;
+    Some(Entry {
+        name,
+        is_dir: metadata.is_dir(),
+        size: if metadata.is_dir() { 0 } else { metadata.len() },
+        modified: metadata.modified().ok(),
+    })
+}
+
+fn sort(entries: &mut [Entry]) {
+    entries.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.name.cmp(&b.name)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        let base = std::env::temp_dir().join(format!("share-tree-{}", std::process::id()));
+        let dir = base.join("docs");
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("sub/a.txt"), b"a").unwrap();
+        fs::write(base.join("secret.txt"), b"s").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(base.join("secret.txt"), dir.join("link.txt")).unwrap();
+
+        let tree = Tree::new(std::slice::from_ref(&dir)).unwrap();
+        let dir = dir.canonicalize().unwrap();
+        assert_eq!(tree.resolve("docs"), Some(dir.clone()));
+        assert_eq!(tree.resolve("docs/sub/a.txt"), Some(dir.join("sub/a.txt")));
+        assert_eq!(tree.resolve("docs//sub/"), Some(dir.join("sub")));
+        assert_eq!(tree.resolve("docs/sub/../sub/a.txt"), None);
+        assert_eq!(tree.resolve("docs/../secret.txt"), None);
+        assert_eq!(tree.resolve("docs/missing"), None);
+        assert_eq!(tree.resolve("other/a.txt"), None);
+        assert_eq!(tree.resolve(""), None);
+        #[cfg(unix)]
+        assert_eq!(tree.resolve("docs/link.txt"), None);
+
+        let names: Vec<_> = list(&dir).unwrap().into_iter().map(|e| (e.name, e.is_dir)).collect();
+        assert_eq!(names[0], ("sub".to_string(), true));
+
+        assert!(Tree::new(&[dir.clone(), dir.clone()]).is_err());
+        assert!(Tree::new(&[base.join("missing")]).is_err());
+        fs::remove_dir_all(&base).unwrap();
+    }
+}
@@ -0,0 +1,551 @@
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use axum::{
+    body::Body,
+    extract::{Path as UrlPath, State},
+    http::{HeaderMap, HeaderName, StatusCode, header},
+    response::Response,
+};
+use data_encoding::BASE64;
+use futures_util::StreamExt;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::oneshot;
+use uuid::Uuid;
+
+use crate::receive::{self, Receiver};
+
+const VERSION: &str = "1.0.0";
+const EXTENSIONS: &str = "creation,termination,expiration";
+/// 未完成的上传在最后一次写入之后保留的时长
+pub const EXPIRATION: Duration = Duration::from_secs(24 * 3600);
+// 上传中的数据放在接收目录下的隐藏目录中，每个进程一个子目录
+const PARTS_DIR: &str = ".share-uploads";
+// 写入过程中每隔这么多字节重新检查一次剩余空间
+const SPACE_CHECK_INTERVAL: u64 = 64 << 20;
+
+const TUS_RESUMABLE: HeaderName = HeaderName::from_static("tus-resumable");
+const TUS_VERSION: HeaderName = HeaderName::from_static("tus-version");
+const TUS_EXTENSION: HeaderName = HeaderName::from_static("tus-extension");
+const TUS_MAX_SIZE: HeaderName = HeaderName::from_static("tus-max-size");
+const UPLOAD_LENGTH: HeaderName = HeaderName::from_static("upload-length");
+const UPLOAD_OFFSET: HeaderName = HeaderName::from_static("upload-offset");
+const UPLOAD_METADATA: HeaderName = HeaderName::from_static("upload-metadata");
+const UPLOAD_EXPIRES: HeaderName = HeaderName::from_static("upload-expires");
+
+/// tus 协议的断点续传上传，数据写入磁盘，连接中断后从已写入的位置继续
+pub struct Uploads {
+    dir: PathBuf,
+    uploads: Mutex<HashMap<String, Arc<Upload>>>,
+}
+
+struct Upload {
+    length: u64,
+    /// 客户端提供的 Upload-Metadata，查询时原样返回
+    metadata: Option<String>,
+    filename: String,
+    progress: Mutex<Progress>,
+    /// 同一时间只有一个请求写入
+    writer: tokio::sync::Mutex<()>,
+    /// 通知正在写入的请求退出，断线重连时旧连接可能还未被察觉
+    cancel: Mutex<Option<oneshot::Sender<()>>>,
+}
+
+struct Progress {
+    offset: u64,
+    expires: SystemTime,
+    /// 上传完成后保存的位置
+    saved: Option<PathBuf>,
+}
+
+impl Uploads {
+    /// 在接收目录下建立本进程的数据目录，并清理以前的进程遗留的过期数据
+    pub fn new(receive_dir: &Path, file_id: &str) -> io::Result<Self> {
+        let parts = receive_dir.join(PARTS_DIR);
+        if let Ok(items) = fs::read_dir(&parts) {
+            for item in items.flatten() {
+                if is_stale(&item.path()) {
+                    let _ = fs::remove_dir_all(item.path());
+                }
+            }
+        }
+        let dir = parts.join(file_id);
+        fs::create_dir_all(&dir)?;
+        Ok(Uploads {
+            dir,
+            uploads: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// 删除本进程的数据目录，其他进程也没有数据时一并删除上级的隐藏目录
+    pub fn remove(&self) {
+        let _ = fs::remove_dir_all(&self.dir);
+        if let Some(parts) = self.dir.parent() {
+            // 目录不为空时删除失败，正好保留其他进程的数据
+            let _ = fs::remove_dir(parts);
+        }
+    }
+
+    // 已过期的上传视为不存在，数据由 expire 删除
+    fn get(&self, id: &str) -> Option<Arc<Upload>> {
+        let upload = self.uploads.lock().unwrap().get(id).cloned()?;
+        let alive = upload.progress.lock().unwrap().expires > SystemTime::now();
+        alive.then_some(upload)
+    }
+
+    fn part(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.part", id))
+    }
+
+    /// 删除过期的上传记录及未完成的数据，返回这些上传尚未写入的字节数，由调用方归还预留空间
+    pub fn expire(&self) -> u64 {
+        let now = SystemTime::now();
+        let mut pending = 0;
+        let mut uploads = self.uploads.lock().unwrap();
+        uploads.retain(|id, upload| {
+            let progress = upload.progress.lock().unwrap();
+            if progress.expires > now {
+                return true;
+            }
+            if progress.saved.is_none() {
+                pending += upload.length - progress.offset;
+                let _ = fs::remove_file(self.part(id));
+            }
+            false
+        });
+        pending
+    }
+}
+
+impl Upload {
+    // 尚未写入的字节数，已保存的上传为 0
+    fn pending(&self) -> u64 {
+        let progress = self.progress.lock().unwrap();
+        match progress.saved {
+            Some(_) => 0,
+            None => self.length - progress.offset,
+        }
+    }
+}
+
+// 目录及其中的文件都超过有效期未修改
+fn is_stale(dir: &Path) -> bool {
+    let fresh = |path: &Path| {
+        fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .is_ok_and(|time| time.elapsed().is_ok_and(|age| age < EXPIRATION))
+    };
+    if fresh(dir) {
+        return false;
+    }
+    match fs::read_dir(dir) {
+        Ok(items) => !items.flatten().any(|item| fresh(&item.path())),
+        Err(_) => true,
+    }
+}
+
+pub async fn options(State(state): State<Arc<Receiver>>) -> Response {
+    let mut builder = reply(StatusCode::NO_CONTENT)
+        .header(TUS_VERSION, VERSION)
+        .header(TUS_EXTENSION, EXTENSIONS);
+    if let Some(max) = state.max_size {
+        builder = builder.header(TUS_MAX_SIZE, max);
+    }
+    builder.body(Body::empty()).unwrap()
+}
+
+// creation 扩展：登记上传并返回其地址
+pub async fn create(State(state): State<Arc<Receiver>>, headers: HeaderMap) -> Response {
+    if !supported(&headers) {
+        return unsupported();
+    }
+    let Some(length) = header_u64(&headers, &UPLOAD_LENGTH) else {
+        return error(StatusCode::BAD_REQUEST, "missing or invalid Upload-Length");
+    };
+    if let Some(max) = state.max_size
+        && length > max
+    {
+        return error(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            &format!("exceeds the {} byte limit", max),
+        );
+    }
+    // 预留整个文件的空间，数据写入后逐步归还，放弃或过期时归还剩余部分
+    if let Err((status, message)) = receive::reserve(&state, length) {
+        return error(status, &message);
+    }
+    let metadata = headers
+        .get(&UPLOAD_METADATA)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let filename = metadata
+        .as_deref()
+        .and_then(|metadata| metadata_value(metadata, "filename"))
+        .unwrap_or_default();
+
+    let id = Uuid::new_v4().simple().to_string();
+    if let Err(e) = fs::File::create(state.uploads.part(&id)) {
+        receive::release(&state, length);
+        return error(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string());
+    }
+    let expires = SystemTime::now() + EXPIRATION;
+    let upload = Arc::new(Upload {
+        length,
+        metadata,
+        filename,
+        progress: Mutex::new(Progress {
+            offset: 0,
+            expires,
+            saved: None,
+        }),
+        writer: tokio::sync::Mutex::new(()),
+        cancel: Mutex::new(None),
+    });
+    // 空文件无需 PATCH，创建时即完成
+    if length == 0
+        && let Err((status, message)) = finish(&state, &id, &upload)
+    {
+        return error(status, &message);
+    }
+    state.uploads.uploads.lock().unwrap().insert(id.clone(), upload);
+    reply(StatusCode::CREATED)
+        .header(header::LOCATION, format!("/{}/files/{}", state.file_id, id))
+        .header(UPLOAD_EXPIRES, httpdate::fmt_http_date(expires))
+        .body(Body::empty())
+        .unwrap()
+}
+
+// 查询已写入的字节数，客户端据此续传
+pub async fn head(State(state): State<Arc<Receiver>>, UrlPath(id): UrlPath<String>, headers: HeaderMap) -> Response {
+    if !supported(&headers) {
+        return unsupported();
+    }
+    let Some(upload) = state.uploads.get(&id) else {
+        return error(StatusCode::NOT_FOUND, "");
+    };
+    let progress = upload.progress.lock().unwrap();
+    let mut builder = reply(StatusCode::OK)
+        .header(header::CACHE_CONTROL, "no-store")
+        .header(UPLOAD_OFFSET, progress.offset)
+        .header(UPLOAD_LENGTH, upload.length);
+    if progress.saved.is_none() {
+        builder = builder.header(UPLOAD_EXPIRES, httpdate::fmt_http_date(progress.expires));
+    }
+    if let Some(metadata) = &upload.metadata {
+        builder = builder.header(UPLOAD_METADATA, metadata);
+    }
+    builder.body(Body::empty()).unwrap()
+}
+
+// 从 Upload-Offset 处追加数据，每写入一块就更新偏移，连接中断时已写入的部分保留
+pub async fn patch(
+    State(state): State<Arc<Receiver>>,
+    UrlPath(id): UrlPath<String>,
+    headers: HeaderMap,
+    body: Body,
+) -> Response {
+    if !supported(&headers) {
+        return unsupported();
+    }
+    let content_type = headers.get(header::CONTENT_TYPE).and_then(|value| value.to_str().ok());
+    if content_type != Some("application/offset+octet-stream") {
+        return error(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "expected application/offset+octet-stream",
+        );
+    }
+    let Some(offset) = header_u64(&headers, &UPLOAD_OFFSET) else {
+        return error(StatusCode::BAD_REQUEST, "missing or invalid Upload-Offset");
+    };
+    let Some(upload) = state.uploads.get(&id) else {
+        return error(StatusCode::NOT_FOUND, "");
+    };
+
+    // 让仍占用上传的旧请求退出
+    let (cancel, mut cancelled) = oneshot::channel();
+    if let Some(previous) = upload.cancel.lock().unwrap().replace(cancel) {
+        let _ = previous.send(());
+    }
+    let _writer = upload.writer.lock().await;
+    {
+        let progress = upload.progress.lock().unwrap();
+        if progress.offset != offset {
+            return error(StatusCode::CONFLICT, "Upload-Offset does not match the current offset");
+        }
+        if progress.saved.is_some() {
+            return patched(&progress);
+        }
+    }
+    if header_u64(&headers, &header::CONTENT_LENGTH).is_some_and(|len| offset.saturating_add(len) > upload.length) {
+        return error(StatusCode::PAYLOAD_TOO_LARGE, "data exceeds Upload-Length");
+    }
+
+    let part = state.uploads.part(&id);
+    // 丢弃上次中断时写了一半的数据
+    let file = OpenOptions::new().write(true).open(&part).and_then(|mut file| {
+        file.set_len(offset)?;
+        file.seek(SeekFrom::Start(offset))?;
+        Ok(file)
+    });
+    let mut file = match file {
+        Ok(file) => tokio::fs::File::from_std(file),
+        Err(e) => return error(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()),
+    };
+    let mut stream = body.into_data_stream();
+    let mut written = offset;
+    let mut next_check = offset + SPACE_CHECK_INTERVAL;
+    let mut failure = None;
+    loop {
+        let chunk = tokio::select! {
+            _ = &mut cancelled => break,
+            chunk = stream.next() => chunk,
+        };
+        let chunk = match chunk {
+            Some(Ok(chunk)) => chunk,
+            // 客户端断开，保留已写入的部分
+            Some(Err(_)) | None => break,
+        };
+        if written + chunk.len() as u64 > upload.length {
+            failure = Some((StatusCode::PAYLOAD_TOO_LARGE, "data exceeds Upload-Length".to_string()));
+            break;
+        }
+        if written >= next_check {
+            if let Err(e) = receive::check_space(&state) {
+                failure = Some(e);
+                break;
+            }
+            next_check += SPACE_CHECK_INTERVAL;
+        }
+        if let Err(e) = file.write_all(&chunk).await {
+            failure = Some((StatusCode::INTERNAL_SERVER_ERROR, e.to_string()));
+            break;
+        }
+        written += chunk.len() as u64;
+        receive::release(&state, chunk.len() as u64);
+        let mut progress = upload.progress.lock().unwrap();
+        progress.offset = written;
+        progress.expires = SystemTime::now() + EXPIRATION;
+    }
+    if let Err(e) = file.flush().await {
+        failure.get_or_insert((StatusCode::INTERNAL_SERVER_ERROR, e.to_string()));
+    }
+    drop(file);
+    if let Some((status, message)) = failure {
+        return error(status, &message);
+    }
+    if written == upload.length
+        && let Err((status, message)) = finish(&state, &id, &upload)
+    {
+        return error(status, &message);
+    }
+    patched(&upload.progress.lock().unwrap())
+}
+
+// termination 扩展：放弃上传并删除数据
+pub async fn terminate(
+    State(state): State<Arc<Receiver>>,
+    UrlPath(id): UrlPath<String>,
+    headers: HeaderMap,
+) -> Response {
+    if !supported(&headers) {
+        return unsupported();
+    }
+    let Some(upload) = state.uploads.uploads.lock().unwrap().remove(&id) else {
+        return error(StatusCode::NOT_FOUND, "");
+    };
+    if let Some(cancel) = upload.cancel.lock().unwrap().take() {
+        let _ = cancel.send(());
+    }
+    receive::release(&state, upload.pending());
+    let _ = fs::remove_file(state.uploads.part(&id));
+    reply(StatusCode::NO_CONTENT).body(Body::empty()).unwrap()
+}
+
+// 数据写完后移动到接收目录，与普通上传一样处理文件名
+fn finish(state: &Receiver, id: &str, upload: &Upload) -> Result<(), receive::Failure> {
+    let (path, reserved) = receive::create_unique(&state.dir, &receive::sanitize(&upload.filename))?;
+    drop(reserved);
+    if let Err(e) = fs::rename(state.uploads.part(id), &path) {
+        let _ = fs::remove_file(&path);
+        return Err(receive::internal(e));
+    }
+    upload.progress.lock().unwrap().saved = Some(path.clone());
+    receive::announce_saved(&path, upload.length);
+    Ok(())
+}
+
+fn patched(progress: &Progress) -> Response {
+    reply(StatusCode::NO_CONTENT)
+        .header(UPLOAD_OFFSET, progress.offset)
+        .header(UPLOAD_EXPIRES, httpdate::fmt_http_date(progress.expires))
+        .body(Body::empty())
+        .unwrap()
+}
+
+fn reply(status: StatusCode) -> axum::http::response::Builder {
+    Response::builder().status(status).header(TUS_RESUMABLE, VERSION)
+}
+
+fn error(status: StatusCode, message: &str) -> Response {
+    reply(status).body(Body::from(message.to_string())).unwrap()
+}
+
+// 除 OPTIONS 外的请求都要声明协议版本
+fn supported(headers: &HeaderMap) -> bool {
+    headers.get(&TUS_RESUMABLE).is_some_and(|version| version == VERSION)
+}
+
+fn unsupported() -> Response {
+    reply(StatusCode::PRECONDITION_FAILED)
+        .header(TUS_VERSION, VERSION)
+        .body(Body::empty())
+        .unwrap()
+}
+
+fn header_u64(headers: &HeaderMap, name: &HeaderName) -> Option<u64> {
+    headers.get(name)?.to_str().ok()?.parse().ok()
+}
+
+// Upload-Metadata 由逗号分隔的“键 base64值”组成，值可以省略
+fn metadata_value(metadata: &str, key: &str) -> Option<String> {
+    metadata.split(',').find_map(|pair| {
+        let mut parts = pair.trim().splitn(2, ' ');
+        if parts.next()? != key {
+            return None;
+        }
+        let value = BASE64.decode(parts.next().unwrap_or("").trim().as_bytes()).ok()?;
+        String::from_utf8(value).ok()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpStream;
+
+    use super::*;
+    use crate::tests::{Reply, header, send, serve_receive};
+
+    const TUS: (&str, &str) = ("Tus-Resumable", VERSION);
+
+    // 登记上传，返回状态码及上传地址
+    async fn create_upload(addr: &str, length: &str, filename: &str) -> (u16, String) {
+        let metadata = format!("filename {}", BASE64.encode(filename.as_bytes()));
+        let headers = [TUS, ("Upload-Length", length), ("Upload-Metadata", &metadata)];
+        let (status, headers, _) = send(addr, "POST", "/id/files/", &headers, "").await;
+        (status, header(&headers, "location").unwrap_or_default().to_string())
+    }
+
+    async fn head_upload(addr: &str, location: &str) -> Reply {
+        send(addr, "HEAD", location, &[TUS], "").await
+    }
+
+    async fn patch_upload(addr: &str, location: &str, offset: u64, data: &str) -> Reply {
+        let offset = offset.to_string();
+        let headers = [
+            TUS,
+            ("Content-Type", "application/offset+octet-stream"),
+            ("Upload-Offset", &offset),
+        ];
+        send(addr, "PATCH", location, &headers, data).await
+    }
+
+    fn offset(reply: &Reply) -> Option<&str> {
+        header(&reply.1, "upload-offset")
+    }
+
+    fn part(receiver: &Receiver, location: &str) -> PathBuf {
+        receiver.uploads.part(location.rsplit('/').next().unwrap())
+    }
+
+    fn reserved(receiver: &Receiver) -> u64 {
+        receiver.reserved.load(std::sync::atomic::Ordering::SeqCst)
+    }
+
+    #[tokio::test]
+    async fn test_resumable_upload() {
+        let dir = std::env::temp_dir().join(format!("share-tus-{}", std::process::id()));
+        let (addr, receiver) = serve_receive(&[dir.to_str().unwrap(), "--max-size", "1K", "--keep-free", "0"]).await;
+        assert_eq!(create_upload(&addr, "2048", "big.bin").await.0, 413);
+
+        let (status, location) = create_upload(&addr, "10", "../notes.txt").await;
+        assert_eq!(status, 201);
+        let reply = head_upload(&addr, &location).await;
+        assert_eq!((reply.0, offset(&reply)), (200, Some("0")));
+        assert_eq!(header(&reply.1, "upload-length"), Some("10"));
+        // 创建时预留整个文件的空间，写入多少归还多少
+        assert_eq!(reserved(&receiver), 10);
+
+        // 请求体只发出一部分就断开，已收到的部分保留
+        let mut stream = TcpStream::connect(&addr).await.unwrap();
+        let request = format!(
+            "PATCH {} HTTP/1.1\r\nHost: {}\r\nTus-Resumable: {}\r\nContent-Type: application/offset+octet-stream\r\n\
+             Upload-Offset: 0\r\nContent-Length: 10\r\n\r\nhell",
+            location, addr, VERSION
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        drop(stream);
+        let mut resumed = None;
+        for _ in 0..100 {
+            let reply = head_upload(&addr, &location).await;
+            if offset(&reply) == Some("4") {
+                resumed = Some(reply);
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert!(resumed.is_some(), "the partial body was not kept");
+        assert_eq!(reserved(&receiver), 6);
+
+        assert_eq!(patch_upload(&addr, &location, 0, "hello").await.0, 409);
+        assert_eq!(patch_upload(&addr, &location, 4, "o, tus!").await.0, 413);
+        let reply = patch_upload(&addr, &location, 4, "o, tus").await;
+        assert_eq!((reply.0, offset(&reply)), (204, Some("10")));
+        assert_eq!(fs::read_to_string(dir.join("notes.txt")).unwrap(), "hello, tus");
+        assert!(!part(&receiver, &location).exists());
+        assert_eq!(reserved(&receiver), 0);
+
+        // 放弃上传
+        let (_, location) = create_upload(&addr, "5", "gone.txt").await;
+        assert_eq!(patch_upload(&addr, &location, 0, "ab").await.0, 204);
+        assert_eq!(send(&addr, "DELETE", &location, &[TUS], "").await.0, 204);
+        assert_eq!(head_upload(&addr, &location).await.0, 404);
+        assert!(!part(&receiver, &location).exists());
+        assert_eq!(reserved(&receiver), 0);
+
+        // 过期的上传不再可见，清理时删除数据
+        let (_, location) = create_upload(&addr, "5", "late.txt").await;
+        assert_eq!(patch_upload(&addr, &location, 0, "ab").await.0, 204);
+        for upload in receiver.uploads.uploads.lock().unwrap().values() {
+            upload.progress.lock().unwrap().expires = SystemTime::now() - Duration::from_secs(1);
+        }
+        assert_eq!(head_upload(&addr, &location).await.0, 404);
+        assert!(part(&receiver, &location).exists());
+        assert_eq!(receiver.uploads.expire(), 3);
+        assert!(!part(&receiver, &location).exists());
+
+        // 空文件创建时即保存
+        let (status, location) = create_upload(&addr, "0", "empty.txt").await;
+        assert_eq!(status, 201);
+        assert_eq!(fs::metadata(dir.join("empty.txt")).unwrap().len(), 0);
+        let reply = head_upload(&addr, &location).await;
+        assert_eq!((reply.0, offset(&reply)), (200, Some("0")));
+        assert_eq!(header(&reply.1, "upload-expires"), None);
+
+        receiver.uploads.remove();
+        assert!(!dir.join(PARTS_DIR).exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_metadata() {
+        let metadata = "relativePath,filename 5bCP546LLmpwZw==,filetype aW1hZ2UvanBlZw==";
+        assert_eq!(metadata_value(metadata, "filename").as_deref(), Some("小王.jpg"));
+        assert_eq!(metadata_value(metadata, "filetype").as_deref(), Some("image/jpeg"));
+        assert_eq!(metadata_value(metadata, "relativePath").as_deref(), Some(""));
+        assert_eq!(metadata_value(metadata, "missing"), None);
+    }
+}